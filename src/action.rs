@@ -1,19 +1,39 @@
 pub mod bundle_dynamic_dependencies;
 pub mod bundle_executable;
+pub mod bundle_gconv_modules;
+pub mod bundle_locales;
+pub mod bundle_nss_modules;
+pub mod bundle_script;
 pub mod bundle_shared_object_dependencies;
 pub mod compress_executable;
 pub mod emit;
 pub mod exclude_glob;
 pub mod include_glob;
+pub mod link_check;
 pub mod make_directory;
+pub mod print_tree;
+pub mod scan_dlopen_candidates;
 pub mod test;
+pub mod write_dependency_graph;
+pub mod write_manifest;
+pub mod write_sbom;
 
 pub use bundle_dynamic_dependencies::*;
 pub use bundle_executable::*;
+pub use bundle_gconv_modules::*;
+pub use bundle_locales::*;
+pub use bundle_nss_modules::*;
+pub use bundle_script::*;
 pub use bundle_shared_object_dependencies::*;
 pub use compress_executable::*;
 pub use emit::*;
 pub use exclude_glob::*;
 pub use include_glob::*;
+pub use link_check::*;
 pub use make_directory::*;
+pub use print_tree::*;
+pub use scan_dlopen_candidates::*;
 pub use test::*;
+pub use write_dependency_graph::*;
+pub use write_manifest::*;
+pub use write_sbom::*;
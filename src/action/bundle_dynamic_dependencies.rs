@@ -9,7 +9,7 @@ use std::rc::Rc;
 use crate::base::log::{log_output, CommandLogExt};
 use crate::base::trace::{ChildTraceExt, CommandTraceExt, SyscallHandler};
 use crate::base::{Error, Result};
-use crate::domain::{Bundle, Executable};
+use crate::domain::{Action, Bundle, Executable};
 
 pub fn bundle_dynamic_dependencies<I, S, T>(
     bundle: &mut Bundle,
@@ -42,11 +42,12 @@ where
         write!(child.stdin.take().unwrap(), "{}", content.as_ref())?;
     }
 
+    bundle.set_origin(Action::BundleDynamicDependencies);
     let bundle_ref = Rc::new(RefCell::new(bundle));
 
     let output = child.trace_syscalls(SyscallHandler {
-        open: |pathname, _| open_handler(&bundle_ref, "open", pathname),
-        openat: |_, pathname, _| open_handler(&bundle_ref, "openat", pathname),
+        open: |pathname, _| open_handler(&bundle_ref, exe, "open", pathname),
+        openat: |_, pathname, _| open_handler(&bundle_ref, exe, "openat", pathname),
     })?;
     log_output("<dynamic analysis command>", &output);
 
@@ -57,7 +58,12 @@ where
     Ok(())
 }
 
-fn open_handler(bundle: &Rc<RefCell<&mut Bundle>>, name: &str, pathname: OsString) {
+fn open_handler(
+    bundle: &Rc<RefCell<&mut Bundle>>,
+    exe: &Executable,
+    name: &str,
+    pathname: OsString,
+) {
     let path: PathBuf = pathname.into();
 
     tracing::debug!(
@@ -72,6 +78,6 @@ fn open_handler(bundle: &Rc<RefCell<&mut Bundle>>, name: &str, pathname: OsStrin
             "action: bundle_dynamic_dependencies: found path",
         );
 
-        bundle.borrow_mut().add(path);
+        bundle.borrow_mut().add_required_by(path, exe.path());
     }
 }
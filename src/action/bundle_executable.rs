@@ -1,14 +1,14 @@
 use std::path::Path;
 
 use crate::base::Result;
-use crate::domain::{Bundle, BundlePath, Executable};
+use crate::domain::{Action, Bundle, BundlePath, BundlePathBuf, Executable};
 
 pub fn bundle_executable<S, P>(
     bundle: &mut Bundle,
     exe: &Executable,
     input_path: P,
     install_path: Option<S>,
-) -> Result<()>
+) -> Result<BundlePathBuf>
 where
     S: AsRef<str>,
     P: AsRef<Path>,
@@ -19,7 +19,7 @@ where
         "action: bundle executable",
     );
 
-    match install_path {
+    let bundle_path = match install_path {
         Some(p) => {
             let mut path = p.as_ref().to_owned();
 
@@ -30,10 +30,12 @@ where
                     "action: bundle_executable: completing full path",
                 );
             }
-            bundle.add_file_from(BundlePath::projection(&path), exe.path());
+            BundlePath::projection(&path).to_owned()
         }
-        None => bundle.add_file_from(BundlePath::projection(&input_path), exe.path()),
-    }
+        None => BundlePath::projection(&input_path).to_owned(),
+    };
+    bundle.set_origin(Action::BundleExecutable);
+    bundle.add_executable(&bundle_path, exe);
 
-    Ok(())
+    Ok(bundle_path)
 }
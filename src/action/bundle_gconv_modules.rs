@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::gconv::{GconvModules, GCONV_MODULES};
+use crate::domain::{Action, Bundle, BundlePath, Executable, ResolverBackend, SharedObject};
+
+/// Finds the gconv directory of glibc that `exe` is linked with, which is `gconv` in the directory of
+/// libc.so.6, or in the same directory under `/usr` on distributions like Debian where libc.so.6 is in `/lib`.
+fn find_gconv_dir(exe: &Executable, resolver: &ResolverBackend) -> Result<Option<PathBuf>> {
+    let root = exe.root();
+    let libc = exe
+        .dynamic_libraries(resolver)?
+        .into_iter()
+        .find(|path| matches!(path.file_name(), Some(name) if name == "libc.so.6"));
+    let libc_dir = match libc.as_deref().and_then(Path::parent) {
+        Some(dir) => dir.to_owned(),
+        None => return Ok(None),
+    };
+
+    // glibc looks for modules in the directory under /usr, even when libc.so.6 is found through /lib
+    let mut candidates = Vec::new();
+    if !libc_dir.starts_with("/usr") {
+        // unwrap is ok because paths in the root are absolute
+        candidates.push(
+            Path::new("/usr")
+                .join(libc_dir.strip_prefix("/").unwrap())
+                .join("gconv"),
+        );
+    }
+    candidates.push(libc_dir.join("gconv"));
+    Ok(candidates
+        .into_iter()
+        .find(|dir| root.real_host_path(dir.join(GCONV_MODULES)).is_file()))
+}
+
+/// Bundles gconv modules that iconv(3) loads to convert `charsets`, and `gconv-modules` listing only them.
+pub fn bundle_gconv_modules<S>(
+    bundle: &mut Bundle,
+    exes: &[&Executable],
+    resolver: &ResolverBackend,
+    charsets: &[S],
+) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    let charsets: Vec<&str> = charsets.iter().map(AsRef::as_ref).collect();
+    tracing::info!(?charsets, "action: bundle gconv modules");
+
+    bundle.set_origin(Action::BundleGconvModules);
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    // musl does not load modules in iconv(3), and static executables cannot load them
+    for exe in exes
+        .iter()
+        .filter(|exe| exe.interpreter().is_some() && !exe.is_musl())
+    {
+        let dir = match find_gconv_dir(exe, resolver)? {
+            Some(dir) => dir,
+            None => {
+                tracing::warn!(exe = %exe.path().display(), "action: bundle_gconv_modules: gconv directory could not be found");
+                continue;
+            }
+        };
+        if dirs.contains(&dir) {
+            continue;
+        }
+        tracing::debug!(dir = %dir.display(), "action: bundle_gconv_modules: found gconv directory");
+
+        let mut conf = GconvModules::load(exe.root().real_host_path(&dir))?;
+        for charset in &charsets {
+            if conf.is_builtin(charset) {
+                tracing::debug!(%charset, "action: bundle_gconv_modules: charset is built into libc");
+                continue;
+            }
+            let modules = conf.modules_for(charset);
+            if modules.is_empty() {
+                return Err(Error::GconvCharsetNotFound((*charset).to_owned()));
+            }
+            for module in modules {
+                let path = GconvModules::module_path(&dir, module);
+                tracing::info!(%charset, path = %path.display(), "action: bundle_gconv_modules: including module");
+                bundle.add_required_by(&path, exe.path());
+                let module = SharedObject::load_for(&path, exe, resolver)?;
+                bundle.add(module.dependencies(resolver)?);
+            }
+        }
+
+        // without gconv-modules.cache, glibc reads gconv-modules to find modules
+        conf.retain_charsets(&charsets);
+        bundle.add_file(
+            BundlePath::projection(&dir.join(GCONV_MODULES)),
+            conf.render().into_bytes(),
+        );
+        dirs.push(dir);
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::locale_archive::{self, LocaleArchive, LOCALE_DIR};
+use crate::domain::{Action, Bundle, BundlePath, Root};
+
+fn add_dir(bundle: &mut Bundle, dir: &Path, root: &Root) -> Result<()> {
+    for entry in fs::read_dir(root.real_host_path(dir))? {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            add_dir(bundle, &path, root)?;
+        } else {
+            bundle.add(path);
+        }
+    }
+    Ok(())
+}
+
+/// Bundles compiled locales `names`, extracting them from `locale-archive` into locale directories
+/// instead of bundling the whole archive.
+pub fn bundle_locales<S>(bundle: &mut Bundle, names: &[S]) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    let names: Vec<&str> = names.iter().map(AsRef::as_ref).collect();
+    tracing::info!(?names, "action: bundle locales");
+
+    bundle.set_origin(Action::BundleLocales);
+    let root = bundle.root().clone();
+    let archive_path = Path::new(LOCALE_DIR).join("locale-archive");
+    let archive = if root.real_host_path(&archive_path).is_file() {
+        Some(LocaleArchive::load(root.real_host_path(&archive_path))?)
+    } else {
+        tracing::debug!("action: bundle_locales: locale-archive not found");
+        None
+    };
+
+    for name in names {
+        if let Some(locale) = archive
+            .as_ref()
+            .map(|a| a.extract(name))
+            .transpose()?
+            .flatten()
+        {
+            tracing::info!(%name, extracted = %locale.name, "action: bundle_locales: extracting locale from the archive");
+            let dir = Path::new(LOCALE_DIR).join(&locale.name);
+            for (path, content) in locale.files {
+                bundle.add_file(BundlePath::projection(&dir.join(path)), content);
+            }
+            continue;
+        }
+
+        // locales can also be installed as directories, such as C.utf8 in Debian
+        let dir = [name.to_owned(), locale_archive::normalize_locale_name(name)]
+            .into_iter()
+            .map(|name| Path::new(LOCALE_DIR).join(name))
+            .find(|dir| root.real_host_path(dir).is_dir());
+        let dir: PathBuf = dir.ok_or_else(|| Error::LocaleNotFound(name.to_owned()))?;
+        tracing::info!(%name, dir = %dir.display(), "action: bundle_locales: including locale directory");
+        add_dir(bundle, &dir, &root)?;
+    }
+    Ok(())
+}
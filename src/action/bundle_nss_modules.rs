@@ -0,0 +1,198 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::nsswitch::{self, NsswitchConf, NSSWITCH_CONF_PATH};
+use crate::domain::{Action, Bundle, BundlePath, Executable, ResolverBackend, SharedObject};
+
+/// Services that glibc 2.34 and later provides in libc.so.6 itself, without loading modules.
+const BUILTIN_SERVICES: &[&str] = &["files", "dns"];
+
+/// The symbol version defined by libc.so.6 of glibc 2.34 and later.
+const BUILTIN_SERVICES_VERSION: &str = "GLIBC_2.34";
+
+/// Tells whether libc.so.6 loaded into `exe` provides `BUILTIN_SERVICES` itself.
+fn has_builtin_services(exe: &Executable, resolver: &ResolverBackend) -> Result<bool> {
+    let path = exe.resolve("libc.so.6", resolver)?;
+    let libc = SharedObject::load_for(&path, exe, resolver)?;
+    let builtin = libc
+        .object()
+        .symbols()?
+        .defines_version(BUILTIN_SERVICES_VERSION);
+    tracing::debug!(libc = %path.display(), builtin, "action: bundle_nss_modules: checked the version of glibc");
+    Ok(builtin)
+}
+
+/// Bundles NSS modules configured in nsswitch.conf, which glibc loads with dlopen(3) on lookups of
+/// users, hosts and so on. `conf_path` is a path on the host, or `/etc/nsswitch.conf` in the root by default.
+pub fn bundle_nss_modules<P>(
+    bundle: &mut Bundle,
+    exes: &[&Executable],
+    resolver: &ResolverBackend,
+    conf_path: Option<P>,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let conf_host_path = match &conf_path {
+        Some(path) => path.as_ref().to_owned(),
+        None => bundle.root().real_host_path(NSSWITCH_CONF_PATH),
+    };
+    tracing::info!(conf = %conf_host_path.display(), "action: bundle NSS modules");
+
+    // musl does not implement NSS, and static executables cannot load modules
+    let exes: Vec<_> = exes
+        .iter()
+        .filter(|exe| exe.interpreter().is_some() && !exe.is_musl())
+        .collect();
+    if exes.is_empty() {
+        tracing::info!("action: bundle_nss_modules: no executables dynamically linked with glibc");
+        return Ok(());
+    }
+
+    let conf = if conf_path.is_some() || conf_host_path.exists() {
+        Some(NsswitchConf::load(&conf_host_path)?)
+    } else {
+        tracing::info!(
+            "action: bundle_nss_modules: nsswitch.conf not found, using the default of glibc"
+        );
+        None
+    };
+
+    let mut builtin = true;
+    for exe in &exes {
+        match has_builtin_services(exe, resolver) {
+            Ok(true) => {}
+            Ok(false) => builtin = false,
+            Err(e) => {
+                tracing::warn!(exe = %exe.path().display(), error = %e, "action: bundle_nss_modules: unable to determine the version of glibc, assuming it is older than 2.34");
+                builtin = false;
+            }
+        }
+    }
+
+    bundle.set_origin(Action::BundleNssModules);
+    let mut found = HashSet::new();
+    let mut bundled: HashSet<PathBuf> = HashSet::new();
+    let default_conf = NsswitchConf::glibc_default();
+    for service in conf.as_ref().unwrap_or(&default_conf).services() {
+        let name = nsswitch::module_name(service);
+        for exe in &exes {
+            let path = match exe.resolve(&name, resolver) {
+                Ok(path) => path,
+                Err(e) => {
+                    tracing::debug!(%name, error = %e, "action: bundle_nss_modules: unable to resolve module");
+                    continue;
+                }
+            };
+            found.insert(service.to_owned());
+            if !bundled.insert(path.clone()) {
+                continue;
+            }
+
+            tracing::info!(%service, path = %path.display(), "action: bundle_nss_modules: including module");
+            bundle.add_required_by(&path, exe.path());
+            let module = SharedObject::load_for(&path, exe, resolver)?;
+            bundle.add(module.dependencies(resolver)?);
+        }
+
+        if !found.contains(service) {
+            if BUILTIN_SERVICES.contains(&service) {
+                if !builtin {
+                    return Err(Error::NssModuleNotFound(name));
+                }
+                tracing::debug!(%service, "action: bundle_nss_modules: the service is built into libc");
+            } else {
+                tracing::warn!(%name, "action: bundle_nss_modules: NSS module could not be found, removing the service");
+            }
+        }
+    }
+
+    if let Some(mut conf) = conf {
+        conf.retain_services(|service| {
+            found.contains(service) || BUILTIN_SERVICES.contains(&service)
+        });
+        bundle.add_file(
+            BundlePath::projection(&NSSWITCH_CONF_PATH),
+            conf.render().into_bytes(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Hwcaps, Root};
+    use assert_fs::prelude::*;
+    use std::process::Command;
+
+    /// Builds `/app` and the stub libc.so.6 it finds in RPATH, which defines the symbol versions up to `version`.
+    fn build_root(
+        cc: &Path,
+        version: &str,
+    ) -> std::result::Result<assert_fs::TempDir, Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("main.c")
+            .write_str("int main(void) { return 0; }\n")?;
+        dir.child("libc.c")
+            .write_str("int stub(void) { return 0; }\n")?;
+        dir.child("libc.map").write_str(&format!(
+            "GLIBC_2.2.5 {{ global: stub; local: *; }};\n{} {{ }} GLIBC_2.2.5;\n",
+            version
+        ))?;
+        dir.child("lib").create_dir_all()?;
+        let status = Command::new(cc)
+            .current_dir(dir.path())
+            .args([
+                "-shared",
+                "-fPIC",
+                "-nostdlib",
+                "-Wl,-soname,libc.so.6",
+                "-Wl,--version-script,libc.map",
+                "-o",
+                "lib/libc.so.6",
+                "libc.c",
+            ])
+            .status()?;
+        assert!(status.success());
+        let status = Command::new(cc)
+            .current_dir(dir.path())
+            .args(["-o", "app", "main.c", "-Wl,-rpath,/lib"])
+            .status()?;
+        assert!(status.success());
+        dir.child("nsswitch.conf").write_str("hosts: files dns\n")?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_builtin_services() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cc = which::which("cc")?;
+
+        for (version, builtin) in [("GLIBC_2.34", true), ("GLIBC_2.31", false)] {
+            // no NSS modules are installed in the root
+            let dir = build_root(&cc, version)?;
+            let root = Root::new(dir.path())?;
+            let resolver = ResolverBackend::native(&root, Hwcaps::baseline())?;
+            let exe = Executable::load_in("/app", &root, &resolver)?;
+            assert_eq!(has_builtin_services(&exe, &resolver)?, builtin);
+
+            let mut bundle = Bundle::new();
+            bundle.set_root(root.clone());
+            let result = bundle_nss_modules(
+                &mut bundle,
+                &[&exe],
+                &resolver,
+                Some(dir.child("nsswitch.conf").path()),
+            );
+            if builtin {
+                assert!(result.is_ok());
+            } else {
+                assert!(
+                    matches!(result, Err(Error::NssModuleNotFound(name)) if name == "libnss_files.so.2")
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use crate::base::Result;
+use crate::domain::{Action, Bundle, BundlePath, BundlePathBuf, Script};
+
+pub fn bundle_script<S, P>(
+    bundle: &mut Bundle,
+    script: &Script,
+    input_path: P,
+    install_path: Option<S>,
+) -> Result<BundlePathBuf>
+where
+    S: AsRef<str>,
+    P: AsRef<Path>,
+{
+    tracing::info!(
+        script = %script.path().display(),
+        install_path = ?install_path.as_ref().map(|x| x.as_ref()),
+        "action: bundle script",
+    );
+
+    let bundle_path = match install_path {
+        Some(p) => {
+            let mut path = p.as_ref().to_owned();
+
+            if path.ends_with('/') {
+                path.push_str(script.name());
+                tracing::info!(
+                    completed_path = %path,
+                    "action: bundle_script: completing full path",
+                );
+            }
+            BundlePath::projection(&path).to_owned()
+        }
+        None => BundlePath::projection(&input_path).to_owned(),
+    };
+    bundle.set_origin(Action::BundleScript);
+    bundle.add_file_from(&bundle_path, script.path());
+
+    // interpreters are run by their paths in the shebangs, so they are placed at the same paths
+    for path in script.scripts() {
+        bundle.add_required_by(path, script.path());
+    }
+    for exe in script.executables() {
+        bundle.add_required_by(exe.path(), script.path());
+    }
+
+    Ok(bundle_path)
+}
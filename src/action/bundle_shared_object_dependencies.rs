@@ -1,41 +1,86 @@
-use crate::base::{Error, Result};
-use crate::domain::{Bundle, Executable};
+use crate::base::Result;
+use crate::domain::{Action, Bundle, Executable, ResolverBackend};
 
-fn bundle_shared_object_dependencies_impl(
+pub fn bundle_shared_object_dependencies(
     bundle: &mut Bundle,
     exe: &Executable,
-    cc: &str,
-    noload_resolver: bool,
+    resolver: &ResolverBackend,
 ) -> Result<()> {
     tracing::info!(
         exe = %exe.path().display(),
         "action: bundle shared object dependencies",
     );
 
-    let cc_path = which::which(cc).map_err(|e| Error::ExecutableLocateFailed(cc.to_owned(), e))?;
-
-    bundle.add(exe.interpreter());
-    if noload_resolver {
-        bundle.add(exe.dynamic_libraries_noload(cc_path)?);
-    } else {
-        bundle.add(exe.dynamic_libraries(cc_path)?);
-    }
+    bundle.set_origin(Action::BundleSharedObjectDependencies);
+    bundle.add_required_by(exe.interpreter(), exe.path());
+    bundle.add(exe.dependencies(resolver)?);
 
     Ok(())
 }
 
-pub fn bundle_shared_object_dependencies(
-    bundle: &mut Bundle,
-    exe: &Executable,
-    cc: &str,
-) -> Result<()> {
-    bundle_shared_object_dependencies_impl(bundle, exe, cc, false)
-}
+// the test relies on the glibc-hwcaps levels of x86-64
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+    use crate::domain::{Hwcaps, Root};
+    use assert_fs::prelude::*;
+    use std::fs;
+    use std::process::Command;
 
-pub fn bundle_shared_object_dependencies_noload(
-    bundle: &mut Bundle,
-    exe: &Executable,
-    cc: &str,
-) -> Result<()> {
-    bundle_shared_object_dependencies_impl(bundle, exe, cc, true)
+    #[test]
+    fn test_bundle_hwcaps_baseline() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cc = which::which("cc")?;
+
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("foo.c")
+            .write_str("int foo(void) { return 0; }\n")?;
+        dir.child("main.c")
+            .write_str("int foo(void);\nint main(void) { return foo(); }\n")?;
+        dir.child("lib/glibc-hwcaps/x86-64-v3").create_dir_all()?;
+        let status = Command::new(&cc)
+            .current_dir(dir.path())
+            .args([
+                "-shared",
+                "-fPIC",
+                "-Wl,-soname,libfoo.so.1",
+                "-o",
+                "lib/libfoo.so.1",
+                "foo.c",
+            ])
+            .status()?;
+        assert!(status.success());
+        fs::copy(
+            dir.child("lib/libfoo.so.1").path(),
+            dir.child("lib/glibc-hwcaps/x86-64-v3/libfoo.so.1").path(),
+        )?;
+        let status = Command::new(&cc)
+            .current_dir(dir.path())
+            .args([
+                "-o",
+                "main",
+                "main.c",
+                "lib/libfoo.so.1",
+                "-Wl,-rpath,$ORIGIN/lib",
+            ])
+            .status()?;
+        assert!(status.success());
+
+        let exe = Executable::load(dir.child("main").path())?;
+        let hwcaps = Hwcaps::level("x86-64-v3", exe.target())?;
+        let backend = ResolverBackend::native(&Root::host(), hwcaps)?;
+        let dest = assert_fs::TempDir::new()?;
+        let mut bundle = Bundle::new();
+        bundle_shared_object_dependencies(&mut bundle, &exe, &backend)?;
+        bundle.emit(dest.path())?;
+
+        let bundled = |path: &str| {
+            dest.path()
+                .join(dir.path().strip_prefix("/").unwrap())
+                .join(path)
+        };
+        assert!(bundled("lib/glibc-hwcaps/x86-64-v3/libfoo.so.1").is_file());
+        // ld.so falls back to the baseline library on CPUs without x86-64-v3
+        assert!(bundled("lib/libfoo.so.1").is_file());
+        Ok(())
+    }
 }
@@ -1,9 +1,14 @@
 use std::ffi::OsStr;
 
 use crate::base::{Error, Result};
-use crate::domain::Executable;
+use crate::domain::{Executable, ResolverBackend};
 
-pub fn compress_exexcutable<I, S>(exe: &mut Executable, upx: &str, upx_opts: I) -> Result<()>
+pub fn compress_exexcutable<I, S>(
+    exe: &mut Executable,
+    upx: &str,
+    upx_opts: I,
+    resolver: &ResolverBackend,
+) -> Result<()>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
@@ -13,7 +18,7 @@ where
     let upx_path =
         which::which(upx).map_err(|e| Error::ExecutableLocateFailed(upx.to_owned(), e))?;
 
-    let compressed = exe.compressed(upx_path, upx_opts)?;
+    let compressed = exe.compressed(upx_path, upx_opts, resolver)?;
     tracing::debug!(
         path = %compressed.path().display(),
         "action: compress_exexcutable: compressed executable",
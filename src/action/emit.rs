@@ -1,16 +1,12 @@
 use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
 
 use crate::base::{Error, Result};
-use crate::domain::Bundle;
-
-pub fn emit<P>(bundle: &mut Bundle, path: P) -> Result<()>
-where
-    P: AsRef<Path>,
-{
-    let dest = path.as_ref();
-    tracing::info!(dest = %dest.display(), "action: emit");
+use crate::domain::bundle::DirectoryEmitter;
+use crate::domain::{Bundle, ImageConfig, Reproducible};
 
+fn prepare_destination(dest: &Path) -> Result<()> {
     if dest.exists() {
         if !dest.is_dir() {
             return Err(Error::InvalidDestination(dest.to_owned()));
@@ -25,5 +21,62 @@ where
         );
         fs::create_dir(dest)?;
     };
-    bundle.emit(dest.canonicalize()?)
+    Ok(())
+}
+
+pub fn emit<P>(bundle: &mut Bundle, path: P, reproducible: Option<Reproducible>) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let dest = path.as_ref();
+    tracing::info!(dest = %dest.display(), ?reproducible, "action: emit");
+
+    prepare_destination(dest)?;
+    let mut emitter = DirectoryEmitter::new(dest.canonicalize()?).reproducible(reproducible);
+    bundle.emit_with(&mut emitter)
+}
+
+pub fn emit_oci<P>(
+    bundle: &mut Bundle,
+    path: P,
+    config: &ImageConfig,
+    reproducible: Option<Reproducible>,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let dest = path.as_ref();
+    tracing::info!(
+        dest = %dest.display(),
+        ?config,
+        ?reproducible,
+        "action: emit OCI image layout",
+    );
+
+    prepare_destination(dest)?;
+    bundle.emit_oci(dest.canonicalize()?, config, reproducible)
+}
+
+pub fn emit_tar<P>(bundle: &mut Bundle, path: P, reproducible: Option<Reproducible>) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let dest = path.as_ref();
+    tracing::info!(dest = %dest.display(), ?reproducible, "action: emit tar archive");
+
+    if dest == Path::new("-") {
+        bundle
+            .emit_tar(io::stdout().lock(), reproducible)?
+            .flush()?;
+        return Ok(());
+    }
+
+    if dest.exists() {
+        return Err(Error::InvalidDestination(dest.to_owned()));
+    }
+    let file = fs::File::create(dest)?;
+    bundle
+        .emit_tar(io::BufWriter::new(file), reproducible)?
+        .flush()?;
+    Ok(())
 }
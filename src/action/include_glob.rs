@@ -1,28 +1,142 @@
-use crate::base::{Error, Result};
-use crate::domain::{Bundle, Executable};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-fn include_glob_impl(
+use crate::base::Result;
+use crate::domain::{
+    Action, Bundle, BundlePath, BundlePathBuf, ElfFile, ResolverBackend, Root, Script,
+};
+
+/// Splits `HOST:BUNDLE` into the host pattern and the destination in the bundle.
+fn parse_mapping(pattern: &str) -> (&str, Option<&str>) {
+    match pattern.rsplit_once(':') {
+        Some((host, dest)) if dest.starts_with('/') => (host, Some(dest)),
+        _ => (pattern, None),
+    }
+}
+
+/// Includes `path` to `to` in the bundle, or to the same path as on the host if `to` is `None`.
+/// Dependencies that cannot be resolved are fatal only if `path` is matched by the pattern directly,
+/// and are skipped for files nested in included directories.
+fn include_file(
     bundle: &mut Bundle,
-    pattern: &str,
-    cc: &str,
-    noload_resolver: bool,
+    path: &Path,
+    to: Option<&BundlePath>,
+    nested: bool,
+    root: &Root,
+    resolver: &ResolverBackend,
 ) -> Result<()> {
+    if let Ok(obj) = ElfFile::load_in(path, root, resolver) {
+        bundle.add_required_by(obj.interpreter(), obj.object().path());
+        match obj.dependencies(resolver) {
+            Ok(deps) => bundle.add(deps),
+            Err(e) if nested => {
+                tracing::warn!(path = %path.display(), error = %e, "action: include_glob: Ignoring dependencies of the nested file")
+            }
+            Err(e) => return Err(e),
+        }
+    } else if root.real_host_path(path).is_file() {
+        match Script::load_in(path, root, resolver) {
+            Ok(Some(script)) => include_interpreters(bundle, &script, resolver)?,
+            Ok(None) => {}
+            // the files are still included even if their interpreters are missing
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "action: include_glob: Ignoring interpreters of the script")
+            }
+        }
+    }
+    match to {
+        Some(to) => bundle.add_file_from(to, path),
+        None => bundle.add(path),
+    }
+    Ok(())
+}
+
+/// Includes the interpreters in the chain of shebangs of `script`, with their dependencies.
+fn include_interpreters(
+    bundle: &mut Bundle,
+    script: &Script,
+    resolver: &ResolverBackend,
+) -> Result<()> {
+    for path in script.scripts() {
+        bundle.add_required_by(path, script.path());
+    }
+    for exe in script.executables() {
+        bundle.add_required_by(exe.path(), script.path());
+        bundle.add_required_by(exe.interpreter(), exe.path());
+        bundle.add(exe.dependencies(resolver)?);
+    }
+    Ok(())
+}
+
+/// Includes the contents of the resolved directory `dir` recursively. `visited` holds the directories
+/// already walked, which stops cycles of symbolic links.
+fn include_dir(
+    bundle: &mut Bundle,
+    dir: &Path,
+    to: Option<&BundlePath>,
+    visited: &mut HashSet<PathBuf>,
+    root: &Root,
+    resolver: &ResolverBackend,
+) -> Result<()> {
+    if !visited.insert(dir.to_owned()) {
+        tracing::debug!(dir = %dir.display(), "action: include_glob: already included directory");
+        return Ok(());
+    }
+
+    tracing::debug!(dir = %dir.display(), "action: include_glob: including directory");
+    for entry in fs::read_dir(root.host_path(dir))? {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        let child_to = to.map(|to| to.join(entry.file_name()));
+        include_file(bundle, &path, child_to.as_deref(), true, root, resolver)?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            include_dir(bundle, &path, child_to.as_deref(), visited, root, resolver)?;
+        } else if file_type.is_symlink() && root.real_host_path(&path).is_dir() {
+            // symbolic links to directories are kept as links, and their targets are included at the
+            // resolved paths the links point to
+            let target = root.canonicalize(&path)?;
+            include_dir(bundle, &target, None, visited, root, resolver)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn include_glob(bundle: &mut Bundle, pattern: &str, resolver: &ResolverBackend) -> Result<()> {
     tracing::info!(%pattern, "action: include using glob");
 
-    let cc_path = which::which(cc).map_err(|e| Error::ExecutableLocateFailed(cc.to_owned(), e))?;
+    let (pattern, dest) = parse_mapping(pattern);
+    // matches of a glob pattern are placed into the destination directory
+    let into_dir =
+        glob::Pattern::escape(pattern) != pattern || matches!(dest, Some(d) if d.ends_with('/'));
 
-    for entry in glob::glob(pattern)? {
+    bundle.set_origin(Action::IncludeGlob);
+    let root = bundle.root().clone();
+    let mut visited = HashSet::new();
+    for entry in root.glob(pattern)? {
         match entry {
             Ok(path) => {
-                if let Ok(obj) = Executable::load(&path) {
-                    bundle.add(obj.interpreter());
-                    if noload_resolver {
-                        bundle.add(obj.dynamic_libraries_noload(&cc_path)?);
-                    } else {
-                        bundle.add(obj.dynamic_libraries(&cc_path)?);
-                    }
+                let to: Option<BundlePathBuf> = dest.map(|dest| match path.file_name() {
+                    Some(name) if into_dir => BundlePath::projection(&dest).join(name),
+                    _ => BundlePath::projection(&dest).to_owned(),
+                });
+                if let Some(to) = &to {
+                    tracing::debug!(path = %path.display(), to = %to.display(), "action: include_glob: relocating");
+                }
+
+                if !root.real_host_path(&path).is_dir() {
+                    include_file(bundle, &path, to.as_deref(), false, &root, resolver)?;
+                    continue;
                 }
-                bundle.add(path);
+                // walk the resolved directory so that nothing is copied through a symlink
+                let dir = root.canonicalize(&path)?;
+                match &to {
+                    // a relocated directory is placed as a directory, not a link to the host path
+                    Some(to) => include_file(bundle, &dir, Some(to), false, &root, resolver)?,
+                    None => include_file(bundle, &path, None, false, &root, resolver)?,
+                }
+                include_dir(bundle, &dir, to.as_deref(), &mut visited, &root, resolver)?;
             }
             Err(e) => tracing::warn!(error = %e, "action: include_glob: Ignoring glob match"),
         }
@@ -31,10 +145,88 @@ fn include_glob_impl(
     Ok(())
 }
 
-pub fn include_glob(bundle: &mut Bundle, pattern: &str, cc: &str) -> Result<()> {
-    include_glob_impl(bundle, pattern, cc, false)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Hwcaps;
+    use assert_fs::prelude::*;
+    use std::os::unix;
+    use std::process::Command;
+
+    #[test]
+    fn test_parse_mapping() {
+        assert_eq!(parse_mapping("/lib/*.so"), ("/lib/*.so", None));
+        assert_eq!(
+            parse_mapping("/opt/build/config.yaml:/etc/app/config.yaml"),
+            ("/opt/build/config.yaml", Some("/etc/app/config.yaml"))
+        );
+        assert_eq!(
+            parse_mapping("/opt/a:b/c:/etc/"),
+            ("/opt/a:b/c", Some("/etc/"))
+        );
+        assert_eq!(parse_mapping("/opt/a:b"), ("/opt/a:b", None));
+    }
+
+    #[test]
+    fn test_include_dir_symlinks() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // app/link -> ../data, data/loop -> ../app
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("app/a.txt").write_str("a")?;
+        dir.child("data/x.txt").write_str("x")?;
+        unix::fs::symlink("../data", dir.child("app/link").path())?;
+        unix::fs::symlink("../app", dir.child("data/loop").path())?;
 
-pub fn include_glob_noload(bundle: &mut Bundle, pattern: &str, cc: &str) -> Result<()> {
-    include_glob_impl(bundle, pattern, cc, true)
+        let mut bundle = Bundle::new();
+        let resolver = ResolverBackend::native(&Root::host(), Hwcaps::baseline())?;
+        let app = dir.child("app");
+        include_glob(&mut bundle, app.path().to_str().unwrap(), &resolver)?;
+
+        let dest = assert_fs::TempDir::new()?;
+        bundle.emit(dest.path())?;
+        let in_dest = |path: &Path| BundlePath::projection(&dir.path().join(path)).reify(&dest);
+        assert!(in_dest(Path::new("app/link")).read_link().is_ok());
+        assert_eq!(fs::read_to_string(in_dest(Path::new("data/x.txt")))?, "x");
+        assert_eq!(fs::read_to_string(in_dest(Path::new("app/a.txt")))?, "a");
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_dir_unresolvable() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cc = which::which("cc")?;
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("foo.c")
+            .write_str("int foo(void) { return 0; }\n")?;
+        dir.child("main.c")
+            .write_str("int foo(void);\nint main(void) { return foo(); }\n")?;
+        dir.child("app/sub").create_dir_all()?;
+        let status = Command::new(&cc)
+            .current_dir(dir.path())
+            .args([
+                "-shared",
+                "-fPIC",
+                "-Wl,-soname,libfoo.so.1",
+                "-o",
+                "libfoo.so.1",
+                "foo.c",
+            ])
+            .status()?;
+        assert!(status.success());
+        // libfoo.so.1 is not found from app/sub/main
+        let status = Command::new(&cc)
+            .current_dir(dir.path())
+            .args(["-o", "app/sub/main", "main.c", "libfoo.so.1"])
+            .status()?;
+        assert!(status.success());
+
+        let resolver = ResolverBackend::native(&Root::host(), Hwcaps::baseline())?;
+        let main = dir.child("app/sub/main");
+        let mut bundle = Bundle::new();
+        include_glob(
+            &mut bundle,
+            dir.child("app").path().to_str().unwrap(),
+            &resolver,
+        )?;
+        assert!(include_glob(&mut bundle, main.path().to_str().unwrap(), &resolver).is_err());
+        Ok(())
+    }
 }
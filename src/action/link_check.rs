@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::executable::Symbols;
+use crate::domain::{ElfObject, Executable, ResolverBackend, SharedObject};
+
+struct Object {
+    path: PathBuf,
+    /// Names the object is referred by in `DT_NEEDED` and `DT_VERNEED`.
+    names: Vec<String>,
+    symbols: Symbols,
+}
+
+impl Object {
+    fn load(obj: &ElfObject, names: Vec<String>) -> Result<Self> {
+        let symbols = obj.symbols()?;
+        let mut names = names;
+        names.extend(symbols.soname().map(ToOwned::to_owned));
+        Ok(Object {
+            path: obj.path().to_owned(),
+            names,
+            symbols,
+        })
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+}
+
+fn file_name(path: &Path) -> Vec<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(ToOwned::to_owned)
+        .into_iter()
+        .collect()
+}
+
+fn display_paths<'a, I>(objects: I) -> String
+where
+    I: IntoIterator<Item = &'a Object>,
+{
+    let paths: Vec<_> = objects
+        .into_iter()
+        .map(|obj| obj.path.display().to_string())
+        .collect();
+    if paths.is_empty() {
+        "(none)".to_owned()
+    } else {
+        paths.join(", ")
+    }
+}
+
+/// Checks every object in `objects` binds its undefined symbols and required versions within `objects`.
+/// Returns the number of problems found, each reported as an error log.
+fn check(objects: &[Object]) -> usize {
+    let mut problems = 0;
+    for obj in objects {
+        for version in obj.symbols.version_needs() {
+            let providers: Vec<_> = objects
+                .iter()
+                .filter(|o| o.is_named(&version.file))
+                .collect();
+            if providers
+                .iter()
+                .any(|o| o.symbols.defines_version(&version.name))
+            {
+                continue;
+            }
+            problems += 1;
+            tracing::error!(
+                version = %version.name,
+                file = %version.file,
+                required_by = %obj.path.display(),
+                providers = %display_paths(providers),
+                "action: link_check: version not found",
+            );
+        }
+
+        for sym in obj.symbols.undefined() {
+            if sym.weak {
+                continue;
+            }
+            let version = sym.version.as_ref();
+            // versioned references are bound to the object named in the version requirement
+            let satisfied = objects
+                .iter()
+                .filter(|o| match version {
+                    Some(v) => o.is_named(&v.file),
+                    None => true,
+                })
+                .any(|o| {
+                    o.symbols
+                        .defines(&sym.name, version.map(|v| v.name.as_str()))
+                });
+            if satisfied {
+                continue;
+            }
+            problems += 1;
+            tracing::error!(
+                symbol = %sym.name,
+                version = %version.map_or("", |v| v.name.as_str()),
+                required_by = %obj.path.display(),
+                providers = %display_paths(objects.iter().filter(|o| o.symbols.defines_name(&sym.name))),
+                "action: link_check: undefined symbol",
+            );
+        }
+    }
+    problems
+}
+
+pub fn link_check(exe: &Executable, resolver: &ResolverBackend) -> Result<()> {
+    tracing::info!(exe = %exe.path().display(), "action: link check");
+
+    let root = exe.root();
+    let mut objects = vec![Object::load(exe.object(), Vec::new())?];
+    if let Some(interp) = exe.interpreter() {
+        let interp_so = SharedObject::load_in(interp, root, resolver)?;
+        objects.push(Object::load(interp_so.object(), file_name(interp))?);
+    }
+    for dep in exe.dependencies(resolver)? {
+        if let Some(obj) = objects.iter_mut().find(|obj| obj.path == dep.path) {
+            // the same object can be required with different names, like libc.so for the musl dynamic linker
+            obj.names.push(dep.name);
+            continue;
+        }
+        let lib = SharedObject::load_for(&dep.path, exe, resolver)?;
+        let mut names = file_name(&dep.path);
+        names.push(dep.name);
+        objects.push(Object::load(lib.object(), names)?);
+    }
+
+    match check(&objects) {
+        0 => Ok(()),
+        problems => Err(Error::LinkCheckFailed(problems)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Hwcaps, Root};
+    use assert_fs::prelude::*;
+    use std::process::Command;
+
+    fn compile(
+        cc: &Path,
+        dir: &Path,
+        args: &[&str],
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let status = Command::new(cc).current_dir(dir).args(args).status()?;
+        assert!(status.success());
+        Ok(())
+    }
+
+    /// Builds `lib/libfoo.so.1` from `foo.c` with the version script `map`, if any.
+    fn build_lib(
+        cc: &Path,
+        dir: &assert_fs::TempDir,
+        map: Option<&str>,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut args = vec!["-shared", "-fPIC", "-Wl,-soname,libfoo.so.1"];
+        if let Some(map) = map {
+            dir.child("foo.map").write_str(map)?;
+            args.push("-Wl,--version-script,foo.map");
+        }
+        args.extend(["-o", "lib/libfoo.so.1", "foo.c"]);
+        compile(cc, dir.path(), &args)
+    }
+
+    #[test]
+    fn test_link_check() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let cc = which::which("cc")?;
+
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("lib").create_dir_all()?;
+        dir.child("main.c").write_str(
+            "int foo(void);\nint bar(void);\nint main(void) { return foo() + bar(); }\n",
+        )?;
+        let versioned = "FOO_1.0 { global: foo; bar; local: *; };\n";
+        dir.child("foo.c")
+            .write_str("int foo(void) { return 0; }\nint bar(void) { return 0; }\n")?;
+        build_lib(&cc, &dir, Some(versioned))?;
+        compile(
+            &cc,
+            dir.path(),
+            &[
+                "-o",
+                "main",
+                "main.c",
+                "lib/libfoo.so.1",
+                "-Wl,-rpath,$ORIGIN/lib",
+            ],
+        )?;
+
+        let resolver = ResolverBackend::native(&Root::host(), Hwcaps::baseline())?;
+        let check_main = || -> Result<()> {
+            let exe = Executable::load(dir.child("main").path())?;
+            link_check(&exe, &resolver)
+        };
+        check_main()?;
+
+        // a stub library that lacks bar
+        dir.child("foo.c")
+            .write_str("int foo(void) { return 0; }\n")?;
+        build_lib(&cc, &dir, Some(versioned))?;
+        assert!(matches!(check_main(), Err(Error::LinkCheckFailed(1))));
+
+        // a stub library built without the version node FOO_1.0
+        dir.child("foo.c")
+            .write_str("int foo(void) { return 0; }\nint bar(void) { return 0; }\n")?;
+        build_lib(&cc, &dir, None)?;
+        assert!(matches!(check_main(), Err(Error::LinkCheckFailed(1))));
+        Ok(())
+    }
+}
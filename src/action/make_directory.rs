@@ -1,6 +1,7 @@
-use crate::domain::{Bundle, BundlePath};
+use crate::domain::{Action, Bundle, BundlePath};
 
 pub fn make_directory(bundle: &mut Bundle, path: &str) {
     tracing::info!(%path, "action: make directory");
+    bundle.set_origin(Action::MakeDirectory);
     bundle.mkdir(BundlePath::projection(&path));
 }
@@ -0,0 +1,15 @@
+use std::io::{self, Write};
+
+use crate::base::Result;
+use crate::domain::bundle::{ManifestEmitter, Tree};
+use crate::domain::Bundle;
+
+pub fn print_tree(bundle: &Bundle) -> Result<()> {
+    tracing::info!("action: print tree");
+
+    let mut emitter = ManifestEmitter::new();
+    bundle.emit_with(&mut emitter)?;
+
+    writeln!(io::stdout().lock(), "{}", Tree::new(&emitter))?;
+    Ok(())
+}
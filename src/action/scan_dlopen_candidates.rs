@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::base::Result;
+use crate::domain::{Action, Bundle, ElfObject, Executable, ResolverBackend, SharedObject};
+
+/// What to do with shared objects found by scanning strings in the executable and its dependencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DlopenScan {
+    /// Only report the candidates.
+    Report,
+    /// Include the candidates and their dependencies in the bundle.
+    Include,
+}
+
+/// Scans `obj` in `exe`, which is either the executable itself or one of its dependencies. `resolve` looks up
+/// names in the same way as dlopen(3) called from `obj`.
+fn scan_object<F>(
+    bundle: &mut Bundle,
+    exe: &Executable,
+    obj: &ElfObject,
+    resolve: F,
+    resolver: &ResolverBackend,
+    mode: DlopenScan,
+    found: &mut HashSet<PathBuf>,
+) -> Result<()>
+where
+    F: Fn(&str) -> Result<PathBuf>,
+{
+    let root = exe.root();
+    for candidate in obj.dlopen_candidates()? {
+        let path = match resolve(&candidate.name) {
+            Ok(path) => path,
+            Err(e) => {
+                tracing::debug!(
+                    name = %candidate.name,
+                    error = %e,
+                    "action: scan_dlopen_candidates: unable to resolve candidate",
+                );
+                continue;
+            }
+        };
+        // skip objects that are already bundled
+        if !root.real_host_path(&path).is_file() || !found.insert(path.clone()) {
+            continue;
+        }
+
+        match mode {
+            DlopenScan::Report => tracing::warn!(
+                name = %candidate.name,
+                path = %path.display(),
+                referenced_by = %obj.path().display(),
+                section = %candidate.section,
+                offset = %format!("{:#x}", candidate.offset),
+                "action: possibly loaded with dlopen(3)",
+            ),
+            DlopenScan::Include => {
+                tracing::info!(
+                    name = %candidate.name,
+                    path = %path.display(),
+                    referenced_by = %obj.path().display(),
+                    section = %candidate.section,
+                    offset = %format!("{:#x}", candidate.offset),
+                    "action: including possibly dlopen(3)ed shared object",
+                );
+                bundle.add_required_by(&path, obj.path());
+                let lib = SharedObject::load_for(&path, exe, resolver)?;
+                bundle.add(lib.dependencies(resolver)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn scan_dlopen_candidates(
+    bundle: &mut Bundle,
+    exe: &Executable,
+    resolver: &ResolverBackend,
+    mode: DlopenScan,
+) -> Result<()> {
+    tracing::info!(
+        exe = %exe.path().display(),
+        ?mode,
+        "action: scan strings for dlopen(3)ed shared objects",
+    );
+
+    bundle.set_origin(Action::ScanDlopenCandidates);
+    let libraries = exe.dynamic_libraries(resolver)?;
+    let mut found: HashSet<PathBuf> = libraries.iter().cloned().collect();
+    found.extend(exe.interpreter().cloned());
+
+    let resolve = |name: &str| exe.resolve(name, resolver);
+    scan_object(
+        bundle,
+        exe,
+        exe.object(),
+        resolve,
+        resolver,
+        mode,
+        &mut found,
+    )?;
+    for path in libraries {
+        let lib = SharedObject::load_for(&path, exe, resolver)?;
+        let resolve = |name: &str| lib.resolve(name, resolver);
+        scan_object(
+            bundle,
+            exe,
+            lib.object(),
+            resolve,
+            resolver,
+            mode,
+            &mut found,
+        )?;
+    }
+
+    Ok(())
+}
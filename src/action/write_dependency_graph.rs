@@ -0,0 +1,25 @@
+use std::fs;
+use std::path::Path;
+
+use crate::base::Result;
+use crate::domain::{DependencyGraph, Executable, GraphFormat, ResolverBackend};
+
+pub fn write_dependency_graph<P>(
+    exes: &[&Executable],
+    resolver: &ResolverBackend,
+    path: P,
+    format: GraphFormat,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    tracing::info!(path = %path.display(), ?format, "action: write dependency graph");
+
+    let mut graph = DependencyGraph::new();
+    for exe in exes {
+        graph.add_executable(exe, resolver)?;
+    }
+    fs::write(path, graph.render(format))?;
+    Ok(())
+}
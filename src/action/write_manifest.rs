@@ -0,0 +1,23 @@
+use std::fs;
+use std::path::Path;
+
+use crate::base::Result;
+use crate::domain::bundle::ManifestEmitter;
+use crate::domain::Bundle;
+
+pub fn write_manifest<P>(bundle: &Bundle, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    tracing::info!(path = %path.display(), "action: write manifest");
+
+    let mut emitter = ManifestEmitter::new();
+    bundle.emit_with(&mut emitter)?;
+
+    let mut content = serde_json::to_string_pretty(&emitter.to_json())
+        .expect("serializing JSON values never fails");
+    content.push('\n');
+    fs::write(path, content)?;
+    Ok(())
+}
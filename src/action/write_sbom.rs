@@ -0,0 +1,27 @@
+use std::fs;
+use std::path::Path;
+
+use crate::base::Result;
+use crate::domain::bundle::ManifestEmitter;
+use crate::domain::package::PackageDatabase;
+use crate::domain::{Bundle, Sbom, SbomFormat};
+
+pub fn write_sbom<P>(bundle: &Bundle, path: P, format: SbomFormat, created: u64) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    tracing::info!(path = %path.display(), ?format, "action: write SBOM");
+
+    let mut manifest = ManifestEmitter::new();
+    bundle.emit_with(&mut manifest)?;
+
+    let database = PackageDatabase::load(bundle.root().dir())?;
+    let sbom = Sbom::new(&manifest, database.as_ref(), created)?;
+
+    let mut content = serde_json::to_string_pretty(&sbom.to_json(format))
+        .expect("serializing JSON values never fails");
+    content.push('\n');
+    fs::write(path, content)?;
+    Ok(())
+}
@@ -12,20 +12,51 @@ pub enum Error {
     InvalidGlobPattern(String),
     SharedLibraryLookup(String),
     ResolverCompilation(String),
+    MalformedLdSoCache(String),
+    MalformedLocaleArchive(String),
     MalformedExecutable(String),
-    ValueNotFoundInStrtab { tag: u64, val: u64 },
+    ValueNotFoundInStrtab {
+        tag: u64,
+        val: u64,
+    },
     InterpretorNotFound,
     BusyBoxInstall(String),
     TestFailed(String),
-    TestStdoutMismatch { expected: String, got: String },
+    TestStdoutMismatch {
+        expected: String,
+        got: String,
+    },
     ExecutableLocateFailed(String, which::Error),
     Upx(String),
     DynamicFailed(ExitStatus),
     Encoding(str::Utf8Error),
     PathEncoding(OsString),
     InvalidObjectPath(PathBuf),
+    SymlinkCycle {
+        path: PathBuf,
+        chain: Vec<PathBuf>,
+    },
+    SymlinkChainTooLong {
+        path: PathBuf,
+        chain: Vec<PathBuf>,
+    },
     DynamicWithMultipleInputsUnsupported,
+    DynamicWithScriptUnsupported,
     TestWithMultipleInputsUnsupported,
+    LinkCheckFailed(usize),
+    ScriptInterpreterNotFound {
+        script: PathBuf,
+        interpreter: String,
+    },
+    ScriptNestingTooDeep(PathBuf),
+    NssModuleNotFound(String),
+    GconvCharsetNotFound(String),
+    LocaleNotFound(String),
+    ImageTargetUnknown,
+    MixedImageTargets(String, String),
+    UnsupportedImageTarget(String),
+    HwcapsLevelMismatch(String, String),
+    HostTargetUnknown,
     IO(io::Error),
 }
 
@@ -47,6 +78,8 @@ impl fmt::Display for Error {
                 "Error happend during the compilation of library resolver: {}",
                 e
             ),
+            Error::MalformedLdSoCache(e) => write!(f, "ld.so.cache is malformed: {}", e),
+            Error::MalformedLocaleArchive(e) => write!(f, "locale-archive is malformed: {}", e),
             Error::MalformedExecutable(e) => write!(f, "The executable is malformed: {}", e),
             Error::ValueNotFoundInStrtab { tag, val } => write!(
                 f,
@@ -83,17 +116,89 @@ impl fmt::Display for Error {
             Error::InvalidObjectPath(p) => {
                 write!(f, "Invalid ELF object file path '{}'", p.display())
             }
+            Error::SymlinkCycle { path, chain } => write!(
+                f,
+                "Symbolic link cycle found while bundling {}: {}",
+                path.display(),
+                display_chain(chain)
+            ),
+            Error::SymlinkChainTooLong { path, chain } => write!(
+                f,
+                "Too many levels of symbolic links while bundling {}: {}",
+                path.display(),
+                display_chain(chain)
+            ),
             Error::TestWithMultipleInputsUnsupported => {
                 write!(f, "use of --test with multiple inputs is not supported")
             }
+            Error::LinkCheckFailed(count) => write!(
+                f,
+                "Link check failed: {} undefined symbols or missing versions",
+                count
+            ),
             Error::DynamicWithMultipleInputsUnsupported => {
                 write!(f, "use of --dynamic with multiple inputs is not supported")
             }
+            Error::DynamicWithScriptUnsupported => {
+                write!(f, "use of --dynamic with a script input is not supported")
+            }
+            Error::ScriptInterpreterNotFound {
+                script,
+                interpreter,
+            } => write!(
+                f,
+                "Interpreter {} of the script {} could not be found",
+                interpreter,
+                script.display()
+            ),
+            Error::ScriptNestingTooDeep(path) => write!(
+                f,
+                "Too many levels of interpreters in shebangs from {}",
+                path.display()
+            ),
+            Error::NssModuleNotFound(name) => write!(
+                f,
+                "NSS module {} could not be found, which libc older than glibc 2.34 requires",
+                name
+            ),
+            Error::GconvCharsetNotFound(charset) => {
+                write!(f, "Charset {} could not be found in gconv-modules", charset)
+            }
+            Error::LocaleNotFound(name) => write!(f, "Locale {} could not be found", name),
+            Error::ImageTargetUnknown => {
+                write!(f, "The architecture of the image could not be determined")
+            }
+            Error::MixedImageTargets(a, b) => write!(
+                f,
+                "Executables of different targets cannot be in the same image: {} and {}",
+                a, b
+            ),
+            Error::UnsupportedImageTarget(target) => write!(
+                f,
+                "The target {} has no corresponding architecture in OCI images",
+                target
+            ),
+            Error::HwcapsLevelMismatch(level, target) => write!(
+                f,
+                "The hwcaps level {} is not supported by the target {}",
+                level, target
+            ),
+            Error::HostTargetUnknown => {
+                write!(f, "The target of the host could not be determined")
+            }
             Error::IO(e) => write!(f, "IO error: {}", e),
         }
     }
 }
 
+fn display_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
 impl error::Error for Error {}
 
 impl From<io::Error> for Error {
@@ -226,14 +226,11 @@ mod tests {
             .assert()
             .success();
 
-        assert_eq!(
-            true,
-            Rc::try_unwrap(paths)
-                .unwrap()
-                .into_inner()
-                .iter()
-                .any(|p| p == test_path.path())
-        );
+        assert!(Rc::try_unwrap(paths)
+            .unwrap()
+            .into_inner()
+            .iter()
+            .any(|p| p == test_path.path()));
         Ok(())
     }
 }
@@ -1,10 +1,14 @@
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use magicpak::action;
 use magicpak::base::{Error, Result};
-use magicpak::domain::{Bundle, Executable};
+use magicpak::domain::{
+    Bundle, ElfTarget, Executable, GraphFormat, Hwcaps, ImageConfig, Platform, Reproducible,
+    ResolverBackend, Root, SbomFormat, Script, SymlinkStyle,
+};
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
 #[value(rename_all = "PascalCase")]
@@ -29,19 +33,156 @@ impl LogLevel {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[value(rename_all = "PascalCase")]
+enum OutputFormat {
+    Directory,
+    Tar,
+    Oci,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+#[value(rename_all = "PascalCase")]
+enum GraphFormatArg {
+    Dot,
+    Json,
+}
+
+impl GraphFormatArg {
+    fn to_graph_format(self) -> GraphFormat {
+        match self {
+            GraphFormatArg::Dot => GraphFormat::Dot,
+            GraphFormatArg::Json => GraphFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+#[value(rename_all = "PascalCase")]
+enum SbomFormatArg {
+    Spdx,
+    CycloneDx,
+}
+
+impl SbomFormatArg {
+    fn to_sbom_format(self) -> SbomFormat {
+        match self {
+            SbomFormatArg::Spdx => SbomFormat::Spdx,
+            SbomFormatArg::CycloneDx => SbomFormat::CycloneDx,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+#[value(rename_all = "PascalCase")]
+enum DlopenScanArg {
+    Report,
+    Include,
+}
+
+impl DlopenScanArg {
+    fn to_dlopen_scan(self) -> action::DlopenScan {
+        match self {
+            DlopenScanArg::Report => action::DlopenScan::Report,
+            DlopenScanArg::Include => action::DlopenScan::Include,
+        }
+    }
+}
+
 #[derive(Parser)]
-#[command(name = "magicpak")]
+#[command(
+    name = "magicpak",
+    override_usage = "magicpak [OPTIONS] <INPUT>... <OUTPUT>\n       magicpak [OPTIONS] --dry-run <INPUT>..."
+)]
 struct Args {
     #[arg(value_name = "INPUT", required = true)]
-    /// Input executable
+    /// Input executable, or script run with the interpreter in its shebang, followed by OUTPUT,
+    /// the output destination ('-' for stdout in --output-format Tar), which is not given with --dry-run
     input: Vec<PathBuf>,
 
-    #[arg(value_name = "OUTPUT")]
-    /// Output destination
-    output: PathBuf,
+    // clap does not allow an optional positional argument after INPUT..., so OUTPUT is taken from
+    // the end of INPUT in `parse_args`
+    #[arg(skip)]
+    output: Option<PathBuf>,
 
-    #[arg(short, long, value_name = "GLOB")]
-    /// Additionally include files/directories with glob patterns
+    #[arg(long)]
+    /// Print the resulting bundle as a tree instead of writing OUTPUT or any other file
+    dry_run: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["dynamic", "dlopen_resolver", "experimental_noload_resolver"]
+    )]
+    /// Analyze INPUT and its dependencies inside the root filesystem at DIR, as if DIR were /
+    root: Option<PathBuf>,
+
+    #[arg(long, value_name = "FORMAT", default_value = "Directory")]
+    /// Specify the format of the output
+    output_format: OutputFormat,
+
+    #[arg(long, value_name = "PATH")]
+    /// Write a JSON manifest that explains every file in the bundle
+    manifest: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH")]
+    /// Write a SBOM that maps bundled files to packages in the local package database
+    sbom: Option<PathBuf>,
+
+    #[arg(long, value_name = "FORMAT", default_value = "Spdx")]
+    /// Specify the format of the SBOM in --sbom
+    sbom_format: SbomFormatArg,
+
+    #[arg(long, value_name = "PATH")]
+    /// Write the graph of shared object dependencies, labeled with the search rules that found them
+    dependency_graph: Option<PathBuf>,
+
+    #[arg(long, value_name = "FORMAT", default_value = "Dot")]
+    /// Specify the format of the graph in --dependency-graph
+    dependency_graph_format: GraphFormatArg,
+
+    #[arg(long)]
+    /// Produce identical output for identical inputs
+    reproducible: bool,
+
+    #[arg(long, value_name = "TIMESTAMP", env = "SOURCE_DATE_EPOCH")]
+    /// Clamp file modification times to the timestamp in --reproducible
+    /// (defaults to 0)
+    source_date_epoch: Option<u64>,
+
+    #[arg(long)]
+    /// Keep relative symbolic links relative as long as they stay inside the bundle
+    preserve_relative_links: bool,
+
+    #[arg(
+        long,
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        number_of_values = 1
+    )]
+    /// Specify the entrypoint of the image in --output-format Oci
+    /// (defaults to the installed executable)
+    entrypoint: Vec<String>,
+
+    #[arg(
+        long = "cmd",
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        number_of_values = 1
+    )]
+    /// Specify the default arguments of the image in --output-format Oci
+    image_cmd: Vec<String>,
+
+    #[arg(long, value_name = "PATH")]
+    /// Specify the working directory of the image in --output-format Oci
+    workdir: Option<String>,
+
+    #[arg(long, value_name = "KEY=VALUE")]
+    /// Specify environment variables of the image in --output-format Oci
+    env: Vec<String>,
+
+    #[arg(short, long, value_name = "GLOB[:PATH]")]
+    /// Additionally include files/directories with glob patterns, optionally placing them at PATH
     include: Vec<String>,
 
     #[arg(short, long, value_name = "GLOB")]
@@ -64,7 +205,7 @@ struct Args {
     /// Verbose mode, same as --log-level Info
     verbose: bool,
 
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "dry_run")]
     /// Enable testing
     test: bool,
 
@@ -84,6 +225,33 @@ struct Args {
     /// Enable dynamic analysis
     dynamic: bool,
 
+    #[arg(long)]
+    /// Check that every undefined symbol and symbol version required by the executable and
+    /// its dependencies is provided within the bundle
+    link_check: bool,
+
+    #[arg(long, value_name = "MODE")]
+    /// Scan strings in the executable and its dependencies for shared objects possibly
+    /// loaded with dlopen(3), and Report or Include the ones found by the resolver
+    scan_dlopen: Option<DlopenScanArg>,
+
+    #[arg(long)]
+    /// Bundle NSS modules for the services in /etc/nsswitch.conf, their dependencies,
+    /// and a minimal nsswitch.conf, so that lookups of users and hosts work
+    nss: bool,
+
+    #[arg(long, value_name = "PATH", requires = "nss")]
+    /// Read the NSS configuration from PATH on the host instead of /etc/nsswitch.conf in --nss
+    nsswitch_conf: Option<PathBuf>,
+
+    #[arg(long, value_name = "CHARSET")]
+    /// Bundle gconv modules that iconv(3) loads to convert CHARSET, and a gconv-modules listing only them
+    gconv: Vec<String>,
+
+    #[arg(long, value_name = "NAME")]
+    /// Bundle the locale NAME, extracting it from locale-archive instead of bundling the whole archive
+    locale: Vec<String>,
+
     #[arg(
         long,
         value_name = "ARG",
@@ -118,35 +286,131 @@ struct Args {
     /// Specify the path or name of upx that would be used in compression
     upx: String,
 
+    #[arg(long)]
+    /// Resolve shared library dependencies with a program compiled with --cc
+    /// and dlopen(3), instead of searching ld.so.cache and default directories
+    dlopen_resolver: bool,
+
+    #[arg(long, value_name = "LEVEL")]
+    /// Select libraries in glibc-hwcaps subdirectories for a CPU of LEVEL, such as
+    /// x86-64-v3, in addition to the baseline ones (defaults to baseline)
+    hwcaps: Option<String>,
+
+    #[arg(long, value_name = "NAME")]
+    /// Additionally search legacy hwcap subdirectories like tls, as glibc before 2.37 does
+    legacy_hwcap_dir: Vec<String>,
+
+    #[arg(long, value_name = "PATH")]
+    /// Persist parsed objects and resolved shared objects in PATH to reuse them in later runs
+    cache: Option<PathBuf>,
+
     #[arg(long, value_name = "PATH or NAME", default_value = "cc", env = "CC")]
     /// Specify the path or name of c compiler that would be used in
-    /// the name resolution of shared library dependencies
+    /// --dlopen-resolver
     cc: String,
 
     #[clap(long)]
     /// [EXPERIMENTAL] Resolve dynamic library paths without loading in dlopen(3).
+    /// Implies --dlopen-resolver
     experimental_noload_resolver: bool,
 }
 
 fn run(args: &Args) -> Result<()> {
+    let root = match &args.root {
+        Some(dir) => Root::new(dir)?,
+        None => Root::host(),
+    };
+
     let mut bundle = Bundle::new();
-    let mut exes = args
-        .input
+    bundle.set_root(root.clone());
+    if args.preserve_relative_links {
+        bundle.set_symlink_style(SymlinkStyle::PreserveRelative);
+    }
+    let resolver = if args.dlopen_resolver || args.experimental_noload_resolver {
+        if args.hwcaps.is_some() || !args.legacy_hwcap_dir.is_empty() {
+            tracing::warn!("--hwcaps and --legacy-hwcap-dir are ignored in --dlopen-resolver");
+        }
+        ResolverBackend::dlopen(&args.cc, args.experimental_noload_resolver)?
+    } else {
+        let hwcaps = match &args.hwcaps {
+            Some(level) => Hwcaps::level(level, input_target(&args.input, &root)?)?,
+            None => Hwcaps::baseline(),
+        };
+        ResolverBackend::native(
+            &root,
+            hwcaps.with_legacy_dirs(args.legacy_hwcap_dir.iter().cloned()),
+        )?
+    };
+    let resolver = match &args.cache {
+        Some(path) => resolver.with_cache_file(path)?,
+        None => resolver,
+    };
+
+    let mut exes = Vec::new();
+    let mut exe_inputs = Vec::new();
+    let mut scripts = Vec::new();
+    for input in &args.input {
+        match Script::load_in(input, &root, &resolver)? {
+            Some(script) => scripts.push((script, input)),
+            None => {
+                exes.push(Executable::load_in(input, &root, &resolver)?);
+                exe_inputs.push(input);
+            }
+        }
+    }
+    // executables analyzed for dependencies, including the interpreters of scripts
+    let analyzed: Vec<&Executable> = exes
         .iter()
-        .map(Executable::load)
-        .collect::<Result<Vec<_>>>()?;
-
-    for exe in &exes {
-        if args.experimental_noload_resolver {
-            action::bundle_shared_object_dependencies_noload(&mut bundle, exe, &args.cc)?;
-        } else {
-            action::bundle_shared_object_dependencies(&mut bundle, exe, &args.cc)?;
+        .chain(scripts.iter().flat_map(|(script, _)| script.executables()))
+        .collect();
+
+    for exe in &analyzed {
+        action::bundle_shared_object_dependencies(&mut bundle, exe, &resolver)?;
+    }
+
+    if let Some(mode) = args.scan_dlopen {
+        for exe in &analyzed {
+            action::scan_dlopen_candidates(&mut bundle, exe, &resolver, mode.to_dlopen_scan())?;
+        }
+    }
+
+    if args.nss {
+        action::bundle_nss_modules(
+            &mut bundle,
+            &analyzed,
+            &resolver,
+            args.nsswitch_conf.as_ref(),
+        )?;
+    }
+
+    if !args.gconv.is_empty() {
+        action::bundle_gconv_modules(&mut bundle, &analyzed, &resolver, &args.gconv)?;
+    }
+
+    if !args.locale.is_empty() {
+        action::bundle_locales(&mut bundle, &args.locale)?;
+    }
+
+    if args.link_check {
+        for exe in &analyzed {
+            action::link_check(exe, &resolver)?;
         }
     }
 
+    if let Some(path) = args.dependency_graph.as_ref().filter(|_| !args.dry_run) {
+        action::write_dependency_graph(
+            &analyzed,
+            &resolver,
+            path,
+            args.dependency_graph_format.to_graph_format(),
+        )?;
+    }
+
     if args.dynamic {
-        let &[ref exe] = &exes[..] else {
-            return Err(Error::DynamicWithMultipleInputsUnsupported);
+        let exe = match (&exes[..], &scripts[..]) {
+            ([exe], []) => exe,
+            ([], [_]) => return Err(Error::DynamicWithScriptUnsupported),
+            _ => return Err(Error::DynamicWithMultipleInputsUnsupported),
         };
 
         action::bundle_dynamic_dependencies(
@@ -157,14 +421,29 @@ fn run(args: &Args) -> Result<()> {
         )?;
     }
 
+    // the image is labelled with the target of the bundled executables, not the host
+    let platform = match args.output_format {
+        OutputFormat::Oci => Some(Platform::from_targets(
+            analyzed.iter().map(|exe| exe.target()),
+        )?),
+        _ => None,
+    };
+
     if args.compress {
+        // interpreters of scripts are left uncompressed
         for exe in &mut exes {
-            action::compress_exexcutable(exe, &args.upx, &args.upx_arg)?;
+            action::compress_exexcutable(exe, &args.upx, &args.upx_arg, &resolver)?;
         }
     }
 
-    for (exe, input) in exes.iter().zip(&args.input) {
-        action::bundle_executable(&mut bundle, exe, input, args.install_to.as_ref())?;
+    let mut installed_paths = Vec::new();
+    for (exe, input) in exes.iter().zip(exe_inputs) {
+        let path = action::bundle_executable(&mut bundle, exe, input, args.install_to.as_ref())?;
+        installed_paths.push(path);
+    }
+    for (script, input) in &scripts {
+        let path = action::bundle_script(&mut bundle, script, input, args.install_to.as_ref())?;
+        installed_paths.push(path);
     }
 
     for dir in &args.mkdir {
@@ -172,11 +451,10 @@ fn run(args: &Args) -> Result<()> {
     }
 
     for glob in &args.include {
-        if args.experimental_noload_resolver {
-            action::include_glob_noload(&mut bundle, glob, &args.cc)?;
-        } else {
-            action::include_glob(&mut bundle, glob, &args.cc)?;
-        }
+        action::include_glob(&mut bundle, glob, &resolver)?;
+    }
+    if !args.dry_run {
+        resolver.save_cache()?;
     }
 
     for glob in &args.exclude {
@@ -184,27 +462,103 @@ fn run(args: &Args) -> Result<()> {
     }
 
     if args.test {
-        let &[ref exe] = &exes[..] else {
-            return Err(Error::TestWithMultipleInputsUnsupported);
+        let (exe, default_command) = match (&exes[..], &scripts[..]) {
+            ([exe], []) => (exe, None),
+            // /proc/self/exe of a running script is its interpreter
+            ([], [(script, _)]) => (script.executable(), Some(script.name())),
+            _ => return Err(Error::TestWithMultipleInputsUnsupported),
         };
 
         action::test(
             &bundle,
             exe,
-            args.test_command.as_ref(),
+            args.test_command.as_ref().or(default_command),
             args.test_stdin.as_ref(),
             args.test_stdout.as_ref(),
             &args.busybox,
         )?;
     }
 
-    action::emit(&mut bundle, &args.output)?;
+    if args.dry_run {
+        return action::print_tree(&bundle);
+    }
+
+    if let Some(path) = &args.manifest {
+        action::write_manifest(&bundle, path)?;
+    }
+
+    let reproducible = args.reproducible.then(|| Reproducible {
+        source_date_epoch: args.source_date_epoch.unwrap_or(0),
+    });
+
+    if let Some(path) = &args.sbom {
+        let created = match &reproducible {
+            Some(reproducible) => reproducible.source_date_epoch,
+            None => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        };
+        action::write_sbom(&bundle, path, args.sbom_format.to_sbom_format(), created)?;
+    }
+
+    let output = args
+        .output
+        .as_ref()
+        .expect("OUTPUT is required unless --dry-run is given");
+    match args.output_format {
+        OutputFormat::Directory => action::emit(&mut bundle, output, reproducible)?,
+        OutputFormat::Tar => action::emit_tar(&mut bundle, output, reproducible)?,
+        OutputFormat::Oci => {
+            let entrypoint = if !args.entrypoint.is_empty() {
+                Some(args.entrypoint.clone())
+            } else if let [path] = &installed_paths[..] {
+                Some(vec![format!("/{}", path.to_str_lossy())])
+            } else {
+                None
+            };
+            let config = ImageConfig {
+                platform,
+                entrypoint,
+                cmd: (!args.image_cmd.is_empty()).then(|| args.image_cmd.clone()),
+                working_dir: args.workdir.clone(),
+                env: args.env.clone(),
+            };
+            action::emit_oci(&mut bundle, output, &config, reproducible)?;
+        }
+    }
 
     Ok(())
 }
 
+/// The target of the first ELF input, or of the host if all inputs are scripts.
+fn input_target(inputs: &[PathBuf], root: &Root) -> Result<ElfTarget> {
+    for input in inputs {
+        if let Some(target) = ElfTarget::read(root.real_host_path(input))? {
+            return Ok(target);
+        }
+    }
+    ElfTarget::host().ok_or(Error::HostTargetUnknown)
+}
+
+/// Parses the arguments, where OUTPUT is not given with --dry-run.
+fn parse_args() -> Args {
+    let mut args = Args::parse();
+    if !args.dry_run {
+        if args.input.len() < 2 {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "the following required arguments were not provided:\n  <OUTPUT>",
+                )
+                .exit();
+        }
+        args.output = args.input.pop();
+    }
+    args
+}
+
 fn main() {
-    let args = Args::parse();
+    let args = parse_args();
 
     let level_filter = if args.verbose {
         tracing_subscriber::filter::LevelFilter::INFO
@@ -1,11 +1,28 @@
 pub mod bundle;
 pub mod bundle_path;
+pub mod dependency_graph;
 pub mod executable;
+pub mod gconv;
 pub mod jail;
+pub mod locale_archive;
+pub mod nsswitch;
+pub mod oci;
+pub mod package;
 pub mod resource;
+pub mod root;
+pub mod sbom;
+pub mod script;
 
-pub use bundle::Bundle;
+pub use bundle::{Action, Bundle, Origin, Reproducible, SymlinkStyle};
 pub use bundle_path::{BundlePath, BundlePathBuf};
-pub use executable::Executable;
+pub use dependency_graph::{DependencyGraph, GraphFormat};
+pub use executable::{
+    DlopenCandidate, ElfFile, ElfObject, ElfTarget, Executable, Hwcaps, ResolverBackend,
+    SharedObject,
+};
 pub use jail::Jail;
+pub use oci::{ImageConfig, Platform};
 pub use resource::Resource;
+pub use root::Root;
+pub use sbom::{Sbom, SbomFormat};
+pub use script::Script;
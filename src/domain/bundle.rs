@@ -1,28 +1,179 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::default::Default;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
 
-use crate::base::Result;
-use crate::domain::{BundlePath, BundlePathBuf, Executable, Jail, Resource};
+use crate::base::{Error, Result};
+use crate::domain::oci::OciLayout;
+use crate::domain::root::MAX_SYMLINK_FOLLOWS;
+use crate::domain::{BundlePath, BundlePathBuf, Executable, ImageConfig, Jail, Resource, Root};
+
+use nix::errno::Errno;
+
+mod emitter;
+mod manifest;
+mod tree;
+pub use emitter::{DirectoryEmitter, Emitter, Reproducible, TarEmitter};
+pub use manifest::{EntryKind, ManifestEmitter, ManifestEntry};
+pub(crate) use tree::format_size;
+pub use tree::Tree;
 
 #[derive(Clone)]
 enum Source {
     NewDirectory,
     NewFile(Vec<u8>),
     CopyFrom(PathBuf),
+    CopyFromHost(PathBuf),
+}
+
+/// The action that adds an entry to the bundle.
+///
+/// Variants are declared in increasing precedence: when several actions add the same entry, the
+/// one declared last is recorded as its origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Action {
+    #[default]
+    Unknown,
+    /// The target of a bundled symbolic link, pulled in implicitly.
+    SyncCopy,
+    ScanDlopenCandidates,
+    BundleDynamicDependencies,
+    BundleLocales,
+    BundleGconvModules,
+    BundleNssModules,
+    BundleSharedObjectDependencies,
+    MakeDirectory,
+    IncludeGlob,
+    BundleScript,
+    BundleExecutable,
+    Test,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Unknown => "unknown",
+            Action::SyncCopy => "sync_copy",
+            Action::ScanDlopenCandidates => "scan_dlopen_candidates",
+            Action::BundleDynamicDependencies => "bundle_dynamic_dependencies",
+            Action::BundleLocales => "bundle_locales",
+            Action::BundleGconvModules => "bundle_gconv_modules",
+            Action::BundleNssModules => "bundle_nss_modules",
+            Action::BundleSharedObjectDependencies => "bundle_shared_object_dependencies",
+            Action::MakeDirectory => "make_directory",
+            Action::IncludeGlob => "include_glob",
+            Action::BundleScript => "bundle_script",
+            Action::BundleExecutable => "bundle_executable",
+            Action::Test => "test",
+        }
+    }
+}
+
+/// Records why an entry is added to the bundle.
+#[derive(Debug, Clone, Default)]
+pub struct Origin {
+    action: Action,
+    required_by: BTreeSet<PathBuf>,
+}
+
+impl Origin {
+    pub fn new(action: Action) -> Self {
+        Origin {
+            action,
+            required_by: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_required_by<P>(&self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let mut origin = self.clone();
+        origin.required_by.insert(path.as_ref().to_owned());
+        origin
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    pub fn required_by(&self) -> impl Iterator<Item = &Path> {
+        self.required_by.iter().map(PathBuf::as_path)
+    }
+
+    /// Merges the origin of another addition of the same entry, keeping the action of higher
+    /// precedence and every path that requires the entry through that action.
+    pub fn merge(&mut self, other: &Origin) {
+        match other.action.cmp(&self.action) {
+            Ordering::Greater => *self = other.clone(),
+            Ordering::Equal => self.required_by.extend(other.required_by.iter().cloned()),
+            Ordering::Less => {}
+        }
+    }
+}
+
+/// How symbolic links are recreated in the bundle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkStyle {
+    /// Point every link to the absolute path of its target.
+    #[default]
+    Absolute,
+    /// Keep relative links as they are as long as they stay inside the bundle.
+    /// Relative links escaping the bundle are rewritten to the shortest relative path to the target.
+    PreserveRelative,
+}
+
+#[derive(Clone)]
+struct Entry {
+    source: Source,
+    origin: Origin,
 }
 
 #[derive(Default, Clone)]
 pub struct Bundle {
-    entries: HashMap<BundlePathBuf, Source>,
+    entries: BTreeMap<BundlePathBuf, Entry>,
+    origin: Origin,
+    symlink_style: SymlinkStyle,
+    root: Root,
 }
 
 impl Bundle {
     pub fn new() -> Bundle {
         Bundle {
-            entries: HashMap::new(),
+            entries: BTreeMap::new(),
+            origin: Origin::default(),
+            symlink_style: SymlinkStyle::default(),
+            root: Root::host(),
+        }
+    }
+
+    pub fn set_symlink_style(&mut self, style: SymlinkStyle) {
+        self.symlink_style = style;
+    }
+
+    /// Sets the root filesystem that paths given to `add_file_from` are interpreted in.
+    pub fn set_root(&mut self, root: Root) {
+        self.root = root;
+    }
+
+    pub fn root(&self) -> &Root {
+        &self.root
+    }
+
+    /// Sets the action that is recorded as the origin of subsequently added entries.
+    pub fn set_origin(&mut self, action: Action) {
+        self.origin = Origin::new(action);
+    }
+
+    fn insert(&mut self, path: &BundlePath, source: Source) {
+        let mut origin = self.origin.clone();
+        if let Some(entry) = self.entries.get(path) {
+            origin.merge(&entry.origin);
         }
+        let entry = Entry { source, origin };
+        self.entries.insert(path.to_owned(), entry);
     }
 
     pub fn mkdir<P>(&mut self, path: P)
@@ -30,8 +181,7 @@ impl Bundle {
         P: AsRef<BundlePath>,
     {
         tracing::debug!(path = %path.as_ref().display(), "bundle: mkdir");
-        self.entries
-            .insert(path.as_ref().to_owned(), Source::NewDirectory);
+        self.insert(path.as_ref(), Source::NewDirectory);
     }
 
     pub fn add_file<P>(&mut self, path: P, content: Vec<u8>)
@@ -42,8 +192,7 @@ impl Bundle {
             path = %path.as_ref().display(),
             "bundle: add_file",
         );
-        self.entries
-            .insert(path.as_ref().to_owned(), Source::NewFile(content));
+        self.insert(path.as_ref(), Source::NewFile(content));
     }
 
     pub fn add_file_from<P, Q>(&mut self, path: P, from: Q)
@@ -58,12 +207,40 @@ impl Bundle {
             "bundle: copy",
         );
 
-        self.entries.insert(
-            path.as_ref().to_owned(),
-            Source::CopyFrom(from.as_ref().to_owned()),
+        self.insert(path.as_ref(), Source::CopyFrom(from.as_ref().to_owned()));
+    }
+
+    /// Same as `add_file_from`, but `from` is a path on the host even if the root is set.
+    pub fn add_file_from_host<P, Q>(&mut self, path: P, from: Q)
+    where
+        P: AsRef<BundlePath>,
+        Q: AsRef<Path>,
+    {
+        debug_assert!(from.as_ref().is_absolute());
+        tracing::debug!(
+            from = %from.as_ref().display(),
+            path = %path.as_ref().display(),
+            "bundle: copy from host",
+        );
+
+        self.insert(
+            path.as_ref(),
+            Source::CopyFromHost(from.as_ref().to_owned()),
         );
     }
 
+    pub fn add_executable<P>(&mut self, path: P, exe: &Executable)
+    where
+        P: AsRef<BundlePath>,
+    {
+        // compressed executables are temporary files on the host
+        if exe.is_temporary() {
+            self.add_file_from_host(path, exe.path());
+        } else {
+            self.add_file_from(path, exe.path());
+        }
+    }
+
     pub fn add<R>(&mut self, resource: R)
     where
         R: Resource,
@@ -71,6 +248,17 @@ impl Bundle {
         resource.bundle_to(self);
     }
 
+    pub fn add_required_by<R, P>(&mut self, resource: R, required_by: P)
+    where
+        R: Resource,
+        P: AsRef<Path>,
+    {
+        let origin = self.origin.with_required_by(required_by);
+        let action_origin = std::mem::replace(&mut self.origin, origin);
+        resource.bundle_to(self);
+        self.origin = action_origin;
+    }
+
     pub fn filter<P>(&mut self, mut predicate: P)
     where
         P: FnMut(&BundlePathBuf) -> bool,
@@ -80,34 +268,69 @@ impl Bundle {
         self.entries = updated;
     }
 
-    pub fn emit<P>(&self, dest: P) -> Result<()>
+    pub fn emit_with<E>(&self, emitter: &mut E) -> Result<()>
     where
-        P: AsRef<Path>,
+        E: Emitter,
     {
-        for (bpath, source) in self.entries.iter() {
+        for (bpath, Entry { source, origin }) in self.entries.iter() {
             match source {
-                Source::NewDirectory => {
-                    let path = bpath.reify(&dest);
-                    tracing::info!(path = %path.display(), "emit: mkdir");
-                    fs::create_dir_all(path)?
-                }
-                Source::NewFile(blob) => {
-                    let path = bpath.reify(&dest);
-                    tracing::info!(path = %path.display(), "emit: write");
-                    create_parent_dir(&path)?;
-                    fs::write(path, blob)?
-                }
-                Source::CopyFrom(src_path) => {
-                    sync_copy(src_path, bpath, dest.as_ref())?;
-                }
+                Source::NewDirectory => emitter.mkdir(bpath, origin)?,
+                Source::NewFile(blob) => emitter.write(bpath, blob, origin)?,
+                Source::CopyFrom(src_path) => sync_copy(
+                    src_path,
+                    bpath,
+                    origin,
+                    &self.root,
+                    self.symlink_style,
+                    emitter,
+                )?,
+                Source::CopyFromHost(src_path) => sync_copy(
+                    src_path,
+                    bpath,
+                    origin,
+                    &Root::host(),
+                    self.symlink_style,
+                    emitter,
+                )?,
             }
         }
-        Ok(())
+        emitter.finalize()
+    }
+
+    pub fn emit<P>(&self, dest: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        self.emit_with(&mut DirectoryEmitter::new(dest))
+    }
+
+    pub fn emit_tar<W>(&self, writer: W, reproducible: Option<Reproducible>) -> Result<W>
+    where
+        W: Write,
+    {
+        let mut emitter = TarEmitter::new(writer).reproducible(reproducible);
+        self.emit_with(&mut emitter)?;
+        emitter.finish()
+    }
+
+    pub fn emit_oci<P>(
+        &self,
+        dest: P,
+        config: &ImageConfig,
+        reproducible: Option<Reproducible>,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let layout = OciLayout::create(dest)?;
+        let layer = layout.write_layer(|writer| self.emit_tar(writer, reproducible).map(|_| ()))?;
+        layout.write_image(&layer, config)
     }
 
     pub fn add_pseudo_proc(&mut self, exe: &Executable) {
         // TODO: using symlink would be better
-        self.add_file_from(BundlePath::new("proc/self/exe"), exe.path());
+        self.set_origin(Action::Test);
+        self.add_executable(BundlePath::new("proc/self/exe"), exe);
     }
 
     pub fn create_jail(&self) -> Result<Jail> {
@@ -119,72 +342,180 @@ impl Bundle {
     }
 }
 
-fn create_parent_dir<P>(path: P) -> Result<()>
-where
-    P: AsRef<Path>,
-{
-    match path.as_ref().parent() {
-        Some(parent) if !parent.exists() => fs::create_dir_all(parent).map_err(Into::into),
-        _ => Ok(()),
-    }
-}
-
 // We don't use `fs::copy` directly because we want to respect symlinks.
 // Also `fs::canonicalize` is not used because we don't want to skip intermediate links.
-fn sync_copy(from: &Path, to: &BundlePath, dest: &Path) -> Result<()> {
-    use std::os::unix;
+// Paths in the chain are paths in `root`, and emitters receive the corresponding paths on the host.
+fn sync_copy<E>(
+    from: &Path,
+    to: &BundlePath,
+    origin: &Origin,
+    root: &Root,
+    style: SymlinkStyle,
+    emitter: &mut E,
+) -> Result<()>
+where
+    E: Emitter,
+{
     debug_assert!(from.is_absolute());
-    debug_assert!(dest.is_absolute());
-
-    let target = to.reify(dest);
-    debug_assert!(target.is_absolute());
 
-    create_parent_dir(&target)?;
+    let chain = match follow_links(from, to, root)? {
+        Some(chain) => chain,
+        None => return Ok(()),
+    };
+    // unwrap is ok because the chain contains at least `from`
+    let (file, links) = chain.split_last().unwrap();
 
-    if !from.exists() {
-        tracing::warn!(
-            path = %from.display(),
-            "emit: copy source does not exist. skipping.",
-        );
-        return Ok(());
+    let mut to = to.to_owned();
+    let mut origin = origin.clone();
+    for (link, link_dest_absolute) in links.iter().zip(&chain[1..]) {
+        let target = match style {
+            SymlinkStyle::Absolute => {
+                emitter.symlink(&to, &root.host_path(link), link_dest_absolute, &origin)?;
+                BundlePath::projection(&normalize_path(link_dest_absolute)).to_owned()
+            }
+            SymlinkStyle::PreserveRelative => {
+                let host_link = root.host_path(link);
+                let target = BundlePath::projection(&normalize_path(link_dest_absolute)).to_owned();
+                let link_text = relative_link(&to, &host_link.read_link()?, &target);
+                emitter.symlink(&to, &host_link, &link_text, &origin)?;
+                target
+            }
+        };
+        to = target;
+        origin = Origin::new(Action::SyncCopy).with_required_by(link);
+    }
+    let host_file = root.host_path(file);
+    if host_file.is_dir() {
+        emitter.copy_dir(&to, &host_file, &origin)
+    } else {
+        emitter.copy(&to, &host_file, &origin)
     }
+}
 
-    if fs::symlink_metadata(from)?.file_type().is_symlink() {
-        let link_dest = from.read_link()?;
+/// Follows the chain of symbolic links starting at `from`, which is bundled to `to`.
+/// Returns the chain ending with a non-link file, or `None` if the chain is dangling.
+fn follow_links(from: &Path, to: &BundlePath, root: &Root) -> Result<Option<Vec<PathBuf>>> {
+    let mut chain = vec![from.to_owned()];
+    loop {
+        // unwrap is ok because the chain is never empty
+        let current = chain.last().unwrap();
+        let host_current = root.host_path(current);
+        let metadata = match fs::symlink_metadata(&host_current) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && chain.len() == 1 => {
+                tracing::warn!(
+                    path = %from.display(),
+                    "emit: copy source does not exist. skipping.",
+                );
+                return Ok(None);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::warn!(
+                    path = %to.display(),
+                    chain = ?chain,
+                    "emit: dangling symbolic link. skipping.",
+                );
+                return Ok(None);
+            }
+            // a cycle in the symbolic links of parent directories
+            Err(e) if e.raw_os_error() == Some(Errno::ELOOP as i32) => {
+                return Err(Error::SymlinkCycle {
+                    path: Path::new("/").join(to.as_os_str()),
+                    chain,
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !metadata.file_type().is_symlink() {
+            return Ok(Some(chain));
+        }
+
+        let link_dest = host_current.read_link()?;
         let link_dest_absolute = if link_dest.is_relative() {
-            // unwrap is ok because `from` here is an absolute path to a symbolic link
-            from.parent().unwrap().join(link_dest)
+            // unwrap is ok because `current` here is an absolute path to a symbolic link
+            current.parent().unwrap().join(&link_dest)
         } else {
             link_dest
         };
-        tracing::info!(
-            link = %link_dest_absolute.display(),
-            target = %target.display(),
-            "emit: link",
-        );
-        match target.read_link() {
-            // the bundle may contain an entry that symlinks to `target`
-            Ok(target_link_dest) if target_link_dest == link_dest_absolute => {
-                tracing::debug!(
-                    link = %link_dest_absolute.display(),
-                    target = %target.display(),
-                    "emit: already linked, skipping",
-                );
-            }
-            _ => {
-                unix::fs::symlink(&link_dest_absolute, target)?;
+
+        let normalized = normalize_path(&link_dest_absolute);
+        let cyclic = chain.iter().any(|p| normalize_path(p) == normalized);
+        chain.push(link_dest_absolute);
+        if cyclic {
+            return Err(Error::SymlinkCycle {
+                path: Path::new("/").join(to.as_os_str()),
+                chain,
+            });
+        }
+        if chain.len() - 1 > MAX_SYMLINK_FOLLOWS {
+            return Err(Error::SymlinkChainTooLong {
+                path: Path::new("/").join(to.as_os_str()),
+                chain,
+            });
+        }
+    }
+}
+
+/// Returns the link text that refers to `target` from the link at `path`.
+/// `link_dest` is used as it is if it is absolute or it is relative and refers to `target`
+/// without escaping the bundle.
+fn relative_link(path: &BundlePath, link_dest: &Path, target: &BundlePath) -> PathBuf {
+    if link_dest.is_absolute() {
+        return link_dest.to_owned();
+    }
+
+    // unwrap is ok because bundle paths are relative
+    let dir = Path::new(path.as_os_str()).parent().unwrap();
+    let mut resolved = dir.to_owned();
+    let mut escaped = false;
+    for component in link_dest.components() {
+        match component {
+            Component::ParentDir => escaped |= !resolved.pop(),
+            Component::Normal(name) => resolved.push(name),
+            _ => {}
+        }
+    }
+    if !escaped && resolved.as_os_str() == target.as_os_str() {
+        return link_dest.to_owned();
+    }
+
+    let dir_components: Vec<_> = dir.components().collect();
+    let target_components: Vec<_> = Path::new(target.as_os_str()).components().collect();
+    let common = dir_components
+        .iter()
+        .zip(&target_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut link = PathBuf::new();
+    for _ in common..dir_components.len() {
+        link.push("..");
+    }
+    link.extend(&target_components[common..]);
+    if link.as_os_str().is_empty() {
+        link.push(".");
+    }
+    tracing::info!(
+        path = %path.display(),
+        original = %link_dest.display(),
+        rewritten = %link.display(),
+        "emit: rewriting a relative link that escapes the bundle",
+    );
+    link
+}
+
+/// Lexically normalizes an absolute path, removing `.` and `..` components.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
             }
+            Component::CurDir => {}
+            component => normalized.push(component),
         }
-        sync_copy(
-            &link_dest_absolute,
-            BundlePath::projection(&link_dest_absolute),
-            dest,
-        )
-    } else {
-        tracing::info!(from = %from.display(), target = %target.display(), "emit: copy");
-        fs::copy(from, target)?;
-        Ok(())
     }
+    normalized
 }
 
 #[cfg(test)]
@@ -192,7 +523,9 @@ mod tests {
     use super::*;
     use assert_fs::prelude::*;
     use predicates::prelude::*;
+    use serde_json::json;
     use std::os::unix;
+    use std::os::unix::fs::PermissionsExt;
 
     #[test]
     fn test_sync_copy() -> std::result::Result<(), Box<dyn std::error::Error>> {
@@ -200,7 +533,14 @@ mod tests {
         let src = assert_fs::NamedTempFile::new("x.txt")?;
         src.write_str("hello")?;
         let bundle_path = BundlePath::new("a/b/c.txt");
-        sync_copy(src.path(), bundle_path, dest.path())?;
+        sync_copy(
+            src.path(),
+            bundle_path,
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut DirectoryEmitter::new(dest.path()),
+        )?;
         dest.child("a/b/c.txt").assert("hello");
         Ok(())
     }
@@ -210,7 +550,14 @@ mod tests {
         let dest = assert_fs::TempDir::new()?;
         let src = assert_fs::TempDir::new()?.child("nonexistent.txt");
         let bundle_path = BundlePath::new("a/b/c.txt");
-        sync_copy(src.path(), bundle_path, dest.path())?;
+        sync_copy(
+            src.path(),
+            bundle_path,
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut DirectoryEmitter::new(dest.path()),
+        )?;
         dest.child("a/b/c.txt").assert(predicate::path::missing());
         Ok(())
     }
@@ -226,7 +573,14 @@ mod tests {
         unix::fs::symlink(src.path(), link.path())?;
 
         let bundle_path = BundlePath::new("a/b/c.txt");
-        sync_copy(link.path(), bundle_path, dest.path())?;
+        sync_copy(
+            link.path(),
+            bundle_path,
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut DirectoryEmitter::new(dest.path()),
+        )?;
 
         assert!(fs::symlink_metadata(dest.child("a/b/c.txt").path())?
             .file_type()
@@ -308,9 +662,31 @@ mod tests {
         let link2 = src_dir.child("z.txt");
         unix::fs::symlink(link1.path(), link2.path())?;
 
-        sync_copy(src.path(), BundlePath::projection(&src), dest.path())?;
-        sync_copy(link1.path(), BundlePath::projection(&link1), dest.path())?;
-        sync_copy(link2.path(), BundlePath::projection(&link2), dest.path())?;
+        let mut emitter = DirectoryEmitter::new(dest.path());
+        sync_copy(
+            src.path(),
+            BundlePath::projection(&src),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut emitter,
+        )?;
+        sync_copy(
+            link1.path(),
+            BundlePath::projection(&link1),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut emitter,
+        )?;
+        sync_copy(
+            link2.path(),
+            BundlePath::projection(&link2),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut emitter,
+        )?;
 
         assert!(matches!(
             dest.child(link1.path()).path().read_link(),
@@ -327,4 +703,547 @@ mod tests {
             .assert("hello");
         Ok(())
     }
+
+    #[test]
+    fn test_sync_copy_relative_link() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // bin/z.txt -> ../lib/y.txt -> x.txt
+        let dest = assert_fs::TempDir::new()?;
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("lib/x.txt");
+        src.write_str("hello")?;
+        let link1 = src_dir.child("lib/y.txt");
+        unix::fs::symlink("x.txt", link1.path())?;
+        let link2 = src_dir.child("bin/z.txt");
+        src_dir.child("bin").create_dir_all()?;
+        unix::fs::symlink("../lib/y.txt", link2.path())?;
+
+        sync_copy(
+            link2.path(),
+            BundlePath::projection(&link2),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::PreserveRelative,
+            &mut DirectoryEmitter::new(dest.path()),
+        )?;
+
+        assert_eq!(
+            BundlePath::projection(&link2).reify(&dest).read_link()?,
+            Path::new("../lib/y.txt")
+        );
+        assert_eq!(
+            BundlePath::projection(&link1).reify(&dest).read_link()?,
+            Path::new("x.txt")
+        );
+        dest.child(BundlePath::projection(&link2).as_os_str())
+            .assert("hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_copy_in_root() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // /lib -> /usr/lib, /usr/lib/y.txt -> /lib/x.txt in the root
+        let dest = assert_fs::TempDir::new()?;
+        let root_dir = assert_fs::TempDir::new()?;
+        root_dir.child("usr/lib/x.txt").write_str("hello")?;
+        unix::fs::symlink("/usr/lib", root_dir.child("lib").path())?;
+        unix::fs::symlink("/lib/x.txt", root_dir.child("usr/lib/y.txt").path())?;
+
+        sync_copy(
+            Path::new("/lib/y.txt"),
+            BundlePath::new("lib/y.txt"),
+            &Origin::default(),
+            &Root::new(root_dir.path())?,
+            SymlinkStyle::Absolute,
+            &mut DirectoryEmitter::new(dest.path()),
+        )?;
+
+        assert_eq!(
+            dest.child("lib/y.txt").read_link()?,
+            Path::new("/lib/x.txt")
+        );
+        dest.child("lib/x.txt").assert("hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_copy_escaping_relative_link() -> std::result::Result<(), Box<dyn std::error::Error>>
+    {
+        let dest = assert_fs::TempDir::new()?;
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("x.txt");
+        src.write_str("hello")?;
+
+        // goes above the root directory before coming back to x.txt,
+        // which works on the host but escapes the bundle
+        let depth = src_dir.path().components().count();
+        let mut escaping = PathBuf::new();
+        for _ in 0..depth + 2 {
+            escaping.push("..");
+        }
+        escaping.push(src.path().strip_prefix("/")?);
+        let link1 = src_dir.child("y.txt");
+        unix::fs::symlink(&escaping, link1.path())?;
+
+        // refers to a different path once installed somewhere else in the bundle
+        let link2 = src_dir.child("z.txt");
+        unix::fs::symlink("x.txt", link2.path())?;
+
+        let mut emitter = DirectoryEmitter::new(dest.path());
+        sync_copy(
+            link1.path(),
+            BundlePath::projection(&link1),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::PreserveRelative,
+            &mut emitter,
+        )?;
+        sync_copy(
+            link2.path(),
+            BundlePath::new("bin/z.txt"),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::PreserveRelative,
+            &mut emitter,
+        )?;
+
+        assert_eq!(
+            BundlePath::projection(&link1).reify(&dest).read_link()?,
+            Path::new("x.txt")
+        );
+        assert_eq!(
+            dest.child("bin/z.txt").path().read_link()?,
+            Path::new("..").join(src.path().strip_prefix("/")?)
+        );
+        dest.child(BundlePath::projection(&link1).as_os_str())
+            .assert("hello");
+        dest.child("bin/z.txt").assert("hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_copy_cyclic_link() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // x.txt -> y.txt -> z.txt -> y.txt
+        let dest = assert_fs::TempDir::new()?;
+        let src_dir = assert_fs::TempDir::new()?;
+        let link1 = src_dir.child("x.txt");
+        let link2 = src_dir.child("y.txt");
+        let link3 = src_dir.child("z.txt");
+        unix::fs::symlink("y.txt", link1.path())?;
+        unix::fs::symlink("z.txt", link2.path())?;
+        unix::fs::symlink(link2.path(), link3.path())?;
+
+        let result = sync_copy(
+            link1.path(),
+            BundlePath::new("a/b.txt"),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut DirectoryEmitter::new(dest.path()),
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::SymlinkCycle { path, chain })
+            if path == Path::new("/a/b.txt")
+                && chain == [link1.path(), link2.path(), link3.path(), link2.path()]
+        ));
+        dest.child("a/b.txt").assert(predicate::path::missing());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_copy_long_link_chain() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dest = assert_fs::TempDir::new()?;
+        let src_dir = assert_fs::TempDir::new()?;
+        src_dir.child("0.txt").write_str("hello")?;
+        for idx in 1..=MAX_SYMLINK_FOLLOWS + 1 {
+            unix::fs::symlink(
+                format!("{}.txt", idx - 1),
+                src_dir.child(format!("{}.txt", idx)).path(),
+            )?;
+        }
+
+        let result = sync_copy(
+            src_dir
+                .child(format!("{}.txt", MAX_SYMLINK_FOLLOWS + 1))
+                .path(),
+            BundlePath::new("a.txt"),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut DirectoryEmitter::new(dest.path()),
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::SymlinkChainTooLong { chain, .. }) if chain.len() == MAX_SYMLINK_FOLLOWS + 2
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_copy_dangling_link() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dest = assert_fs::TempDir::new()?;
+        let src_dir = assert_fs::TempDir::new()?;
+        let link1 = src_dir.child("x.txt");
+        let link2 = src_dir.child("y.txt");
+        unix::fs::symlink("y.txt", link1.path())?;
+        unix::fs::symlink("nonexistent.txt", link2.path())?;
+
+        sync_copy(
+            link1.path(),
+            BundlePath::new("a/b.txt"),
+            &Origin::default(),
+            &Root::host(),
+            SymlinkStyle::Absolute,
+            &mut DirectoryEmitter::new(dest.path()),
+        )?;
+
+        dest.child("a/b.txt").assert(predicate::path::missing());
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_copied_directory() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dest = assert_fs::TempDir::new()?;
+        let src_dir = assert_fs::TempDir::new()?;
+        let dir = src_dir.child("ro");
+        dir.create_dir_all()?;
+        let src = dir.child("x.txt");
+        src.write_str("hello")?;
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o555))?;
+
+        let mut bundle = Bundle::new();
+        bundle.add(dir.path());
+        bundle.add(src.path());
+        bundle.emit(dest.path())?;
+
+        let copied = BundlePath::projection(&dir).reify(&dest);
+        assert_eq!(fs::metadata(&copied)?.permissions().mode() & 0o777, 0o555);
+        dest.child(BundlePath::projection(&src).as_os_str())
+            .assert("hello");
+
+        // allow the temporary directories to be removed
+        fs::set_permissions(copied, fs::Permissions::from_mode(0o755))?;
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_oci() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dest = assert_fs::TempDir::new()?;
+
+        let mut bundle = Bundle::new();
+        bundle.add_file(BundlePath::new("dir/text.txt"), b"hello".to_vec());
+        let config = ImageConfig {
+            entrypoint: Some(vec!["/bin/sh".to_owned()]),
+            ..Default::default()
+        };
+        bundle.emit_oci(dest.path(), &config, None)?;
+
+        dest.child("oci-layout")
+            .assert(predicate::str::contains("imageLayoutVersion"));
+        let blob_path = |digest: &serde_json::Value| {
+            let digest = digest.as_str().unwrap().replacen(':', "/", 1);
+            dest.child("blobs").child(digest).path().to_owned()
+        };
+        let index: serde_json::Value =
+            serde_json::from_slice(&fs::read(dest.child("index.json").path())?)?;
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&fs::read(blob_path(&index["manifests"][0]["digest"]))?)?;
+        let image_config: serde_json::Value =
+            serde_json::from_slice(&fs::read(blob_path(&manifest["config"]["digest"]))?)?;
+        assert_eq!(image_config["config"]["Entrypoint"][0], "/bin/sh");
+        assert_eq!(
+            image_config["rootfs"]["diff_ids"][0],
+            manifest["layers"][0]["digest"]
+        );
+
+        let layer = fs::File::open(blob_path(&manifest["layers"][0]["digest"]))?;
+        let mut archive = tar::Archive::new(layer);
+        let mut found = false;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.path()?.ends_with("dir/text.txt") {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut content)?;
+                assert_eq!(content, "hello");
+                found = true;
+            }
+        }
+        assert!(found);
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_tar() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("x.txt");
+        src.write_str("hello")?;
+        let link = src_dir.child("y.txt");
+        unix::fs::symlink(src.path(), link.path())?;
+
+        let mut bundle = Bundle::new();
+        bundle.mkdir(BundlePath::new("dir/empty"));
+        bundle.add_file(BundlePath::new("dir/text.txt"), b"hello".to_vec());
+        bundle.add_file_from(BundlePath::new("a/b/c.txt"), link.path());
+        let archive = bundle.emit_tar(Vec::new(), None)?;
+
+        let mut entries = std::collections::HashMap::new();
+        for entry in tar::Archive::new(archive.as_slice()).entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            entries.insert(
+                entry.path()?.into_owned(),
+                (
+                    header.entry_type(),
+                    entry.link_name()?.map(|l| l.into_owned()),
+                ),
+            );
+        }
+
+        for dir in ["dir", "dir/empty", "a", "a/b"] {
+            assert_eq!(entries[Path::new(dir)].0, tar::EntryType::Directory);
+        }
+        assert_eq!(
+            entries[Path::new("a/b/c.txt")],
+            (tar::EntryType::Symlink, Some(src.path().to_owned()))
+        );
+        assert_eq!(
+            entries[&BundlePath::projection(&src).reify("")].0,
+            tar::EntryType::Regular
+        );
+        assert_eq!(
+            entries[Path::new("dir/text.txt")].0,
+            tar::EntryType::Regular
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_tar_parent_relative_link() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // bin/y.txt -> ../lib/x.txt
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("lib/x.txt");
+        src.write_str("hello")?;
+        let link = src_dir.child("bin/y.txt");
+        src_dir.child("bin").create_dir_all()?;
+        unix::fs::symlink("../lib/x.txt", link.path())?;
+
+        let mut bundle = Bundle::new();
+        bundle.add_file_from(BundlePath::new("a/y.txt"), link.path());
+        let archive = bundle.emit_tar(Vec::new(), None)?;
+
+        let mut found = false;
+        for entry in tar::Archive::new(archive.as_slice()).entries()? {
+            let mut entry = entry?;
+            if entry.path()? == BundlePath::projection(&src).reify("") {
+                let mut content = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut content)?;
+                assert_eq!(content, "hello");
+                found = true;
+            }
+        }
+        assert!(found);
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_tar_reproducible() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let src = assert_fs::NamedTempFile::new("x.txt")?;
+        src.write_str("hello")?;
+
+        let reproducible = Reproducible {
+            source_date_epoch: 1000,
+        };
+        let emit = |paths: &[&str]| {
+            let mut bundle = Bundle::new();
+            for path in paths {
+                bundle.add_file_from(BundlePath::new(path), src.path());
+            }
+            bundle.add_file(BundlePath::new("dir/text.txt"), b"hello".to_vec());
+            bundle.emit_tar(Vec::new(), Some(reproducible))
+        };
+        let archive1 = emit(&["a/x.txt", "b/x.txt", "c/x.txt"])?;
+        let archive2 = emit(&["c/x.txt", "a/x.txt", "b/x.txt"])?;
+        assert!(archive1 == archive2);
+
+        for entry in tar::Archive::new(archive1.as_slice()).entries()? {
+            let entry = entry?;
+            assert_eq!(entry.header().mtime()?, 1000);
+            assert_eq!(entry.header().uid()?, 0);
+            assert_eq!(entry.header().gid()?, 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_reproducible() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::MetadataExt;
+
+        let dest = assert_fs::TempDir::new()?;
+        let src = assert_fs::NamedTempFile::new("x.txt")?;
+        src.write_str("hello")?;
+
+        let mut bundle = Bundle::new();
+        bundle.add_file_from(BundlePath::new("a/x.txt"), src.path());
+        bundle.add_file(BundlePath::new("dir/text.txt"), b"hello".to_vec());
+        let mut emitter = DirectoryEmitter::new(dest.path()).reproducible(Some(Reproducible {
+            source_date_epoch: 1000,
+        }));
+        bundle.emit_with(&mut emitter)?;
+
+        for path in ["a", "a/x.txt", "dir", "dir/text.txt"] {
+            assert_eq!(fs::symlink_metadata(dest.child(path).path())?.mtime(), 1000);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_emit_permissions_reproducible() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("x.txt");
+        src.write_str("hello")?;
+        fs::set_permissions(src_dir.path(), fs::Permissions::from_mode(0o750))?;
+        let reproducible = Some(Reproducible {
+            source_date_epoch: 1000,
+        });
+        let origin = Origin::new(Action::Test);
+
+        // the directory is copied after an entry in it, which has synthesized the directory
+        let emit = |emitter: &mut dyn Emitter| -> Result<()> {
+            emitter.copy(BundlePath::new("d/x.txt"), src.path(), &origin)?;
+            emitter.copy_dir(BundlePath::new("d"), src_dir.path(), &origin)?;
+            emitter.write(BundlePath::new("w/text.txt"), b"hello", &origin)?;
+            emitter.finalize()
+        };
+
+        let dest = assert_fs::TempDir::new()?;
+        let mut emitter = DirectoryEmitter::new(dest.path()).reproducible(reproducible);
+        emit(&mut emitter)?;
+        let mode = |path: &str| -> std::io::Result<u32> {
+            Ok(fs::metadata(dest.child(path).path())?.permissions().mode() & 0o7777)
+        };
+        assert_eq!(mode("d")?, 0o750);
+        assert_eq!(mode("w")?, 0o755);
+        assert_eq!(mode("w/text.txt")?, 0o644);
+
+        let mut emitter = TarEmitter::new(Vec::new()).reproducible(reproducible);
+        emit(&mut emitter)?;
+        let archive = emitter.finish()?;
+        let mut modes = std::collections::HashMap::new();
+        for entry in tar::Archive::new(archive.as_slice()).entries()? {
+            let entry = entry?;
+            // later entries override earlier ones on extraction
+            modes.insert(entry.path()?.into_owned(), entry.header().mode()?);
+        }
+        assert_eq!(modes[Path::new("d")], 0o750);
+        assert_eq!(modes[Path::new("w")], 0o755);
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("x.txt");
+        src.write_str("hello")?;
+        let link = src_dir.child("y.txt");
+        unix::fs::symlink(src.path(), link.path())?;
+
+        let mut bundle = Bundle::new();
+        bundle.set_origin(Action::IncludeGlob);
+        bundle.add_required_by(link.path(), "/bin/exe");
+        let mut emitter = ManifestEmitter::new();
+        bundle.emit_with(&mut emitter)?;
+
+        let manifest = emitter.to_json();
+        let entries = manifest["entries"].as_array().unwrap();
+        let find = |path: &Path| {
+            entries
+                .iter()
+                .find(|e| e["path"] == path.to_string_lossy().as_ref())
+                .unwrap()
+        };
+
+        let link_entry = find(link.path());
+        assert_eq!(link_entry["kind"], "symlink");
+        assert_eq!(link_entry["link"], src.path().to_string_lossy().as_ref());
+        assert_eq!(link_entry["reason"]["action"], "include_glob");
+        assert_eq!(link_entry["reason"]["required_by"], json!(["/bin/exe"]));
+
+        let src_entry = find(src.path());
+        assert_eq!(src_entry["kind"], "file");
+        assert_eq!(src_entry["size"], 5);
+        assert_eq!(
+            src_entry["sha256"],
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(src_entry["reason"]["action"], "sync_copy");
+        assert_eq!(
+            src_entry["reason"]["required_by"],
+            json!([link.path().to_string_lossy()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_several_origins() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("x.txt");
+        src.write_str("hello")?;
+        let link = src_dir.child("y.txt");
+        unix::fs::symlink(src.path(), link.path())?;
+
+        let mut bundle = Bundle::new();
+        bundle.set_origin(Action::BundleSharedObjectDependencies);
+        bundle.add_required_by(src.path(), "/bin/a");
+        bundle.add_required_by(src.path(), "/bin/b");
+        bundle.set_origin(Action::ScanDlopenCandidates);
+        bundle.add_required_by(src.path(), "/bin/c");
+        bundle.set_origin(Action::IncludeGlob);
+        bundle.add(link.path());
+        let mut emitter = ManifestEmitter::new();
+        bundle.emit_with(&mut emitter)?;
+
+        // neither the action of lower precedence nor the implicit link target replaces the reason
+        let manifest = emitter.to_json();
+        let entry = manifest["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["path"] == src.path().to_string_lossy().as_ref())
+            .unwrap();
+        assert_eq!(
+            entry["reason"]["action"],
+            "bundle_shared_object_dependencies"
+        );
+        assert_eq!(entry["reason"]["required_by"], json!(["/bin/a", "/bin/b"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let src_dir = assert_fs::TempDir::new()?;
+        let src = src_dir.child("x.txt");
+        src.write_str("hello")?;
+        let link1 = src_dir.child("y.txt");
+        unix::fs::symlink(src.path(), link1.path())?;
+        let link2 = src_dir.child("z.txt");
+        unix::fs::symlink(link1.path(), link2.path())?;
+
+        let mut bundle = Bundle::new();
+        bundle.add(link2.path());
+        let mut emitter = ManifestEmitter::new();
+        bundle.emit_with(&mut emitter)?;
+
+        let tree = Tree::new(&emitter).to_string();
+        assert!(tree.contains("x.txt (5 B)"));
+        assert!(tree.contains(&format!(
+            "z.txt -> {} => {}",
+            link1.path().display(),
+            src.path().display()
+        )));
+        assert!(tree.ends_with("2 symlinks, 5 B total"));
+        Ok(())
+    }
 }
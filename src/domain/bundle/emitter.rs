@@ -0,0 +1,388 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::os::unix;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::base::Result;
+use crate::domain::bundle::Origin;
+use crate::domain::{BundlePath, BundlePathBuf};
+
+use nix::sys::stat::UtimensatFlags;
+use nix::sys::time::TimeSpec;
+use nix::unistd::{FchownatFlags, Gid, Uid};
+
+/// Settings to make emitted files byte-identical for identical inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct Reproducible {
+    /// Upper bound of modification times, in seconds since the Unix epoch.
+    pub source_date_epoch: u64,
+}
+
+impl Reproducible {
+    fn clamp_mtime(&self, mtime: u64) -> u64 {
+        mtime.min(self.source_date_epoch)
+    }
+}
+
+pub trait Emitter {
+    fn mkdir(&mut self, path: &BundlePath, origin: &Origin) -> Result<()>;
+
+    fn write(&mut self, path: &BundlePath, content: &[u8], origin: &Origin) -> Result<()>;
+
+    fn copy(&mut self, path: &BundlePath, from: &Path, origin: &Origin) -> Result<()>;
+
+    /// Creates a directory at `path` with the permissions of the directory `from`, without its contents.
+    fn copy_dir(&mut self, path: &BundlePath, from: &Path, origin: &Origin) -> Result<()>;
+
+    /// Creates a symlink at `path` pointing to `link`, which is copied from the symlink `from`.
+    fn symlink(
+        &mut self,
+        path: &BundlePath,
+        from: &Path,
+        link: &Path,
+        origin: &Origin,
+    ) -> Result<()>;
+
+    fn finalize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct DirectoryEmitter {
+    dest: PathBuf,
+    reproducible: Option<Reproducible>,
+    copied_mtimes: HashMap<PathBuf, u64>,
+    copied_dir_permissions: Vec<(PathBuf, fs::Permissions)>,
+}
+
+impl DirectoryEmitter {
+    pub fn new<P>(dest: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        debug_assert!(dest.as_ref().is_absolute());
+        DirectoryEmitter {
+            dest: dest.as_ref().to_owned(),
+            reproducible: None,
+            copied_mtimes: HashMap::new(),
+            copied_dir_permissions: Vec::new(),
+        }
+    }
+
+    pub fn reproducible(mut self, reproducible: Option<Reproducible>) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    fn normalize_tree(&self, reproducible: &Reproducible, path: &Path) -> Result<()> {
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path)? {
+                self.normalize_tree(reproducible, &entry?.path())?;
+            }
+        }
+
+        let mtime = match self.copied_mtimes.get(path) {
+            Some(mtime) => reproducible.clamp_mtime(*mtime),
+            None => reproducible.source_date_epoch,
+        };
+        let time = TimeSpec::new(mtime as _, 0);
+        nix::sys::stat::utimensat(None, path, &time, &time, UtimensatFlags::NoFollowSymlink)?;
+
+        // copied entries keep the permissions of their sources, and the others would depend on the umask
+        if !metadata.file_type().is_symlink() && !self.copied_mtimes.contains_key(path) {
+            let mode = if metadata.is_dir() { 0o755 } else { 0o644 };
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+
+        if Uid::effective().is_root() {
+            nix::unistd::fchownat(
+                None,
+                path,
+                Some(Uid::from_raw(0)),
+                Some(Gid::from_raw(0)),
+                FchownatFlags::NoFollowSymlink,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Emitter for DirectoryEmitter {
+    fn mkdir(&mut self, path: &BundlePath, _origin: &Origin) -> Result<()> {
+        let path = path.reify(&self.dest);
+        tracing::info!(path = %path.display(), "emit: mkdir");
+        fs::create_dir_all(path).map_err(Into::into)
+    }
+
+    fn write(&mut self, path: &BundlePath, content: &[u8], _origin: &Origin) -> Result<()> {
+        let path = path.reify(&self.dest);
+        tracing::info!(path = %path.display(), "emit: write");
+        create_parent_dir(&path)?;
+        fs::write(path, content).map_err(Into::into)
+    }
+
+    fn copy(&mut self, path: &BundlePath, from: &Path, _origin: &Origin) -> Result<()> {
+        let target = path.reify(&self.dest);
+        tracing::info!(from = %from.display(), target = %target.display(), "emit: copy");
+        create_parent_dir(&target)?;
+        fs::copy(from, &target)?;
+        if self.reproducible.is_some() {
+            let mtime = fs::metadata(from)?.mtime();
+            self.copied_mtimes.insert(target, mtime.max(0) as u64);
+        }
+        Ok(())
+    }
+
+    fn copy_dir(&mut self, path: &BundlePath, from: &Path, _origin: &Origin) -> Result<()> {
+        let target = path.reify(&self.dest);
+        tracing::info!(from = %from.display(), target = %target.display(), "emit: copy directory");
+        fs::create_dir_all(&target)?;
+        let metadata = fs::metadata(from)?;
+        if self.reproducible.is_some() {
+            self.copied_mtimes
+                .insert(target.clone(), metadata.mtime().max(0) as u64);
+        }
+        // permissions are applied in `finalize` so that read-only directories can be filled
+        self.copied_dir_permissions
+            .push((target, metadata.permissions()));
+        Ok(())
+    }
+
+    fn symlink(
+        &mut self,
+        path: &BundlePath,
+        _from: &Path,
+        link: &Path,
+        _origin: &Origin,
+    ) -> Result<()> {
+        let target = path.reify(&self.dest);
+        tracing::info!(
+            link = %link.display(),
+            target = %target.display(),
+            "emit: link",
+        );
+        create_parent_dir(&target)?;
+        match target.read_link() {
+            // the bundle may contain an entry that symlinks to `target`
+            Ok(target_link_dest) if target_link_dest == link => {
+                tracing::debug!(
+                    link = %link.display(),
+                    target = %target.display(),
+                    "emit: already linked, skipping",
+                );
+            }
+            _ => {
+                unix::fs::symlink(link, target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<()> {
+        for (path, permissions) in self.copied_dir_permissions.drain(..).rev() {
+            fs::set_permissions(path, permissions)?;
+        }
+
+        if let Some(reproducible) = &self.reproducible {
+            if !Uid::effective().is_root() {
+                tracing::warn!("emit: not running as root, file ownership is not normalized");
+            }
+            tracing::info!(
+                dest = %self.dest.display(),
+                source_date_epoch = reproducible.source_date_epoch,
+                "emit: normalizing metadata",
+            );
+            for entry in fs::read_dir(&self.dest)? {
+                self.normalize_tree(reproducible, &entry?.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn create_parent_dir<P>(path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    match path.as_ref().parent() {
+        Some(parent) if !parent.exists() => fs::create_dir_all(parent).map_err(Into::into),
+        _ => Ok(()),
+    }
+}
+
+pub struct TarEmitter<W: Write> {
+    builder: tar::Builder<W>,
+    emitted: HashSet<BundlePathBuf>,
+    /// Parent directories appended with the default permissions, before their own entries if any.
+    synthesized_dirs: HashSet<BundlePathBuf>,
+    reproducible: Option<Reproducible>,
+    now: u64,
+}
+
+impl<W> TarEmitter<W>
+where
+    W: Write,
+{
+    pub fn new(writer: W) -> Self {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        TarEmitter {
+            builder: tar::Builder::new(writer),
+            emitted: HashSet::new(),
+            synthesized_dirs: HashSet::new(),
+            reproducible: None,
+            now,
+        }
+    }
+
+    pub fn reproducible(mut self, reproducible: Option<Reproducible>) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    fn new_header(&self, entry_type: tar::EntryType, mode: u32) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(match &self.reproducible {
+            Some(reproducible) => reproducible.source_date_epoch,
+            None => self.now,
+        });
+        header
+    }
+
+    fn metadata_header(&self, metadata: &fs::Metadata) -> Result<tar::Header> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(metadata);
+        // the mode from the metadata includes the file type bits
+        header.set_mode(metadata.permissions().mode() & 0o7777);
+        if let Some(reproducible) = &self.reproducible {
+            header.set_mtime(reproducible.clamp_mtime(header.mtime()?));
+            header.set_uid(0);
+            header.set_gid(0);
+        }
+        Ok(header)
+    }
+
+    pub fn finish(self) -> Result<W> {
+        self.builder.into_inner().map_err(Into::into)
+    }
+
+    fn append_parent_dirs(&mut self, path: &BundlePath) -> Result<()> {
+        let ancestors: Vec<_> = Path::new(path.as_os_str())
+            .ancestors()
+            .skip(1)
+            .filter(|p| !p.as_os_str().is_empty())
+            .collect();
+        for dir in ancestors.into_iter().rev() {
+            let dir = BundlePath::new(dir);
+            if !self.emitted.contains(dir) {
+                self.synthesized_dirs.insert(dir.to_owned());
+            }
+            self.append_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    fn append_dir(&mut self, path: &BundlePath) -> Result<()> {
+        if self.emitted.contains(path) {
+            return Ok(());
+        }
+
+        let mut header = self.new_header(tar::EntryType::Directory, 0o755);
+        self.builder
+            .append_data(&mut header, path.as_os_str(), std::io::empty())?;
+        self.emitted.insert(path.to_owned());
+        Ok(())
+    }
+
+    fn mark_emitted(&mut self, path: &BundlePath) -> bool {
+        if self.emitted.contains(path) {
+            tracing::debug!(path = %path.display(), "emit: already emitted, skipping");
+            return false;
+        }
+        self.emitted.insert(path.to_owned());
+        true
+    }
+}
+
+impl<W> Emitter for TarEmitter<W>
+where
+    W: Write,
+{
+    fn mkdir(&mut self, path: &BundlePath, _origin: &Origin) -> Result<()> {
+        tracing::info!(path = %path.display(), "emit: tar: mkdir");
+        self.append_parent_dirs(path)?;
+        self.append_dir(path)
+    }
+
+    fn write(&mut self, path: &BundlePath, content: &[u8], _origin: &Origin) -> Result<()> {
+        tracing::info!(path = %path.display(), "emit: tar: write");
+        self.append_parent_dirs(path)?;
+        if !self.mark_emitted(path) {
+            return Ok(());
+        }
+
+        let mut header = self.new_header(tar::EntryType::Regular, 0o644);
+        header.set_size(content.len() as u64);
+        self.builder
+            .append_data(&mut header, path.as_os_str(), content)
+            .map_err(Into::into)
+    }
+
+    fn copy(&mut self, path: &BundlePath, from: &Path, _origin: &Origin) -> Result<()> {
+        tracing::info!(from = %from.display(), path = %path.display(), "emit: tar: copy");
+        self.append_parent_dirs(path)?;
+        if !self.mark_emitted(path) {
+            return Ok(());
+        }
+
+        let file = fs::File::open(from)?;
+        let mut header = self.metadata_header(&file.metadata()?)?;
+        self.builder
+            .append_data(&mut header, path.as_os_str(), file)
+            .map_err(Into::into)
+    }
+
+    fn copy_dir(&mut self, path: &BundlePath, from: &Path, _origin: &Origin) -> Result<()> {
+        tracing::info!(from = %from.display(), path = %path.display(), "emit: tar: copy directory");
+        self.append_parent_dirs(path)?;
+        // a directory synthesized as a parent of earlier entries is appended again with the metadata of
+        // `from`, which overrides the earlier entry on extraction
+        if !self.synthesized_dirs.remove(path) && !self.mark_emitted(path) {
+            return Ok(());
+        }
+
+        let mut header = self.metadata_header(&fs::metadata(from)?)?;
+        self.builder
+            .append_data(&mut header, path.as_os_str(), std::io::empty())
+            .map_err(Into::into)
+    }
+
+    fn symlink(
+        &mut self,
+        path: &BundlePath,
+        _from: &Path,
+        link: &Path,
+        _origin: &Origin,
+    ) -> Result<()> {
+        tracing::info!(link = %link.display(), path = %path.display(), "emit: tar: link");
+        self.append_parent_dirs(path)?;
+        if !self.mark_emitted(path) {
+            return Ok(());
+        }
+
+        let mut header = self.new_header(tar::EntryType::Symlink, 0o777);
+        self.builder
+            .append_link(&mut header, path.as_os_str(), link)
+            .map_err(Into::into)
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::base::Result;
+use crate::domain::bundle::{Emitter, Origin};
+use crate::domain::root::MAX_SYMLINK_FOLLOWS;
+use crate::domain::{BundlePath, BundlePathBuf};
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestEntry {
+    pub kind: EntryKind,
+    pub host_path: Option<PathBuf>,
+    pub link: Option<PathBuf>,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub origin: Origin,
+}
+
+impl ManifestEntry {
+    fn new(kind: EntryKind, origin: &Origin) -> Self {
+        ManifestEntry {
+            kind,
+            host_path: None,
+            link: None,
+            size: None,
+            sha256: None,
+            origin: origin.clone(),
+        }
+    }
+
+    fn to_json(&self, path: &BundlePath) -> serde_json::Value {
+        let (kind, source) = match self.kind {
+            EntryKind::Directory if self.host_path.is_none() => ("directory", "new_directory"),
+            EntryKind::Directory => ("directory", "copy_from"),
+            EntryKind::File if self.host_path.is_none() => ("file", "new_file"),
+            EntryKind::File => ("file", "copy_from"),
+            EntryKind::Symlink => ("symlink", "copy_from"),
+        };
+        let required_by: Vec<_> = self
+            .origin
+            .required_by()
+            .map(|p| p.to_string_lossy())
+            .collect();
+        let mut entry = json!({
+            "path": format!("/{}", path.to_str_lossy()),
+            "kind": kind,
+            "source": source,
+            "reason": {
+                "action": self.origin.action().as_str(),
+                "required_by": required_by,
+            },
+        });
+        if let Some(host_path) = &self.host_path {
+            entry["host_path"] = json!(host_path.to_string_lossy());
+        }
+        if let Some(link) = &self.link {
+            entry["link"] = json!(link.to_string_lossy());
+        }
+        if let Some(size) = self.size {
+            entry["size"] = json!(size);
+        }
+        if let Some(sha256) = &self.sha256 {
+            entry["sha256"] = json!(sha256);
+        }
+        entry
+    }
+}
+
+/// Collects what would be emitted into a machine-readable manifest without writing any file.
+#[derive(Default)]
+pub struct ManifestEmitter {
+    entries: BTreeMap<BundlePathBuf, ManifestEntry>,
+}
+
+impl ManifestEmitter {
+    pub fn new() -> Self {
+        ManifestEmitter {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&BundlePath, &ManifestEntry)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_ref(), entry))
+    }
+
+    /// Follows symlinks in the bundle and returns the path that `path` finally refers to.
+    /// Returns `None` if the link is dangling or too many links are encountered.
+    pub fn resolve(&self, path: &BundlePath) -> Option<BundlePathBuf> {
+        let mut pending: Vec<_> = Path::new(path.as_os_str())
+            .components()
+            .map(|c| c.as_os_str().to_owned())
+            .collect();
+        pending.reverse();
+
+        let mut resolved = PathBuf::new();
+        let mut followed = 0;
+        while let Some(component) = pending.pop() {
+            match Path::new(&component).components().next() {
+                Some(Component::ParentDir) => {
+                    resolved.pop();
+                }
+                Some(Component::Normal(name)) => {
+                    let candidate = resolved.join(name);
+                    match self.entries.get(BundlePath::new(&candidate)) {
+                        Some(ManifestEntry {
+                            kind: EntryKind::Symlink,
+                            link: Some(link),
+                            ..
+                        }) => {
+                            followed += 1;
+                            if followed > MAX_SYMLINK_FOLLOWS {
+                                return None;
+                            }
+                            if link.is_absolute() {
+                                resolved = PathBuf::new();
+                            }
+                            pending.extend(link.components().rev().filter_map(|c| match c {
+                                Component::RootDir | Component::CurDir => None,
+                                c => Some(c.as_os_str().to_owned()),
+                            }));
+                        }
+                        _ => resolved = candidate,
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let resolved = BundlePath::new(&resolved);
+        let exists = self.entries.contains_key(resolved)
+            || self
+                .entries
+                .keys()
+                .any(|p| Path::new(p.as_os_str()).starts_with(resolved.as_os_str()));
+        exists.then(|| resolved.to_owned())
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let entries: Vec<_> = self
+            .entries()
+            .map(|(path, entry)| entry.to_json(path))
+            .collect();
+        json!({ "entries": entries })
+    }
+
+    fn record(&mut self, path: &BundlePath, mut entry: ManifestEntry) {
+        tracing::debug!(path = %path.display(), origin = ?entry.origin, "manifest: record");
+        // a path can be emitted more than once, e.g. as an entry and as the target of a link
+        if let Some(recorded) = self.entries.get(path) {
+            entry.origin.merge(&recorded.origin);
+        }
+        self.entries.insert(path.to_owned(), entry);
+    }
+}
+
+impl Emitter for ManifestEmitter {
+    fn mkdir(&mut self, path: &BundlePath, origin: &Origin) -> Result<()> {
+        self.record(path, ManifestEntry::new(EntryKind::Directory, origin));
+        Ok(())
+    }
+
+    fn write(&mut self, path: &BundlePath, content: &[u8], origin: &Origin) -> Result<()> {
+        let mut entry = ManifestEntry::new(EntryKind::File, origin);
+        entry.size = Some(content.len() as u64);
+        entry.sha256 = Some(format!("{:x}", Sha256::digest(content)));
+        self.record(path, entry);
+        Ok(())
+    }
+
+    fn copy(&mut self, path: &BundlePath, from: &Path, origin: &Origin) -> Result<()> {
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut fs::File::open(from)?, &mut hasher)?;
+
+        let mut entry = ManifestEntry::new(EntryKind::File, origin);
+        entry.host_path = Some(from.to_owned());
+        entry.size = Some(size);
+        entry.sha256 = Some(format!("{:x}", hasher.finalize()));
+        self.record(path, entry);
+        Ok(())
+    }
+
+    fn copy_dir(&mut self, path: &BundlePath, from: &Path, origin: &Origin) -> Result<()> {
+        let mut entry = ManifestEntry::new(EntryKind::Directory, origin);
+        entry.host_path = Some(from.to_owned());
+        self.record(path, entry);
+        Ok(())
+    }
+
+    fn symlink(
+        &mut self,
+        path: &BundlePath,
+        from: &Path,
+        link: &Path,
+        origin: &Origin,
+    ) -> Result<()> {
+        let mut entry = ManifestEntry::new(EntryKind::Symlink, origin);
+        entry.host_path = Some(from.to_owned());
+        entry.link = Some(link.to_owned());
+        self.record(path, entry);
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::Path;
+
+use crate::domain::bundle::{EntryKind, ManifestEmitter, ManifestEntry};
+use crate::domain::BundlePathBuf;
+
+#[derive(Default)]
+struct Node<'a> {
+    entry: Option<&'a ManifestEntry>,
+    resolved: Option<BundlePathBuf>,
+    children: BTreeMap<OsString, Node<'a>>,
+}
+
+/// Human-readable tree view of what would be emitted.
+pub struct Tree<'a> {
+    root: Node<'a>,
+    total_size: u64,
+    files: usize,
+    directories: usize,
+    symlinks: usize,
+}
+
+impl<'a> Tree<'a> {
+    pub fn new(manifest: &'a ManifestEmitter) -> Self {
+        let mut tree = Tree {
+            root: Node::default(),
+            total_size: 0,
+            files: 0,
+            directories: 0,
+            symlinks: 0,
+        };
+
+        for (path, entry) in manifest.entries() {
+            let mut node = &mut tree.root;
+            for component in Path::new(path.as_os_str()).components() {
+                node = node
+                    .children
+                    .entry(component.as_os_str().to_owned())
+                    .or_default();
+            }
+            node.entry = Some(entry);
+
+            match entry.kind {
+                EntryKind::Directory => {}
+                EntryKind::File => {
+                    tree.files += 1;
+                    tree.total_size += entry.size.unwrap_or(0);
+                }
+                EntryKind::Symlink => {
+                    tree.symlinks += 1;
+                    node.resolved = manifest.resolve(path);
+                }
+            }
+        }
+        tree.directories = count_directories(&tree.root);
+        tree
+    }
+
+    fn fmt_children(&self, f: &mut fmt::Formatter, node: &Node, prefix: &str) -> fmt::Result {
+        let count = node.children.len();
+        for (idx, (name, child)) in node.children.iter().enumerate() {
+            let last = idx + 1 == count;
+            let branch = if last { "└── " } else { "├── " };
+            write!(f, "{}{}{}", prefix, branch, name.to_string_lossy())?;
+            match child.entry {
+                Some(ManifestEntry {
+                    kind: EntryKind::File,
+                    size,
+                    ..
+                }) => write!(f, " ({})", format_size(size.unwrap_or(0)))?,
+                Some(ManifestEntry {
+                    kind: EntryKind::Symlink,
+                    link: Some(link),
+                    ..
+                }) => {
+                    write!(f, " -> {}", link.display())?;
+                    match &child.resolved {
+                        Some(resolved) => write!(f, " => /{}", resolved.to_str_lossy())?,
+                        None => write!(f, " => (dangling)")?,
+                    }
+                }
+                _ => write!(f, "/")?,
+            }
+            writeln!(f)?;
+
+            let extension = if last { "    " } else { "│   " };
+            self.fmt_children(f, child, &format!("{}{}", prefix, extension))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Tree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "/")?;
+        self.fmt_children(f, &self.root, "")?;
+        writeln!(f)?;
+        write!(
+            f,
+            "{} directories, {} files, {} symlinks, {} total",
+            self.directories,
+            self.files,
+            self.symlinks,
+            format_size(self.total_size)
+        )
+    }
+}
+
+fn count_directories(node: &Node) -> usize {
+    node.children
+        .values()
+        .filter(|child| !matches!(child.entry, Some(e) if e.kind != EntryKind::Directory))
+        .map(|child| 1 + count_directories(child))
+        .sum()
+}
+
+pub(crate) fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -4,6 +4,7 @@ use std::fmt;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BundlePath {
     inner: OsStr,
 }
@@ -30,7 +31,18 @@ impl BundlePath {
         }
     }
 
-    pub fn to_str_lossy(&self) -> Cow<str> {
+    pub fn join<P>(&self, path: P) -> BundlePathBuf
+    where
+        P: AsRef<Path>,
+    {
+        BundlePath::projection(&Path::new(&self.inner).join(path)).to_owned()
+    }
+
+    pub fn as_os_str(&self) -> &OsStr {
+        &self.inner
+    }
+
+    pub fn to_str_lossy(&self) -> Cow<'_, str> {
         self.inner.to_string_lossy()
     }
 
@@ -60,7 +72,7 @@ impl AsRef<BundlePath> for BundlePath {
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct BundlePathBuf {
     inner: OsString,
 }
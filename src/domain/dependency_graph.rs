@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::base::Result;
+use crate::domain::bundle::format_size;
+use crate::domain::{Executable, ResolverBackend};
+
+use serde_json::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeKind {
+    Executable,
+    Interpreter,
+    SharedObject,
+}
+
+impl NodeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Executable => "executable",
+            NodeKind::Interpreter => "interpreter",
+            NodeKind::SharedObject => "shared_object",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    size: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Edge {
+    from: PathBuf,
+    to: PathBuf,
+    /// The name in `DT_NEEDED`, or the path in `PT_INTERP`.
+    name: String,
+    /// How `name` is resolved to `to`, either a `LookupRule` or `interpreter`.
+    rule: String,
+}
+
+/// Objects in the dependency closure of executables, and edges from each object to the objects it requires.
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    nodes: BTreeMap<PathBuf, Node>,
+    edges: Vec<Edge>,
+}
+
+impl DependencyGraph {
+    pub fn new() -> Self {
+        DependencyGraph::default()
+    }
+
+    fn add_node(&mut self, path: &Path, kind: NodeKind, size: Option<u64>) {
+        // the interpreter may be required as a shared object as well, like libc.so of musl
+        self.nodes
+            .entry(path.to_owned())
+            .or_insert(Node { kind, size });
+    }
+
+    fn add_edge(&mut self, edge: Edge) {
+        if !self.edges.contains(&edge) {
+            self.edges.push(edge);
+        }
+    }
+
+    /// Adds `exe` and its dependency closure resolved with `backend`.
+    pub fn add_executable(&mut self, exe: &Executable, backend: &ResolverBackend) -> Result<()> {
+        let root = exe.root();
+        let size_of = |path: &Path| {
+            fs::metadata(root.real_host_path(path))
+                .ok()
+                .map(|m| m.len())
+        };
+
+        let exe_size = if exe.is_temporary() {
+            fs::metadata(exe.path()).ok().map(|m| m.len())
+        } else {
+            size_of(exe.path())
+        };
+        self.add_node(exe.path(), NodeKind::Executable, exe_size);
+
+        if let Some(interp) = exe.interpreter() {
+            self.add_node(interp, NodeKind::Interpreter, size_of(interp));
+            self.add_edge(Edge {
+                from: exe.path().to_owned(),
+                to: interp.clone(),
+                name: interp.display().to_string(),
+                rule: "interpreter".to_owned(),
+            });
+        }
+
+        for dep in exe.dependency_edges(backend)? {
+            self.add_node(&dep.path, NodeKind::SharedObject, size_of(&dep.path));
+            self.add_edge(Edge {
+                from: dep.required_by,
+                to: dep.path,
+                name: dep.name,
+                rule: dep.rule.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => {
+                let mut content = serde_json::to_string_pretty(&self.to_json())
+                    .expect("serializing JSON values never fails");
+                content.push('\n');
+                content
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|(path, node)| {
+                json!({
+                    "path": path.to_string_lossy(),
+                    "kind": node.kind.as_str(),
+                    "size": node.size,
+                })
+            })
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|edge| {
+                json!({
+                    "from": edge.from.to_string_lossy(),
+                    "to": edge.to.to_string_lossy(),
+                    "name": edge.name,
+                    "rule": edge.rule,
+                })
+            })
+            .collect();
+        json!({ "nodes": nodes, "edges": edges })
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        // unwraps are ok because writing to String never fails
+        writeln!(dot, "digraph dependencies {{").unwrap();
+        writeln!(dot, "  rankdir=LR;").unwrap();
+        writeln!(dot, "  node [shape=box];").unwrap();
+        for (path, node) in &self.nodes {
+            let path = path.to_string_lossy();
+            let label = match node.size {
+                Some(size) => format!("{}\n{}", path, format_size(size)),
+                None => path.to_string(),
+            };
+            let shape = match node.kind {
+                NodeKind::Executable => ", shape=doubleoctagon",
+                NodeKind::Interpreter => ", shape=hexagon",
+                NodeKind::SharedObject => "",
+            };
+            writeln!(
+                dot,
+                "  {} [label={}{}];",
+                quote(&path),
+                quote(&label),
+                shape
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(
+                dot,
+                "  {} -> {} [label={}];",
+                quote(&edge.from.to_string_lossy()),
+                quote(&edge.to.to_string_lossy()),
+                quote(&format!("{}\n({})", edge.name, edge.rule)),
+            )
+            .unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+/// Quotes `s` as a DOT string, writing newlines as `\n` line breaks in labels.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Hwcaps, Root};
+    use std::env;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("/lib/libc.so.6"), "\"/lib/libc.so.6\"");
+        assert_eq!(quote("a\"b\\c\nd"), "\"a\\\"b\\\\c\\nd\"");
+    }
+
+    #[test]
+    fn test_add_executable() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let exe = Executable::load(env::current_exe()?)?;
+        let backend = ResolverBackend::native(&Root::host(), Hwcaps::host())?;
+        let mut graph = DependencyGraph::new();
+        graph.add_executable(&exe, &backend)?;
+
+        let value = graph.to_json();
+        let edges = value["edges"].as_array().unwrap();
+        let interp = exe.interpreter().unwrap().to_string_lossy();
+        assert!(edges
+            .iter()
+            .any(|e| e["to"] == *interp && e["rule"] == "interpreter"));
+        // libc.so.6 may be required by dependencies first, but the edge from the test binary is recorded as well
+        let exe_path = exe.path().to_string_lossy();
+        let libc = edges
+            .iter()
+            .find(|e| e["name"] == "libc.so.6" && e["from"] == *exe_path)
+            .expect("libc.so.6 is required");
+        assert!(value["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|n| n["path"] == libc["to"] && n["kind"] == "shared_object"));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains("[label=\"libc.so.6\\n("));
+        Ok(())
+    }
+}
@@ -1,21 +1,44 @@
-use std::collections::HashSet;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
+use std::io;
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{env, fs};
 
 use crate::base::log::CommandLogExt;
 use crate::base::{Error, Result};
+use crate::domain::Root;
 
-use goblin::elf::dynamic::{Dyn, DT_RPATH, DT_RUNPATH};
-use goblin::elf::Elf;
+use goblin::elf::dynamic::Dyn;
 use goblin::strtab::Strtab;
 use tempfile::{NamedTempFile, TempPath};
 
+mod cache;
+mod dlopen_scan;
+mod hwcaps;
+mod ld_config;
+mod ld_so_cache;
+mod musl;
+mod object;
 mod resolver;
 mod search_paths;
-use search_paths::SearchPaths;
+mod shared_object;
+mod symbols;
+mod target;
+pub use cache::DependencyCache;
+use cache::ObjectInfo;
+pub use dlopen_scan::DlopenCandidate;
+pub use hwcaps::Hwcaps;
+pub use ld_config::LdConfig;
+pub use ld_so_cache::{CacheEntry, Hwcap, LdSoCache};
+pub use musl::MuslConfig;
+pub use object::{ElfFile, ElfObject};
+pub use resolver::{LookupRule, ResolverBackend};
+use search_paths::{SearchPaths, Tokens};
+pub use shared_object::SharedObject;
+pub use symbols::{SymbolRef, SymbolVersion, Symbols};
+pub use target::ElfTarget;
 
 #[derive(Debug)]
 enum ExecutableLocation {
@@ -32,63 +55,70 @@ impl AsRef<Path> for ExecutableLocation {
     }
 }
 
+/// A shared object resolved from `DT_NEEDED` entries of `required_by`.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub name: String,
+    pub path: PathBuf,
+    pub required_by: PathBuf,
+    /// The search rule that found `path`.
+    pub rule: LookupRule,
+    /// The baseline library in the parent search directory, when `path` is a variant in a hwcaps
+    /// subdirectory. It is bundled as well so that the bundle also runs on CPUs without the level.
+    pub baseline: Option<PathBuf>,
+}
+
+/// An executable, which is run with its interpreter.
 #[derive(Debug)]
 pub struct Executable {
-    location: ExecutableLocation,
+    object: ElfObject,
     name: String,
     interpreter: Option<PathBuf>,
-    libraries: Vec<String>,
-    search_paths: SearchPaths,
 }
 
 impl Executable {
     fn load_impl(
         location: ExecutableLocation,
         name: String,
-        propagated_rpaths: Option<Vec<PathBuf>>,
+        secure: bool,
+        root: Root,
+        cache: &DependencyCache,
     ) -> Result<Self> {
-        tracing::debug!(location = %location.as_ref().display(), "exe: loading");
-        let buffer = fs::read(location.as_ref())?;
-        let elf = Elf::parse(buffer.as_slice())?;
-        let interpreter = if let Some(interp) = elf.interpreter {
-            Some(interp.into())
-        } else {
-            let interp = default_interpreter(&location)?;
-            if let Some(interp) = &interp {
-                tracing::debug!(interp = %interp.display(), "exe: using default interpreter");
-            } else {
-                tracing::warn!(
-                    "exe: interpreter could not be found. static or compressed executable?"
-                );
-            }
-            interp
-        };
-        let mut search_paths = collect_paths(&elf, location.as_ref())?;
-        let libraries = elf.libraries.into_iter().map(ToOwned::to_owned).collect();
-
-        if let Some(paths) = propagated_rpaths {
-            search_paths.append_rpath(paths);
-        }
-
-        let exe = Executable {
-            location,
+        let (object, interp) = ElfObject::load(location, None, secure, true, root, cache)?;
+        // static executables do not need the interpreter
+        let interpreter = interp.or_else(|| object.default_interpreter());
+        Ok(Executable {
+            object,
             name,
             interpreter,
-            libraries,
-            search_paths,
-        };
+        })
+    }
 
-        tracing::debug!(exe = ?exe, "exe: loaded");
-        Ok(exe)
+    pub fn load<P>(exe_path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Executable::load_with_cache(exe_path.as_ref(), &Root::host(), &DependencyCache::new())
     }
 
-    fn load_with_rpaths<P>(exe_path: P, propagated_rpaths: Option<Vec<PathBuf>>) -> Result<Self>
+    /// Loads the executable at `exe_path` in `root`, resolving its dependencies in `root` as well.
+    /// The object is parsed through the cache of `backend`, which later resolves its dependencies.
+    pub fn load_in<P>(exe_path: P, root: &Root, backend: &ResolverBackend) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let path = exe_path.as_ref();
+        Executable::load_with_cache(exe_path.as_ref(), root, backend.cache())
+    }
+
+    fn load_with_cache(path: &Path, root: &Root, cache: &DependencyCache) -> Result<Self> {
+        let secure = is_secure_execution(&root.real_host_path(path))?;
+        if secure {
+            tracing::info!(
+                path = %path.display(),
+                "exe: resolving shared objects in secure-execution mode",
+            );
+        }
 
-        let location = ExecutableLocation::Fixed(path.to_owned());
         let file_name = path
             .file_name()
             .ok_or_else(|| Error::InvalidObjectPath(path.to_owned()))?;
@@ -96,18 +126,27 @@ impl Executable {
             .to_str()
             .ok_or_else(|| Error::PathEncoding(file_name.to_os_string()))?
             .to_string();
-        Executable::load_impl(location, file_name_str, propagated_rpaths)
+        Executable::load_impl(
+            ExecutableLocation::Fixed(path.to_owned()),
+            file_name_str,
+            secure,
+            root.clone(),
+            cache,
+        )
     }
 
-    pub fn load<P>(exe_path: P) -> Result<Self>
-    where
-        P: AsRef<Path>,
-    {
-        Executable::load_with_rpaths(exe_path, None)
+    pub fn object(&self) -> &ElfObject {
+        &self.object
     }
 
+    /// The path of the executable in the root, or on the host if it is a temporary file.
     pub fn path(&self) -> &Path {
-        self.location.as_ref()
+        self.object.path()
+    }
+
+    /// Whether the executable is a temporary file on the host, such as a compressed one.
+    pub fn is_temporary(&self) -> bool {
+        matches!(self.object.location(), ExecutableLocation::Temporary(_))
     }
 
     pub fn name(&self) -> &String {
@@ -118,72 +157,60 @@ impl Executable {
         self.interpreter.as_ref()
     }
 
-    fn dynamic_libraries_impl<P>(
-        &self,
-        resolving_libraries: &mut HashSet<String>,
-        cc_path: P,
-        is_noload: bool,
-    ) -> Result<Vec<PathBuf>>
-    where
-        P: AsRef<Path>,
-    {
-        let interpreter = if let Some(interp) = &self.interpreter {
-            interp
-        } else {
-            tracing::warn!(
-                "exe: requesting dynamic libraries of the executable without the interpreter"
-            );
-            return Ok(Vec::new());
-        };
-
-        let resolver = if is_noload {
-            resolver::Resolver::new_noload(interpreter, &self.search_paths, cc_path.as_ref())?
-        } else {
-            resolver::Resolver::new(interpreter, &self.search_paths, cc_path.as_ref())?
-        };
-
-        let mut paths = Vec::new();
-        for lib in &self.libraries {
-            let path = resolver.lookup(lib)?;
-            tracing::debug!(
-                name = %lib,
-                path = %path.display(),
-                "exe: found shared object",
-            );
+    pub fn target(&self) -> ElfTarget {
+        self.object.target()
+    }
 
-            if !resolving_libraries.contains(lib) {
-                resolving_libraries.insert(lib.to_owned());
-                // TODO: cache once traversed
-                // TODO: deal with semantic inconsistency (Executable on shared object)
-                let mut children =
-                    Executable::load_with_rpaths(path.clone(), self.search_paths.rpath().cloned())?
-                        .dynamic_libraries_impl(resolving_libraries, cc_path.as_ref(), is_noload)?;
-
-                paths.push(path);
-                paths.append(&mut children);
-            }
-        }
+    /// The root filesystem the executable is analyzed in.
+    pub fn root(&self) -> &Root {
+        self.object.root()
+    }
 
-        Ok(paths)
+    /// Whether the executable runs in secure-execution mode, where ld.so ignores LD_LIBRARY_PATH and restricts $ORIGIN.
+    pub fn is_secure(&self) -> bool {
+        self.object.is_secure()
     }
 
-    pub fn dynamic_libraries<P>(&self, cc_path: P) -> Result<Vec<PathBuf>>
-    where
-        P: AsRef<Path>,
-    {
-        let mut resolving_libraries = HashSet::new();
-        self.dynamic_libraries_impl(&mut resolving_libraries, cc_path, false)
+    /// Whether the executable is loaded by the musl dynamic linker.
+    pub fn is_musl(&self) -> bool {
+        self.interpreter
+            .as_deref()
+            .and_then(musl::musl_arch)
+            .is_some()
     }
 
-    pub fn dynamic_libraries_noload<P>(&self, cc_path: P) -> Result<Vec<PathBuf>>
-    where
-        P: AsRef<Path>,
-    {
-        let mut resolving_libraries = HashSet::new();
-        self.dynamic_libraries_impl(&mut resolving_libraries, cc_path, true)
+    /// Looks up the shared object `name` in the same way as dlopen(3) called from this executable.
+    pub fn resolve(&self, name: &str, backend: &ResolverBackend) -> Result<PathBuf> {
+        let interpreter = self
+            .interpreter
+            .as_ref()
+            .ok_or(Error::InterpretorNotFound)?;
+        self.object.resolve(name, interpreter, backend)
     }
 
-    pub fn compressed<P, T, I>(&self, upx_path: P, upx_opts: I) -> Result<Executable>
+    pub fn dependencies(&self, backend: &ResolverBackend) -> Result<Vec<Dependency>> {
+        self.object
+            .dependencies(self.interpreter.as_deref(), backend, false)
+    }
+
+    /// Resolves every `DT_NEEDED` entry in the dependency closure, including names that are already
+    /// required by other objects, which are omitted in `dependencies`.
+    pub fn dependency_edges(&self, backend: &ResolverBackend) -> Result<Vec<Dependency>> {
+        self.object
+            .dependencies(self.interpreter.as_deref(), backend, true)
+    }
+
+    pub fn dynamic_libraries(&self, backend: &ResolverBackend) -> Result<Vec<PathBuf>> {
+        let deps = self.dependencies(backend)?;
+        Ok(deps.into_iter().map(|dep| dep.path).collect())
+    }
+
+    pub fn compressed<P, T, I>(
+        &self,
+        upx_path: P,
+        upx_opts: I,
+        backend: &ResolverBackend,
+    ) -> Result<Executable>
     where
         P: AsRef<Path>,
         I: IntoIterator<Item = T>,
@@ -200,7 +227,7 @@ impl Executable {
         let output = Command::new(upx_path.as_ref())
             .args(upx_opts)
             .arg("--no-progress")
-            .arg(self.path().canonicalize()?)
+            .arg(host_path_of(self.object.location(), self.root()).canonicalize()?)
             .arg("-o")
             .arg(&result_path)
             .output_with_log()?;
@@ -213,70 +240,82 @@ impl Executable {
         Executable::load_impl(
             ExecutableLocation::Temporary(result_path),
             self.name().clone(),
-            None,
+            self.is_secure(),
+            self.root().clone(),
+            backend.cache(),
         )
     }
 }
 
-fn default_interpreter<P>(exe: P) -> Result<Option<PathBuf>>
-where
-    P: AsRef<Path>,
-{
-    // from the source code of ldd(1); TODO: deal with hardcoded paths
-    let rtld_list = &[
-        "/usr/lib/ld-linux.so.2",
-        "/usr/lib64/ld-linux-x86-64.so.2",
-        "/usr/libx32/ld-linux-x32.so.2",
-        "/lib/ld-linux.so.2",
-        "/lib64/ld-linux-x86-64.so.2",
-        "/libx32/ld-linux-x32.so.2",
-    ];
-    for rtld in rtld_list {
-        let path = Path::new(rtld);
-        if !path.exists() {
-            continue;
-        }
-
-        let status = Command::new(rtld)
-            .arg("--verify")
-            .arg(exe.as_ref())
-            .status_with_log()?;
-        match status.code() {
-            Some(0) | Some(2) => return Ok(Some(rtld.into())),
-            _ => continue,
-        }
+/// Maps the location to the path on the host; temporary files are always on the host.
+fn host_path_of(location: &ExecutableLocation, root: &Root) -> PathBuf {
+    match location {
+        ExecutableLocation::Fixed(path) => root.real_host_path(path),
+        ExecutableLocation::Temporary(temp_path) => temp_path.to_path_buf(),
     }
+}
+
+/// Finds the dynamic linker for objects without `PT_INTERP`, such as shared objects.
+/// The candidates are derived from the target, so that nothing is executed to verify them.
+fn default_interpreter(target: ElfTarget, root: &Root) -> Option<PathBuf> {
+    let glibc = target.default_interpreters().iter().map(PathBuf::from);
+    let musl = target
+        .musl_arch()
+        .map(|arch| PathBuf::from(format!("/lib/ld-musl-{}.so.1", arch)));
+    glibc
+        .chain(musl)
+        .find(|rtld| root.real_host_path(rtld).exists())
+}
 
-    Ok(None)
+/// Tells whether the kernel would run the executable with AT_SECURE, that is, it is
+/// set-user-ID, set-group-ID, or has file capabilities.
+fn is_secure_execution(path: &Path) -> Result<bool> {
+    let mode = fs::metadata(path)?.permissions().mode();
+    let setuid = mode & 0o4000 != 0;
+    // set-group-ID without group execute permission means mandatory locking
+    let setgid = mode & 0o2000 != 0 && mode & 0o010 != 0;
+    Ok(setuid || setgid || has_file_capabilities(path)?)
 }
 
-fn collect_paths(elf: &Elf<'_>, executable_path: &Path) -> Result<SearchPaths> {
+fn has_file_capabilities(path: &Path) -> Result<bool> {
+    use nix::libc;
+    use std::ffi::CString;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::InvalidObjectPath(path.to_owned()))?;
+    // unwrap is ok because the name does not contain NUL
+    let name = CString::new("security.capability").unwrap();
+    // query the size only to see whether the attribute exists
+    let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+    if size >= 0 {
+        return Ok(true);
+    }
+    match nix::errno::Errno::last() {
+        nix::errno::Errno::ENODATA | nix::errno::Errno::ENOTSUP => Ok(false),
+        errno => Err(io::Error::from(errno).into()),
+    }
+}
+
+fn collect_paths(
+    info: &ObjectInfo,
+    executable_path: &Path,
+    secure: bool,
+    main: bool,
+    root: &Root,
+) -> SearchPaths {
     debug_assert!(executable_path.is_absolute());
     // unwrap is ok here because the path points to file and is absolute
     let origin = executable_path.parent().unwrap();
-    let mut paths = SearchPaths::new(origin.into())?;
+    let mut paths = SearchPaths::new(Tokens::new(origin.into(), info.target), secure, main);
 
-    if let Elf {
-        dynamic: Some(dynamic),
-        dynstrtab,
-        ..
-    } = elf
-    {
-        for d in &dynamic.dyns {
-            if d.d_tag == DT_RUNPATH {
-                paths.append_runpath(get_paths_in_strtab(d, dynstrtab)?);
-            } else if d.d_tag == DT_RPATH {
-                paths.append_rpath(get_paths_in_strtab(d, dynstrtab)?);
-            }
-        }
+    if let Some(runpath) = &info.runpath {
+        paths.append_runpath(runpath);
+    }
+    if let Some(rpath) = &info.rpath {
+        paths.append_rpath(rpath);
     }
 
-    if let Some(paths_str) = env::var_os("LD_LIBRARY_PATH") {
-        tracing::debug!(
-            value = %paths_str.to_string_lossy(),
-            "executable: got LD_LIBRARY_PATH",
-        );
-
+    if let Some(paths_str) = ld_library_path(env::var_os("LD_LIBRARY_PATH"), root) {
         paths.append_ld_library_path(
             paths_str
                 .into_vec()
@@ -285,13 +324,27 @@ fn collect_paths(elf: &Elf<'_>, executable_path: &Path) -> Result<SearchPaths> {
         );
     }
 
-    Ok(paths)
+    paths
 }
 
-fn get_paths_in_strtab(d: &Dyn, strtab: &Strtab<'_>) -> Result<Vec<PathBuf>> {
-    let content = get_content_in_strtab(d, strtab)?;
-    // assuming paths in DT_RPATH and DT_RUNPATH are separated by colons.
-    Ok(content.split(':').map(Into::into).collect())
+/// Filters `value` of LD_LIBRARY_PATH of the host, which only applies to executables in the host root
+/// because the directories in it are not paths in another root.
+fn ld_library_path(value: Option<OsString>, root: &Root) -> Option<OsString> {
+    let paths_str = value?;
+    if !root.is_host() {
+        tracing::info!(
+            value = %paths_str.to_string_lossy(),
+            root = %root.dir().display(),
+            "executable: ignoring LD_LIBRARY_PATH of the host in the root",
+        );
+        return None;
+    }
+
+    tracing::debug!(
+        value = %paths_str.to_string_lossy(),
+        "executable: got LD_LIBRARY_PATH",
+    );
+    Some(paths_str)
 }
 
 fn get_content_in_strtab(d: &Dyn, strtab: &Strtab<'_>) -> Result<String> {
@@ -304,3 +357,18 @@ fn get_content_in_strtab(d: &Dyn, strtab: &Strtab<'_>) -> Result<String> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ld_library_path_in_root() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let value = || Some(OsString::from("/opt/lib"));
+        assert_eq!(ld_library_path(value(), &Root::host()), value());
+        assert_eq!(ld_library_path(value(), &Root::new(dir.path())?), None);
+        assert_eq!(ld_library_path(None, &Root::host()), None);
+        Ok(())
+    }
+}
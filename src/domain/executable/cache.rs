@@ -0,0 +1,435 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::base::Result;
+use crate::domain::executable::{ElfTarget, LookupRule};
+
+use goblin::elf::dynamic::{DT_RPATH, DT_RUNPATH};
+use goblin::elf::Elf;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+// bump when the format or the meaning of entries changes
+const CACHE_VERSION: u64 = 1;
+
+/// The part of an ELF object needed to traverse its dependencies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub target: ElfTarget,
+    pub interpreter: Option<PathBuf>,
+    pub soname: Option<String>,
+    pub libraries: Vec<String>,
+    /// Unexpanded entries of `DT_RPATH` and `DT_RUNPATH`.
+    pub rpath: Option<Vec<String>>,
+    pub runpath: Option<Vec<String>>,
+}
+
+impl ObjectInfo {
+    pub fn from_elf(elf: &Elf<'_>) -> Result<Self> {
+        let mut rpath: Option<Vec<String>> = None;
+        let mut runpath: Option<Vec<String>> = None;
+        if let Some(dynamic) = &elf.dynamic {
+            for d in &dynamic.dyns {
+                let paths = if d.d_tag == DT_RUNPATH {
+                    &mut runpath
+                } else if d.d_tag == DT_RPATH {
+                    &mut rpath
+                } else {
+                    continue;
+                };
+                let content = super::get_content_in_strtab(d, &elf.dynstrtab)?;
+                // assuming paths in DT_RPATH and DT_RUNPATH are separated by colons.
+                paths
+                    .get_or_insert_with(Vec::new)
+                    .extend(content.split(':').map(ToOwned::to_owned));
+            }
+        }
+
+        Ok(ObjectInfo {
+            target: ElfTarget::from_elf(elf),
+            interpreter: elf.interpreter.map(Into::into),
+            soname: elf.soname.map(ToOwned::to_owned),
+            libraries: elf.libraries.iter().map(|&s| s.to_owned()).collect(),
+            rpath,
+            runpath,
+        })
+    }
+
+    pub fn read<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let buffer = fs::read(path)?;
+        let elf = Elf::parse(buffer.as_slice())?;
+        ObjectInfo::from_elf(&elf)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "machine": self.target.machine(),
+            "is_64": self.target.is_64(),
+            "is_little_endian": self.target.is_little_endian(),
+            "abi": self.target.abi(),
+            "interpreter": self.interpreter.as_ref().map(|p| p.to_string_lossy()),
+            "soname": self.soname,
+            "libraries": self.libraries,
+            "rpath": self.rpath,
+            "runpath": self.runpath,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let strings = |value: &Value| -> Option<Vec<String>> {
+            value
+                .as_array()?
+                .iter()
+                .map(|s| s.as_str().map(ToOwned::to_owned))
+                .collect()
+        };
+        let optional_strings = |value: &Value| -> Option<Option<Vec<String>>> {
+            if value.is_null() {
+                Some(None)
+            } else {
+                strings(value).map(Some)
+            }
+        };
+
+        let target = ElfTarget::new(
+            u16::try_from(value["machine"].as_u64()?).ok()?,
+            value["is_64"].as_bool()?,
+            value["is_little_endian"].as_bool()?,
+            u32::try_from(value["abi"].as_u64()?).ok()?,
+        );
+        let interpreter = match &value["interpreter"] {
+            Value::Null => None,
+            interp => Some(interp.as_str()?.into()),
+        };
+        let soname = match &value["soname"] {
+            Value::Null => None,
+            soname => Some(soname.as_str()?.to_owned()),
+        };
+        Some(ObjectInfo {
+            target,
+            interpreter,
+            soname,
+            libraries: strings(&value["libraries"])?,
+            rpath: optional_strings(&value["rpath"])?,
+            runpath: optional_strings(&value["runpath"])?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedLookup {
+    path: PathBuf,
+    rule: LookupRule,
+    /// The content hash of the file at `path`, only used in the persistent cache.
+    sha256: Option<String>,
+}
+
+/// Caches parsed objects and shared object lookups, so that each object is parsed and each lookup is
+/// done only once even if the object is required from many executables.
+///
+/// The cache is optionally persisted to a file. Persisted objects are keyed by their content hash,
+/// and persisted lookups are only reused while the found file keeps the same content. The callers check that
+/// nothing is found earlier in the search paths before reusing a lookup.
+#[derive(Debug, Default)]
+pub struct DependencyCache {
+    path: Option<PathBuf>,
+    objects: RefCell<HashMap<PathBuf, ObjectInfo>>,
+    persisted_objects: RefCell<HashMap<String, ObjectInfo>>,
+    lookups: RefCell<HashMap<String, CachedLookup>>,
+    hashes: RefCell<HashMap<PathBuf, String>>,
+    dirty: Cell<bool>,
+}
+
+impl DependencyCache {
+    /// A cache that lives only in memory.
+    pub fn new() -> Self {
+        DependencyCache::default()
+    }
+
+    /// A cache persisted to the file at `path`, which is loaded if it exists.
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut cache = DependencyCache {
+            path: Some(path.to_owned()),
+            ..DependencyCache::default()
+        };
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::debug!(path = %path.display(), "cache: starting with an empty cache");
+                return Ok(cache);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_str::<Value>(&content) {
+            Ok(value) if value["version"].as_u64() == Some(CACHE_VERSION) => {
+                cache.load_json(&value);
+                tracing::debug!(
+                    path = %path.display(),
+                    objects = cache.persisted_objects.borrow().len(),
+                    lookups = cache.lookups.borrow().len(),
+                    "cache: loaded",
+                );
+            }
+            _ => {
+                tracing::warn!(path = %path.display(), "cache: ignoring malformed or outdated cache file");
+            }
+        }
+        Ok(cache)
+    }
+
+    fn load_json(&mut self, value: &Value) {
+        if let Some(objects) = value["objects"].as_object() {
+            let objects = objects
+                .iter()
+                .filter_map(|(hash, v)| Some((hash.clone(), ObjectInfo::from_json(v)?)));
+            self.persisted_objects.get_mut().extend(objects);
+        }
+        if let Some(lookups) = value["lookups"].as_object() {
+            let lookups = lookups.iter().filter_map(|(key, v)| {
+                let lookup = CachedLookup {
+                    path: v["path"].as_str()?.into(),
+                    rule: LookupRule::from_name(v["rule"].as_str()?)?,
+                    sha256: Some(v["sha256"].as_str()?.to_owned()),
+                };
+                Some((key.clone(), lookup))
+            });
+            self.lookups.get_mut().extend(lookups);
+        }
+    }
+
+    fn is_persistent(&self) -> bool {
+        self.path.is_some()
+    }
+
+    fn hash_of(&self, host_path: &Path) -> Result<String> {
+        if let Some(hash) = self.hashes.borrow().get(host_path) {
+            return Ok(hash.clone());
+        }
+        let mut file = fs::File::open(host_path)?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        let hash = format!("{:x}", hasher.finalize());
+        self.hashes
+            .borrow_mut()
+            .insert(host_path.to_owned(), hash.clone());
+        Ok(hash)
+    }
+
+    /// Returns the parsed object at `host_path`.
+    pub fn object(&self, host_path: &Path) -> Result<ObjectInfo> {
+        if let Some(info) = self.objects.borrow().get(host_path) {
+            return Ok(info.clone());
+        }
+
+        let info = if self.is_persistent() {
+            let hash = self.hash_of(host_path)?;
+            let persisted = self.persisted_objects.borrow().get(&hash).cloned();
+            match persisted {
+                Some(info) => info,
+                None => {
+                    let info = ObjectInfo::read(host_path)?;
+                    self.persisted_objects
+                        .borrow_mut()
+                        .insert(hash, info.clone());
+                    self.dirty.set(true);
+                    info
+                }
+            }
+        } else {
+            ObjectInfo::read(host_path)?
+        };
+        self.objects
+            .borrow_mut()
+            .insert(host_path.to_owned(), info.clone());
+        Ok(info)
+    }
+
+    /// Returns the cached result of the lookup identified by `key`, whose result is found at `host_path`
+    /// of the returned path.
+    pub fn lookup<F>(&self, key: &str, host_path: F) -> Option<(PathBuf, LookupRule)>
+    where
+        F: FnOnce(&Path) -> PathBuf,
+    {
+        let cached = self.lookups.borrow().get(key).cloned()?;
+        if let Some(sha256) = &cached.sha256 {
+            match self.hash_of(&host_path(&cached.path)) {
+                Ok(hash) if &hash == sha256 => {}
+                _ => {
+                    tracing::debug!(%key, path = %cached.path.display(), "cache: stale lookup");
+                    self.lookups.borrow_mut().remove(key);
+                    return None;
+                }
+            }
+        }
+        Some((cached.path, cached.rule))
+    }
+
+    pub fn insert_lookup(&self, key: String, path: PathBuf, rule: LookupRule, host_path: &Path) {
+        let sha256 = if self.is_persistent() {
+            match self.hash_of(host_path) {
+                Ok(hash) => Some(hash),
+                // not worth persisting, such as a name not found on the host
+                Err(_) => return,
+            }
+        } else {
+            None
+        };
+        if sha256.is_some() {
+            self.dirty.set(true);
+        }
+        self.lookups
+            .borrow_mut()
+            .insert(key, CachedLookup { path, rule, sha256 });
+    }
+
+    /// Writes the cache to the file if it is persistent and has been changed.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) if self.dirty.get() => path,
+            _ => return Ok(()),
+        };
+
+        let objects: serde_json::Map<String, Value> = self
+            .persisted_objects
+            .borrow()
+            .iter()
+            .map(|(hash, info)| (hash.clone(), info.to_json()))
+            .collect();
+        let lookups: serde_json::Map<String, Value> = self
+            .lookups
+            .borrow()
+            .iter()
+            .filter_map(|(key, lookup)| {
+                let value = json!({
+                    "path": lookup.path.to_str()?,
+                    "rule": lookup.rule.as_str(),
+                    "sha256": lookup.sha256.as_ref()?,
+                });
+                Some((key.clone(), value))
+            })
+            .collect();
+        let value = json!({
+            "version": CACHE_VERSION,
+            "objects": objects,
+            "lookups": lookups,
+        });
+
+        tracing::debug!(path = %path.display(), "cache: saving");
+        fs::write(
+            path,
+            serde_json::to_string(&value).expect("serializing JSON values never fails"),
+        )?;
+        self.dirty.set(false);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+    use std::env;
+
+    #[test]
+    fn test_persist() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let exe = dir.child("exe");
+        fs::copy(env::current_exe()?, exe.path())?;
+        let cache_path = dir.child("cache.json");
+
+        let cache = DependencyCache::load(cache_path.path())?;
+        let info = cache.object(exe.path())?;
+        assert!(info.libraries.iter().any(|lib| lib.starts_with("libc.so")));
+        cache.insert_lookup(
+            "key".to_owned(),
+            "/exe".into(),
+            LookupRule::DefaultDirs,
+            exe.path(),
+        );
+        cache.save()?;
+
+        let cache = DependencyCache::load(cache_path.path())?;
+        assert_eq!(cache.persisted_objects.borrow().len(), 1);
+        assert_eq!(cache.object(exe.path())?, info);
+        assert_eq!(
+            cache.lookup("key", |_| exe.path().to_owned()),
+            Some((PathBuf::from("/exe"), LookupRule::DefaultDirs))
+        );
+        assert_eq!(cache.lookup("other", |_| exe.path().to_owned()), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_lookup() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let lib = dir.child("libfoo.so.1");
+        lib.write_str("old")?;
+        let cache_path = dir.child("cache.json");
+
+        let cache = DependencyCache::load(cache_path.path())?;
+        cache.insert_lookup(
+            "key".to_owned(),
+            "/libfoo.so.1".into(),
+            LookupRule::Rpath,
+            lib.path(),
+        );
+        cache.save()?;
+
+        // lookups of files replaced since the last run are not reused
+        lib.write_str("new")?;
+        let cache = DependencyCache::load(cache_path.path())?;
+        assert_eq!(cache.lookup("key", |_| lib.path().to_owned()), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let cache_path = dir.child("cache.json");
+        cache_path.write_str("{\"version\": 1, \"objects\": ")?;
+
+        let cache = DependencyCache::load(cache_path.path())?;
+        assert!(cache.persisted_objects.borrow().is_empty());
+        assert!(cache.lookups.borrow().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_version() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let exe = dir.child("exe");
+        fs::copy(env::current_exe()?, exe.path())?;
+        let cache_path = dir.child("cache.json");
+
+        let cache = DependencyCache::load(cache_path.path())?;
+        cache.object(exe.path())?;
+        cache.insert_lookup(
+            "key".to_owned(),
+            "/exe".into(),
+            LookupRule::DefaultDirs,
+            exe.path(),
+        );
+        cache.save()?;
+
+        // a well-formed cache of another version may mean something else, so it is not read
+        let mut value: Value = serde_json::from_str(&fs::read_to_string(cache_path.path())?)?;
+        value["version"] = json!(CACHE_VERSION + 1);
+        cache_path.write_str(&value.to_string())?;
+
+        let cache = DependencyCache::load(cache_path.path())?;
+        assert!(cache.persisted_objects.borrow().is_empty());
+        assert!(cache.lookups.borrow().is_empty());
+        Ok(())
+    }
+}
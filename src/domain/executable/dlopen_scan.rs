@@ -0,0 +1,113 @@
+use goblin::elf::section_header::SHT_NOBITS;
+use goblin::elf::Elf;
+
+// sections where string literals passed to dlopen(3) and names of imported symbols end up
+const SCANNED_SECTIONS: &[&str] = &[".rodata", ".dynstr"];
+
+/// A string in an ELF object that looks like the name or path of a shared object,
+/// which is possibly loaded with dlopen(3) at runtime.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DlopenCandidate {
+    pub name: String,
+    pub section: String,
+    /// The offset of the string in the file.
+    pub offset: u64,
+}
+
+/// Scans the string tables of `elf` for strings that look like shared objects.
+pub fn scan(elf: &Elf<'_>, buffer: &[u8]) -> Vec<DlopenCandidate> {
+    let mut candidates = Vec::new();
+    for header in &elf.section_headers {
+        let section = match elf.shdr_strtab.get_at(header.sh_name) {
+            Some(name) if SCANNED_SECTIONS.contains(&name) => name,
+            _ => continue,
+        };
+        if header.sh_type == SHT_NOBITS {
+            continue;
+        }
+        let data = match usize::try_from(header.sh_offset)
+            .ok()
+            .zip(usize::try_from(header.sh_size).ok())
+            .and_then(|(start, size)| buffer.get(start..start.checked_add(size)?))
+        {
+            Some(data) => data,
+            None => {
+                tracing::warn!(%section, "dlopen_scan: section is out of the file");
+                continue;
+            }
+        };
+
+        let mut start = 0;
+        for bytes in data.split(|b| *b == 0) {
+            if let Ok(s) = std::str::from_utf8(bytes) {
+                if looks_like_shared_object(s) {
+                    candidates.push(DlopenCandidate {
+                        name: s.to_owned(),
+                        section: section.to_owned(),
+                        offset: header.sh_offset + start as u64,
+                    });
+                }
+            }
+            start += bytes.len() + 1;
+        }
+    }
+    candidates
+}
+
+/// Tells whether `s` is a soname like `libfoo.so.1` or an absolute path to it.
+/// Relative paths like `plugins/libfoo.so` are skipped, as dlopen(3) opens them relative to the working
+/// directory at runtime.
+fn looks_like_shared_object(s: &str) -> bool {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "._+-/".contains(c);
+    if s.is_empty() || !s.chars().all(valid_char) {
+        return false;
+    }
+    if s.contains('/') && !s.starts_with('/') {
+        return false;
+    }
+
+    let file_name = s.rsplit('/').next().unwrap_or(s);
+    // `.so` followed by optional version numbers, as in `libfoo.so.1.2`
+    file_name.match_indices(".so").any(|(idx, _)| {
+        let version = &file_name[idx + 3..];
+        idx > 0
+            && version
+                .split('.')
+                .skip(1)
+                .all(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+            && (version.is_empty() || version.starts_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_like_shared_object() {
+        for s in [
+            "libfoo.so",
+            "libfoo.so.1",
+            "libfoo.so.1.2.3",
+            "/usr/lib/x86_64-linux-gnu/libGL.so.1",
+            "libstdc++.so.6",
+        ] {
+            assert!(looks_like_shared_object(s), "{}", s);
+        }
+        for s in [
+            "",
+            ".so",
+            "libfoo",
+            "libfoo.so.x",
+            "libfoo.so.1.",
+            "libnss_%s.so.2",
+            "see libfoo.so",
+            "libfoo.sound",
+            "/usr/lib/",
+            "plugins/libbar.so",
+            "./libbar.so",
+        ] {
+            assert!(!looks_like_shared_object(s), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::executable::{CacheEntry, ElfTarget, Hwcap};
+
+use goblin::elf::header::{EM_PPC64, EM_S390, EM_X86_64};
+
+// from the most capable one, as ld.so tries them in this order
+const X86_64_LEVELS: &[&str] = &["x86-64-v4", "x86-64-v3", "x86-64-v2"];
+const POWER_LEVELS: &[&str] = &["power10", "power9"];
+const S390X_LEVELS: &[&str] = &["z16", "z15", "z14", "z13"];
+const ALL_LEVELS: &[&[&str]] = &[X86_64_LEVELS, POWER_LEVELS, S390X_LEVELS];
+
+/// Subdirectories that ld.so probes in each search directory before the directory itself, in priority order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Hwcaps {
+    glibc_hwcaps: Vec<String>,
+    legacy_dirs: Vec<String>,
+}
+
+impl Hwcaps {
+    /// No subdirectory is probed, that is, only baseline libraries are used.
+    pub const fn baseline() -> Self {
+        Hwcaps {
+            glibc_hwcaps: Vec::new(),
+            legacy_dirs: Vec::new(),
+        }
+    }
+
+    /// The `glibc-hwcaps` subdirectories supported by the CPU of the running host.
+    pub fn host() -> Self {
+        let level = host_level();
+        tracing::debug!(?level, "hwcaps: detected the level of the host");
+        level
+            .and_then(|level| Hwcaps::in_family(X86_64_LEVELS, level))
+            .unwrap_or_else(Hwcaps::baseline)
+    }
+
+    /// The `glibc-hwcaps` subdirectories supported by a CPU of `level`, such as `x86-64-v3`, for objects of
+    /// `target`. Levels below `level` in the same family are also included. `baseline` disables the
+    /// subdirectories. Levels of the other families are rejected, since ld.so never probes them.
+    pub fn level(level: &str, target: ElfTarget) -> Result<Self> {
+        if level == "baseline" {
+            return Ok(Hwcaps::baseline());
+        }
+
+        if let Some(hwcaps) =
+            target_levels(target).and_then(|levels| Hwcaps::in_family(levels, level))
+        {
+            return Ok(hwcaps);
+        }
+        if ALL_LEVELS.iter().any(|levels| levels.contains(&level)) {
+            return Err(Error::HwcapsLevelMismatch(
+                level.to_owned(),
+                format!("{:?}", target),
+            ));
+        }
+
+        tracing::warn!(%level, "hwcaps: unknown level, probing only the subdirectory of the name");
+        Ok(Hwcaps {
+            glibc_hwcaps: vec![level.to_owned()],
+            legacy_dirs: Vec::new(),
+        })
+    }
+
+    fn in_family(levels: &[&str], level: &str) -> Option<Self> {
+        let idx = levels.iter().position(|l| *l == level)?;
+        Some(Hwcaps {
+            glibc_hwcaps: levels[idx..].iter().map(|l| l.to_string()).collect(),
+            legacy_dirs: Vec::new(),
+        })
+    }
+
+    /// Additionally probes legacy hwcap subdirectories such as `tls` and `haswell` after `glibc-hwcaps`,
+    /// as glibc before 2.37 does.
+    pub fn with_legacy_dirs<I, S>(mut self, dirs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.legacy_dirs.extend(dirs.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn glibc_hwcaps(&self) -> &[String] {
+        &self.glibc_hwcaps
+    }
+
+    /// Returns the candidate paths of `name` in `dir`, in the order ld.so tries them.
+    pub fn candidates(&self, dir: &Path, name: &str) -> Vec<PathBuf> {
+        let glibc_hwcaps = self
+            .glibc_hwcaps
+            .iter()
+            .map(|sub| dir.join("glibc-hwcaps").join(sub).join(name));
+        let legacy = self.legacy_dirs.iter().map(|sub| dir.join(sub).join(name));
+        glibc_hwcaps
+            .chain(legacy)
+            .chain(std::iter::once(dir.join(name)))
+            .collect()
+    }
+
+    /// Returns the path of the baseline library that `path` found in a subdirectory is a variant of, or
+    /// `None` if `path` is not in a subdirectory probed with these hwcaps.
+    pub fn baseline_of(&self, path: &Path) -> Option<PathBuf> {
+        let name = path.file_name()?;
+        let sub_dir = path.parent()?;
+        let sub = sub_dir.file_name()?;
+        let parent = sub_dir.parent()?;
+        if self.glibc_hwcaps.iter().any(|s| sub == s.as_str())
+            && matches!(parent.file_name(), Some(dir) if dir == "glibc-hwcaps")
+        {
+            return Some(parent.parent()?.join(name));
+        }
+        if self.legacy_dirs.iter().any(|s| sub == s.as_str()) {
+            return Some(parent.join(name));
+        }
+        None
+    }
+
+    /// Orders the ld.so.cache entries usable with these hwcaps in the order ld.so tries them.
+    pub fn select<'a, I>(&self, entries: I) -> Vec<&'a CacheEntry>
+    where
+        I: IntoIterator<Item = &'a CacheEntry>,
+    {
+        let mut selected: Vec<(usize, &CacheEntry)> = entries
+            .into_iter()
+            .filter_map(|entry| Some((self.priority(entry)?, entry)))
+            .collect();
+        // stable sort keeps the order in the cache within the same priority
+        selected.sort_by_key(|(priority, _)| *priority);
+        selected.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    fn priority(&self, entry: &CacheEntry) -> Option<usize> {
+        let legacy_base = self.glibc_hwcaps.len();
+        let baseline = legacy_base + self.legacy_dirs.len();
+        match &entry.hwcap {
+            Hwcap::None => Some(baseline),
+            Hwcap::GlibcHwcaps(sub) => self.glibc_hwcaps.iter().position(|s| s == sub),
+            // legacy entries are identified with the name of the directory they are placed in
+            Hwcap::Legacy(_) => {
+                let dir_name = entry.value.parent()?.file_name()?;
+                let idx = self
+                    .legacy_dirs
+                    .iter()
+                    .position(|s| dir_name == s.as_str())?;
+                Some(legacy_base + idx)
+            }
+        }
+    }
+}
+
+/// The `glibc-hwcaps` levels ld.so of `target` supports.
+fn target_levels(target: ElfTarget) -> Option<&'static [&'static str]> {
+    match (target.machine(), target.is_64(), target.is_little_endian()) {
+        (EM_X86_64, true, _) => Some(X86_64_LEVELS),
+        (EM_PPC64, _, true) => Some(POWER_LEVELS),
+        (EM_S390, true, _) => Some(S390X_LEVELS),
+        _ => None,
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn host_level() -> Option<&'static str> {
+    macro_rules! has {
+        ($($feature:tt),*) => { true $(&& std::is_x86_feature_detected!($feature))* };
+    }
+
+    if !has!("cmpxchg16b", "popcnt", "sse3", "sse4.1", "sse4.2", "ssse3") {
+        None
+    } else if !has!("avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt") {
+        Some("x86-64-v2")
+    } else if !has!("avx512f", "avx512bw", "avx512cd", "avx512dq", "avx512vl") {
+        Some("x86-64-v3")
+    } else {
+        Some("x86-64-v4")
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn host_level() -> Option<&'static str> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x86_64_level(level: &str) -> Hwcaps {
+        Hwcaps::level(level, ElfTarget::new(EM_X86_64, true, true, 0)).unwrap()
+    }
+
+    fn entry(value: &str, hwcap: Hwcap) -> CacheEntry {
+        CacheEntry {
+            flags: 0x0303,
+            key: "libz.so.1".to_owned(),
+            value: value.into(),
+            os_version: 0,
+            hwcap,
+        }
+    }
+
+    #[test]
+    fn test_level() {
+        let x86_64 = ElfTarget::new(EM_X86_64, true, true, 0);
+        let s390x = ElfTarget::new(EM_S390, true, false, 0);
+        let ppc64le = ElfTarget::new(EM_PPC64, true, true, 0);
+        assert_eq!(
+            Hwcaps::level("x86-64-v3", x86_64).unwrap().glibc_hwcaps(),
+            ["x86-64-v3", "x86-64-v2"]
+        );
+        assert_eq!(
+            Hwcaps::level("z15", s390x).unwrap().glibc_hwcaps(),
+            ["z15", "z14", "z13"]
+        );
+        assert!(Hwcaps::level("baseline", ppc64le)
+            .unwrap()
+            .glibc_hwcaps()
+            .is_empty());
+        // levels of another family are never probed by ld.so of the target
+        assert!(matches!(
+            Hwcaps::level("x86-64-v3", ppc64le),
+            Err(Error::HwcapsLevelMismatch(..))
+        ));
+        assert!(matches!(
+            Hwcaps::level("power9", x86_64),
+            Err(Error::HwcapsLevelMismatch(..))
+        ));
+        assert_eq!(
+            Hwcaps::level("custom", x86_64).unwrap().glibc_hwcaps(),
+            ["custom"]
+        );
+    }
+
+    #[test]
+    fn test_candidates() {
+        let hwcaps = x86_64_level("x86-64-v3").with_legacy_dirs(["tls"]);
+        assert_eq!(
+            hwcaps.candidates(Path::new("/lib"), "libz.so.1"),
+            [
+                "/lib/glibc-hwcaps/x86-64-v3/libz.so.1",
+                "/lib/glibc-hwcaps/x86-64-v2/libz.so.1",
+                "/lib/tls/libz.so.1",
+                "/lib/libz.so.1"
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_baseline_of() {
+        let hwcaps = x86_64_level("x86-64-v3").with_legacy_dirs(["tls"]);
+        assert_eq!(
+            hwcaps.baseline_of(Path::new("/lib/glibc-hwcaps/x86-64-v2/libz.so.1")),
+            Some(PathBuf::from("/lib/libz.so.1"))
+        );
+        assert_eq!(
+            hwcaps.baseline_of(Path::new("/lib/tls/libz.so.1")),
+            Some(PathBuf::from("/lib/libz.so.1"))
+        );
+        assert_eq!(hwcaps.baseline_of(Path::new("/lib/libz.so.1")), None);
+        assert_eq!(
+            Hwcaps::baseline().baseline_of(Path::new("/lib/glibc-hwcaps/x86-64-v2/libz.so.1")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let entries = [
+            entry(
+                "/lib/glibc-hwcaps/x86-64-v4/libz.so.1",
+                Hwcap::GlibcHwcaps("x86-64-v4".to_owned()),
+            ),
+            entry("/lib/tls/libz.so.1", Hwcap::Legacy(1 << 63)),
+            entry("/lib/libz.so.1", Hwcap::None),
+            entry(
+                "/lib/glibc-hwcaps/x86-64-v2/libz.so.1",
+                Hwcap::GlibcHwcaps("x86-64-v2".to_owned()),
+            ),
+        ];
+        let values = |hwcaps: &Hwcaps| {
+            hwcaps
+                .select(&entries)
+                .into_iter()
+                .map(|e| e.value.to_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            values(&x86_64_level("x86-64-v3")),
+            ["/lib/glibc-hwcaps/x86-64-v2/libz.so.1", "/lib/libz.so.1"]
+        );
+        assert_eq!(
+            values(&Hwcaps::baseline().with_legacy_dirs(["tls"])),
+            ["/lib/tls/libz.so.1", "/lib/libz.so.1"]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::base::Result;
+use crate::domain::executable::LdSoCache;
+use crate::domain::Root;
+
+/// System-wide configuration of the dynamic linker, that is, `/etc/ld.so.conf` and `/etc/ld.so.cache`.
+#[derive(Debug, Default)]
+pub struct LdConfig {
+    conf_dirs: Vec<PathBuf>,
+    cache: Option<LdSoCache>,
+}
+
+impl LdConfig {
+    pub fn load(root: &Root) -> Result<Self> {
+        LdConfig::load_from(root, "/etc/ld.so.conf", "/etc/ld.so.cache")
+    }
+
+    /// Loads the configuration from paths in `root`.
+    pub fn load_from<P, Q>(root: &Root, conf_path: P, cache_path: Q) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let mut conf_dirs = Vec::new();
+        parse_conf(
+            root,
+            conf_path.as_ref(),
+            &mut conf_dirs,
+            &mut HashSet::new(),
+        )?;
+        tracing::debug!(?conf_dirs, "ld_config: loaded ld.so.conf");
+
+        let cache = match LdSoCache::load(root.real_host_path(cache_path.as_ref())) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                tracing::warn!(
+                    path = %cache_path.as_ref().display(),
+                    error = %e,
+                    "ld_config: unable to load ld.so.cache, searching directories in ld.so.conf instead",
+                );
+                None
+            }
+        };
+
+        Ok(LdConfig { conf_dirs, cache })
+    }
+
+    pub fn conf_dirs(&self) -> &[PathBuf] {
+        &self.conf_dirs
+    }
+
+    pub fn cache(&self) -> Option<&LdSoCache> {
+        self.cache.as_ref()
+    }
+}
+
+fn parse_conf(
+    root: &Root,
+    path: &Path,
+    dirs: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    if !visited.insert(path.to_owned()) {
+        tracing::warn!(path = %path.display(), "ld_config: ignoring recursive include");
+        return Ok(());
+    }
+
+    let content = match fs::read_to_string(root.real_host_path(path)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            tracing::debug!(path = %path.display(), "ld_config: configuration file not found");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut words = line.splitn(2, char::is_whitespace);
+        match (words.next(), words.next()) {
+            (Some("include"), Some(patterns)) => {
+                for pattern in patterns.split_whitespace() {
+                    // relative patterns are relative to the directory of the current file
+                    let pattern = match path.parent() {
+                        Some(parent) => parent.join(pattern),
+                        None => PathBuf::from(pattern),
+                    };
+                    let mut included: Vec<_> = root
+                        .glob(&pattern.to_string_lossy())?
+                        .into_iter()
+                        .filter_map(|entry| entry.ok())
+                        .collect();
+                    included.sort();
+                    for included_path in included {
+                        parse_conf(root, &included_path, dirs, visited)?;
+                    }
+                }
+            }
+            // hwcap directives are ignored by recent ldconfig as well
+            (Some("hwcap"), Some(_)) => {}
+            _ => {
+                for dir in line.split(|c: char| c == ':' || c == ',' || c.is_whitespace()) {
+                    // strip the library type of the obsolete `dir=TYPE` syntax
+                    let dir = dir.split('=').next().unwrap_or("");
+                    if dir.is_empty() {
+                        continue;
+                    }
+                    let dir = PathBuf::from(dir);
+                    if !dirs.contains(&dir) {
+                        dirs.push(dir);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_parse_conf() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let conf = dir.child("ld.so.conf");
+        conf.write_str(
+            "# comment\n/usr/local/lib\ninclude ld.so.conf.d/*.conf\n/opt/a:/opt/b, /opt/c=libc6\n",
+        )?;
+        dir.child("ld.so.conf.d/b.conf")
+            .write_str("/usr/lib/b # trailing comment\nhwcap 0 nosegneg\n")?;
+        dir.child("ld.so.conf.d/a.conf").write_str(&format!(
+            "/usr/lib/a\n/usr/local/lib\ninclude {}\n",
+            conf.path().display()
+        ))?;
+
+        let mut dirs = Vec::new();
+        parse_conf(&Root::host(), conf.path(), &mut dirs, &mut HashSet::new())?;
+        assert_eq!(
+            dirs,
+            [
+                "/usr/local/lib",
+                "/usr/lib/a",
+                "/usr/lib/b",
+                "/opt/a",
+                "/opt/b",
+                "/opt/c"
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
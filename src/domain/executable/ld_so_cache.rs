@@ -0,0 +1,484 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+
+const CACHE_MAGIC_NEW: &[u8] = b"glibc-ld.so.cache1.1";
+const CACHE_MAGIC_OLD: &[u8] = b"ld.so-1.7.0";
+
+const HEADER_NEW_SIZE: usize = 48;
+const ENTRY_NEW_SIZE: usize = 24;
+const HEADER_OLD_SIZE: usize = 16;
+const ENTRY_OLD_SIZE: usize = 12;
+
+const FLAG_TYPE_MASK: i32 = 0x00ff;
+const FLAG_ELF_LIBC6: i32 = 0x0003;
+
+const EXTENSION_MAGIC: u32 = 0xeaa4_2174;
+const EXTENSION_TAG_GLIBC_HWCAPS: u32 = 1;
+const HWCAP_EXTENSION: u64 = 1 << 62;
+
+/// Hardware capabilities that a cached library requires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hwcap {
+    /// A baseline library, which is used when no other variant is usable.
+    None,
+    /// A library in a `glibc-hwcaps` subdirectory, such as `x86-64-v3`.
+    GlibcHwcaps(String),
+    /// Bits of hwcap and platform for the legacy hwcap subdirectories.
+    Legacy(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub flags: i32,
+    pub key: String,
+    pub value: PathBuf,
+    pub os_version: u32,
+    pub hwcap: Hwcap,
+}
+
+impl CacheEntry {
+    pub fn is_elf_libc6(&self) -> bool {
+        self.flags & FLAG_TYPE_MASK == FLAG_ELF_LIBC6
+    }
+}
+
+/// Contents of `/etc/ld.so.cache` generated by ldconfig(8).
+#[derive(Debug, Default)]
+pub struct LdSoCache {
+    entries: Vec<CacheEntry>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| malformed(format!("unexpected end of file at {}", offset)))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        // unwrap is ok because `bytes` returns a slice with the exact length
+        let bytes = self.bytes(offset, 4)?.try_into().unwrap();
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64> {
+        // unwrap is ok because `bytes` returns a slice with the exact length
+        let bytes = self.bytes(offset, 8)?.try_into().unwrap();
+        Ok(if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    }
+
+    fn str(&self, offset: usize) -> Result<&[u8]> {
+        let rest = self
+            .data
+            .get(offset..)
+            .ok_or_else(|| malformed(format!("string offset {} is out of range", offset)))?;
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| malformed(format!("unterminated string at {}", offset)))?;
+        Ok(&rest[..len])
+    }
+}
+
+impl LdSoCache {
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let data = fs::read(path.as_ref())?;
+        let cache = LdSoCache::parse(&data)?;
+        tracing::debug!(
+            path = %path.as_ref().display(),
+            entries = cache.entries.len(),
+            "ld.so.cache: loaded",
+        );
+        Ok(cache)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.starts_with(CACHE_MAGIC_NEW) {
+            return parse_new(data, 0);
+        }
+        if !data.starts_with(CACHE_MAGIC_OLD) {
+            return Err(malformed("unknown magic".to_owned()));
+        }
+
+        // the old format is followed by the new one since glibc 2.2 unless ldconfig is told otherwise
+        let reader = Reader {
+            data,
+            little_endian: cfg!(target_endian = "little"),
+        };
+        let nlibs = reader.u32(CACHE_MAGIC_OLD.len() + 1)? as usize;
+        check_entries(data, HEADER_OLD_SIZE, nlibs, ENTRY_OLD_SIZE)?;
+        let header = (HEADER_OLD_SIZE + nlibs * ENTRY_OLD_SIZE + 7) & !7;
+        match data.get(header..) {
+            Some(rest) if rest.starts_with(CACHE_MAGIC_NEW) => parse_new(data, header),
+            _ => parse_old(&reader, nlibs),
+        }
+    }
+
+    pub fn entries(&self) -> &[CacheEntry] {
+        &self.entries
+    }
+
+    /// Returns ELF libraries named `name` for all hardware capabilities, in the order ld.so tries them.
+    pub fn lookup_entries<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a CacheEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.key == name && e.is_elf_libc6())
+    }
+
+    /// Returns paths of baseline ELF libraries named `name`, in the order ld.so tries them.
+    pub fn lookup<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Path> {
+        self.lookup_entries(name)
+            .filter(|e| e.hwcap == Hwcap::None)
+            .map(|e| e.value.as_path())
+    }
+}
+
+fn parse_old(reader: &Reader<'_>, nlibs: usize) -> Result<LdSoCache> {
+    // string offsets are relative to the string table placed after the entries
+    let strings = HEADER_OLD_SIZE + nlibs * ENTRY_OLD_SIZE;
+    let mut entries = Vec::with_capacity(nlibs);
+    for idx in 0..nlibs {
+        let base = HEADER_OLD_SIZE + idx * ENTRY_OLD_SIZE;
+        let flags = reader.u32(base)? as i32;
+        let key = reader.str(strings + reader.u32(base + 4)? as usize)?;
+        let value = reader.str(strings + reader.u32(base + 8)? as usize)?;
+        entries.push(CacheEntry {
+            flags,
+            key: String::from_utf8_lossy(key).into_owned(),
+            value: OsStr::from_bytes(value).into(),
+            os_version: 0,
+            hwcap: Hwcap::None,
+        });
+    }
+    Ok(LdSoCache { entries })
+}
+
+/// Parses the new format whose header is at `header` in `data`.
+fn parse_new(data: &[u8], header: usize) -> Result<LdSoCache> {
+    let little_endian = match data.get(header + CACHE_MAGIC_NEW.len() + 8) {
+        Some(2) => true,
+        Some(3) => false,
+        _ => cfg!(target_endian = "little"),
+    };
+    // string offsets are relative to the header, while the extension offset is relative to the file
+    let file = Reader {
+        data,
+        little_endian,
+    };
+    let reader = Reader {
+        data: &data[header..],
+        little_endian,
+    };
+
+    let nlibs = reader.u32(CACHE_MAGIC_NEW.len())? as usize;
+    let extension_offset = reader.u32(CACHE_MAGIC_NEW.len() + 12)? as usize;
+    let glibc_hwcaps = parse_glibc_hwcaps(&file, &reader, extension_offset)?;
+    check_entries(reader.data, HEADER_NEW_SIZE, nlibs, ENTRY_NEW_SIZE)?;
+
+    let mut entries = Vec::with_capacity(nlibs);
+    for idx in 0..nlibs {
+        let base = HEADER_NEW_SIZE + idx * ENTRY_NEW_SIZE;
+        let flags = reader.u32(base)? as i32;
+        let key = reader.str(reader.u32(base + 4)? as usize)?;
+        let value = reader.str(reader.u32(base + 8)? as usize)?;
+        let os_version = reader.u32(base + 12)?;
+        let hwcap = match reader.u64(base + 16)? {
+            0 => Hwcap::None,
+            bits if bits & HWCAP_EXTENSION != 0 => {
+                let index = (bits & 0xffff_ffff) as usize;
+                let name = glibc_hwcaps.get(index).ok_or_else(|| {
+                    malformed(format!("glibc-hwcaps index {} is out of range", index))
+                })?;
+                Hwcap::GlibcHwcaps(name.clone())
+            }
+            bits => Hwcap::Legacy(bits),
+        };
+        entries.push(CacheEntry {
+            flags,
+            key: String::from_utf8_lossy(key).into_owned(),
+            value: OsStr::from_bytes(value).into(),
+            os_version,
+            hwcap,
+        });
+    }
+
+    Ok(LdSoCache { entries })
+}
+
+/// Reads names of the `glibc-hwcaps` subdirectories from the extension section added in glibc 2.33.
+fn parse_glibc_hwcaps(
+    file: &Reader<'_>,
+    reader: &Reader<'_>,
+    extension_offset: usize,
+) -> Result<Vec<String>> {
+    if extension_offset == 0 || file.u32(extension_offset).ok() != Some(EXTENSION_MAGIC) {
+        return Ok(Vec::new());
+    }
+
+    let count = file.u32(extension_offset + 4)? as usize;
+    let mut names = Vec::new();
+    for idx in 0..count {
+        let section = extension_offset + 8 + idx * 16;
+        if file.u32(section)? != EXTENSION_TAG_GLIBC_HWCAPS {
+            continue;
+        }
+        let offset = file.u32(section + 8)? as usize;
+        let size = file.u32(section + 12)? as usize;
+        for string_idx in 0..size / 4 {
+            let name = reader.str(file.u32(offset + string_idx * 4)? as usize)?;
+            names.push(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    Ok(names)
+}
+
+/// Checks that `nlibs` entries of `entry_size` bytes after `offset` fit in `data`, so that the count read
+/// from a corrupt file is not used to reserve memory.
+fn check_entries(data: &[u8], offset: usize, nlibs: usize, entry_size: usize) -> Result<()> {
+    let end = nlibs
+        .checked_mul(entry_size)
+        .and_then(|size| size.checked_add(offset));
+    match end {
+        Some(end) if end <= data.len() => Ok(()),
+        _ => Err(malformed(format!(
+            "{} entries do not fit in {} bytes",
+            nlibs,
+            data.len()
+        ))),
+    }
+}
+
+fn malformed(msg: String) -> Error {
+    Error::MalformedLdSoCache(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a cache in the new format placed at `base` in a file, with `hwcaps` in the extension section.
+    fn build_new(base: usize, entries: &[(i32, &str, &str, u64)], hwcaps: &[&str]) -> Vec<u8> {
+        let strings_offset = HEADER_NEW_SIZE + entries.len() * ENTRY_NEW_SIZE;
+        let mut strings = Vec::new();
+        let mut push_str = |s: &str| {
+            let offset = strings_offset + strings.len();
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+            offset as u32
+        };
+
+        let mut body = Vec::new();
+        for (flags, key, value, hwcap) in entries {
+            body.extend_from_slice(&flags.to_le_bytes());
+            body.extend_from_slice(&push_str(key).to_le_bytes());
+            body.extend_from_slice(&push_str(value).to_le_bytes());
+            body.extend_from_slice(&0u32.to_le_bytes());
+            body.extend_from_slice(&hwcap.to_le_bytes());
+        }
+        let hwcap_offsets: Vec<u32> = hwcaps.iter().map(|name| push_str(name)).collect();
+
+        let mut extension = Vec::new();
+        let extension_offset = if hwcaps.is_empty() {
+            0
+        } else {
+            let offset = (base + strings_offset + strings.len() + 7) & !7;
+            strings.resize(offset - base - strings_offset, 0);
+            extension.extend_from_slice(&EXTENSION_MAGIC.to_le_bytes());
+            extension.extend_from_slice(&1u32.to_le_bytes());
+            extension.extend_from_slice(&EXTENSION_TAG_GLIBC_HWCAPS.to_le_bytes());
+            extension.extend_from_slice(&0u32.to_le_bytes());
+            extension.extend_from_slice(&((offset + 24) as u32).to_le_bytes());
+            extension.extend_from_slice(&((hwcaps.len() * 4) as u32).to_le_bytes());
+            for hwcap_offset in hwcap_offsets {
+                extension.extend_from_slice(&hwcap_offset.to_le_bytes());
+            }
+            offset
+        };
+
+        let mut data = CACHE_MAGIC_NEW.to_vec();
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[2, 0, 0, 0]);
+        data.extend_from_slice(&(extension_offset as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 12]);
+        data.append(&mut body);
+        data.append(&mut strings);
+        data.append(&mut extension);
+        data
+    }
+
+    fn build_old(entries: &[(i32, &str, &str)]) -> Vec<u8> {
+        let mut strings = Vec::new();
+        let mut push_str = |s: &str| {
+            let offset = strings.len();
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+            offset as u32
+        };
+
+        let mut data = CACHE_MAGIC_OLD.to_vec();
+        data.push(0);
+        data.extend_from_slice(&(entries.len() as u32).to_ne_bytes());
+        for (flags, key, value) in entries {
+            data.extend_from_slice(&flags.to_ne_bytes());
+            data.extend_from_slice(&push_str(key).to_ne_bytes());
+            data.extend_from_slice(&push_str(value).to_ne_bytes());
+        }
+        data.append(&mut strings);
+        data
+    }
+
+    #[test]
+    fn test_parse_new() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let data = build_new(
+            0,
+            &[
+                (0x0303, "libc.so.6", "/lib/x86_64-linux-gnu/libc.so.6", 0),
+                (0x0003, "libc.so.6", "/lib/i386-linux-gnu/libc.so.6", 0),
+                (0x0000, "libc.so.5", "/lib/libc.so.5", 0),
+            ],
+            &[],
+        );
+        let cache = LdSoCache::parse(&data)?;
+        assert_eq!(cache.entries().len(), 3);
+        assert_eq!(
+            cache.lookup("libc.so.6").collect::<Vec<_>>(),
+            [
+                Path::new("/lib/x86_64-linux-gnu/libc.so.6"),
+                Path::new("/lib/i386-linux-gnu/libc.so.6")
+            ]
+        );
+        assert_eq!(cache.lookup("libc.so.5").count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_hwcaps() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let data = build_new(
+            0,
+            &[
+                (
+                    0x0303,
+                    "libz.so.1",
+                    "/lib/glibc-hwcaps/x86-64-v3/libz.so.1",
+                    HWCAP_EXTENSION | 1,
+                ),
+                (0x0303, "libz.so.1", "/lib/tls/libz.so.1", 1 << 63),
+                (0x0303, "libz.so.1", "/lib/libz.so.1", 0),
+            ],
+            &["x86-64-v2", "x86-64-v3"],
+        );
+        let cache = LdSoCache::parse(&data)?;
+        assert_eq!(
+            cache
+                .lookup_entries("libz.so.1")
+                .map(|e| e.hwcap.clone())
+                .collect::<Vec<_>>(),
+            [
+                Hwcap::GlibcHwcaps("x86-64-v3".to_owned()),
+                Hwcap::Legacy(1 << 63),
+                Hwcap::None
+            ]
+        );
+        assert_eq!(
+            cache.lookup("libz.so.1").collect::<Vec<_>>(),
+            [Path::new("/lib/libz.so.1")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_old() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let data = build_old(&[
+            (0x0303, "libc.so.6", "/lib/libc.so.6"),
+            (0x0303, "libm.so.6", "/lib/libm.so.6"),
+        ]);
+        let cache = LdSoCache::parse(&data)?;
+        assert_eq!(
+            cache.lookup("libm.so.6").collect::<Vec<_>>(),
+            [Path::new("/lib/libm.so.6")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_compat() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // the new format follows the entries of the old one and takes precedence
+        let mut data = build_old(&[(0x0303, "libc.so.6", "/old/libc.so.6")]);
+        let header = (HEADER_OLD_SIZE + ENTRY_OLD_SIZE + 7) & !7;
+        data.resize(header, 0);
+        data.append(&mut build_new(
+            header,
+            &[
+                (
+                    0x0303,
+                    "libc.so.6",
+                    "/lib/glibc-hwcaps/x86-64-v2/libc.so.6",
+                    HWCAP_EXTENSION,
+                ),
+                (0x0303, "libc.so.6", "/new/libc.so.6", 0),
+            ],
+            &["x86-64-v2"],
+        ));
+        let cache = LdSoCache::parse(&data)?;
+        assert_eq!(
+            cache
+                .lookup_entries("libc.so.6")
+                .map(|e| (e.value.as_path(), e.hwcap.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    Path::new("/lib/glibc-hwcaps/x86-64-v2/libc.so.6"),
+                    Hwcap::GlibcHwcaps("x86-64-v2".to_owned())
+                ),
+                (Path::new("/new/libc.so.6"), Hwcap::None)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        assert!(LdSoCache::parse(b"not a cache").is_err());
+        let mut data = build_new(0, &[(0x0303, "libc.so.6", "/lib/libc.so.6", 0)], &[]);
+        data.truncate(HEADER_NEW_SIZE + 4);
+        assert!(LdSoCache::parse(&data).is_err());
+        let data = build_new(
+            0,
+            &[(0x0303, "libc.so.6", "/lib/libc.so.6", HWCAP_EXTENSION | 3)],
+            &["x86-64-v2"],
+        );
+        assert!(LdSoCache::parse(&data).is_err());
+
+        // entry counts larger than the file are rejected without reserving them
+        let mut data = build_new(0, &[(0x0303, "libc.so.6", "/lib/libc.so.6", 0)], &[]);
+        let nlibs = CACHE_MAGIC_NEW.len();
+        data[nlibs..nlibs + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(LdSoCache::parse(&data).is_err());
+        let mut data = build_old(&[(0x0303, "libc.so.6", "/lib/libc.so.6")]);
+        let nlibs = CACHE_MAGIC_OLD.len() + 1;
+        data[nlibs..nlibs + 4].copy_from_slice(&u32::MAX.to_ne_bytes());
+        assert!(LdSoCache::parse(&data).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::base::Result;
+use crate::domain::Root;
+
+// used when the path file does not exist
+const DEFAULT_SYS_PATH: &str = "/lib:/usr/local/lib:/usr/lib";
+
+/// Returns the architecture name of the musl dynamic linker at `interp`, such as `x86_64` for
+/// `/lib/ld-musl-x86_64.so.1`, or `None` if `interp` is not musl.
+pub fn musl_arch(interp: &Path) -> Option<&str> {
+    interp
+        .file_name()?
+        .to_str()?
+        .strip_prefix("ld-musl-")?
+        .strip_suffix(".so.1")
+}
+
+/// Library search configuration of the musl dynamic linker, which has no cache.
+#[derive(Debug, Clone, Default)]
+pub struct MuslConfig {
+    sys_paths: Vec<PathBuf>,
+}
+
+impl MuslConfig {
+    /// Reads `/etc/ld-musl-$ARCH.path` for the dynamic linker at `interp` in `root`.
+    /// Returns `None` if `interp` is not musl.
+    pub fn load(root: &Root, interp: &Path) -> Result<Option<Self>> {
+        let arch = match musl_arch(interp) {
+            Some(arch) => arch,
+            None => return Ok(None),
+        };
+        let path = path_file(interp, arch);
+        let sys_path = match fs::read_to_string(root.real_host_path(&path)) {
+            Ok(content) => {
+                tracing::debug!(path = %path.display(), "musl: read path file");
+                content
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::debug!(path = %path.display(), "musl: path file not found, using defaults");
+                DEFAULT_SYS_PATH.to_owned()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(MuslConfig::parse(&sys_path)))
+    }
+
+    fn parse(sys_path: &str) -> Self {
+        let sys_paths = sys_path
+            .split([':', '\n'])
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        MuslConfig { sys_paths }
+    }
+
+    pub fn sys_paths(&self) -> &[PathBuf] {
+        &self.sys_paths
+    }
+}
+
+/// musl looks for the path file relative to the parent of the directory of the dynamic linker,
+/// so that `/lib/ld-musl-x86_64.so.1` reads `/etc/ld-musl-x86_64.path`.
+fn path_file(interp: &Path, arch: &str) -> PathBuf {
+    let prefix = interp
+        .parent()
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new("/"));
+    prefix.join(format!("etc/ld-musl-{}.path", arch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_musl_arch() {
+        assert_eq!(
+            musl_arch(Path::new("/lib/ld-musl-x86_64.so.1")),
+            Some("x86_64")
+        );
+        assert_eq!(musl_arch(Path::new("/lib64/ld-linux-x86-64.so.2")), None);
+        assert_eq!(
+            path_file(
+                Path::new("/usr/local/musl/lib/ld-musl-aarch64.so.1"),
+                "aarch64"
+            ),
+            Path::new("/usr/local/musl/etc/ld-musl-aarch64.path")
+        );
+    }
+
+    #[test]
+    fn test_load() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let root = Root::new(dir.path())?;
+        let interp = Path::new("/lib/ld-musl-x86_64.so.1");
+        assert!(MuslConfig::load(&root, Path::new("/lib/ld-linux.so.2"))?.is_none());
+
+        assert_eq!(
+            MuslConfig::load(&root, interp)?.unwrap().sys_paths(),
+            [
+                PathBuf::from("/lib"),
+                PathBuf::from("/usr/local/lib"),
+                PathBuf::from("/usr/lib")
+            ]
+        );
+
+        dir.child("etc/ld-musl-x86_64.path")
+            .write_str("/opt/lib:/usr/lib\n/lib\n")?;
+        assert_eq!(
+            MuslConfig::load(&root, interp)?.unwrap().sys_paths(),
+            [
+                PathBuf::from("/opt/lib"),
+                PathBuf::from("/usr/lib"),
+                PathBuf::from("/lib")
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::base::Result;
+use crate::domain::executable::{
+    collect_paths, default_interpreter, host_path_of, musl, resolver, Dependency, DependencyCache,
+    DlopenCandidate, ElfTarget, Executable, ExecutableLocation, LookupRule, ResolverBackend,
+    SearchPaths, SharedObject, Symbols,
+};
+use crate::domain::Root;
+
+use goblin::elf::Elf;
+
+/// The part common to executables and shared objects: what an ELF object requires and where
+/// its dependencies are searched for.
+#[derive(Debug)]
+pub struct ElfObject {
+    location: ExecutableLocation,
+    target: ElfTarget,
+    soname: Option<String>,
+    libraries: Vec<String>,
+    search_paths: SearchPaths,
+    root: Root,
+}
+
+impl ElfObject {
+    /// Loads the object at `location`, returning it with its `PT_INTERP`.
+    /// `main` tells whether the object is the executable rather than a shared object loaded into it.
+    pub(super) fn load(
+        location: ExecutableLocation,
+        propagated_rpaths: Option<Vec<PathBuf>>,
+        secure: bool,
+        main: bool,
+        root: Root,
+        cache: &DependencyCache,
+    ) -> Result<(Self, Option<PathBuf>)> {
+        tracing::debug!(location = %location.as_ref().display(), "exe: loading");
+        let info = cache.object(&host_path_of(&location, &root))?;
+        let mut search_paths = collect_paths(&info, location.as_ref(), secure, main, &root);
+
+        if let Some(paths) = propagated_rpaths {
+            search_paths.append_rpath(paths);
+        }
+
+        let obj = ElfObject {
+            location,
+            target: info.target,
+            soname: info.soname,
+            libraries: info.libraries,
+            search_paths,
+            root,
+        };
+
+        tracing::debug!(obj = ?obj, "exe: loaded");
+        Ok((obj, info.interpreter))
+    }
+
+    /// The path of the object in the root, or on the host if it is a temporary file.
+    pub fn path(&self) -> &Path {
+        self.location.as_ref()
+    }
+
+    pub(super) fn location(&self) -> &ExecutableLocation {
+        &self.location
+    }
+
+    fn host_path(&self) -> PathBuf {
+        host_path_of(&self.location, &self.root)
+    }
+
+    pub fn target(&self) -> ElfTarget {
+        self.target
+    }
+
+    /// The root filesystem the object is analyzed in.
+    pub fn root(&self) -> &Root {
+        &self.root
+    }
+
+    /// `DT_SONAME` of the object.
+    pub fn soname(&self) -> Option<&str> {
+        self.soname.as_deref()
+    }
+
+    /// Names of shared objects in `DT_NEEDED`.
+    pub fn libraries(&self) -> &[String] {
+        &self.libraries
+    }
+
+    /// Whether the object is loaded in secure-execution mode, where ld.so ignores LD_LIBRARY_PATH and restricts $ORIGIN.
+    pub fn is_secure(&self) -> bool {
+        self.search_paths.is_secure()
+    }
+
+    /// Finds the dynamic linker for objects without `PT_INTERP` nor the one loading them.
+    /// Objects without dependencies do not need it.
+    pub(super) fn default_interpreter(&self) -> Option<PathBuf> {
+        if self.libraries.is_empty() {
+            return None;
+        }
+
+        let interp = default_interpreter(self.target, &self.root);
+        if let Some(interp) = &interp {
+            tracing::debug!(interp = %interp.display(), "exe: using default interpreter");
+        } else {
+            tracing::warn!(
+                target = ?self.target,
+                "exe: interpreter could not be found. compressed executable or unsupported target?"
+            );
+        }
+        interp
+    }
+
+    /// Search paths inherited by the shared objects required by this object.
+    pub(super) fn propagated_rpaths(&self, interp: &Path) -> Option<Vec<PathBuf>> {
+        if musl::musl_arch(interp).is_some() {
+            // musl searches both RPATH and RUNPATH of all objects in the chain of dependents
+            let paths: Vec<_> = self
+                .search_paths
+                .iter_runpaths()
+                .chain(self.search_paths.iter_rpaths())
+                .map(ToOwned::to_owned)
+                .collect();
+            return Some(paths);
+        }
+        self.search_paths.rpath().cloned()
+    }
+
+    /// Finds strings that look like shared objects not listed in `DT_NEEDED`,
+    /// which the object possibly loads with dlopen(3).
+    pub fn dlopen_candidates(&self) -> Result<Vec<DlopenCandidate>> {
+        let buffer = fs::read(self.host_path())?;
+        let elf = Elf::parse(buffer.as_slice())?;
+        let mut candidates = super::dlopen_scan::scan(&elf, &buffer);
+        candidates.retain(|c| {
+            !self.libraries.contains(&c.name) && Some(c.name.as_str()) != self.soname()
+        });
+        Ok(candidates)
+    }
+
+    /// Reads the dynamic symbols and symbol versions of the object.
+    pub fn symbols(&self) -> Result<Symbols> {
+        let buffer = fs::read(self.host_path())?;
+        let elf = Elf::parse(buffer.as_slice())?;
+        Ok(Symbols::parse(&elf))
+    }
+
+    /// Looks up the shared object `name` in the same way as dlopen(3) called from this object
+    /// loaded by `interp`.
+    pub(super) fn resolve(
+        &self,
+        name: &str,
+        interp: &Path,
+        backend: &ResolverBackend,
+    ) -> Result<PathBuf> {
+        let (path, _) = self.lookup_cached(name, interp, backend, &mut None)?;
+        Ok(path)
+    }
+
+    /// Looks up `name` with the cache in `backend`. The resolver is created in `resolver` on the first
+    /// cache miss, and reused for the following lookups from the same object.
+    fn lookup_cached<'a>(
+        &'a self,
+        name: &str,
+        interp: &'a Path,
+        backend: &'a ResolverBackend,
+        resolver: &mut Option<resolver::Resolver<'a>>,
+    ) -> Result<(PathBuf, LookupRule)> {
+        let cache = backend.cache();
+        let key = backend.lookup_key(name, interp, &self.search_paths, self.target, &self.root);
+        if let Some((path, rule)) = cache.lookup(&key, |path| self.root.real_host_path(path)) {
+            match backend.lookup_search_paths(
+                name,
+                interp,
+                &self.search_paths,
+                self.target,
+                &self.root,
+            ) {
+                Some(found) if found != path => {
+                    tracing::debug!(
+                        %name,
+                        cached = %path.display(),
+                        path = %found.display(),
+                        "exe: cached shared object is shadowed in search paths",
+                    );
+                }
+                _ => {
+                    tracing::trace!(%name, path = %path.display(), %rule, "exe: found shared object in cache");
+                    return Ok((path, rule));
+                }
+            }
+        }
+
+        let resolver = match resolver {
+            Some(resolver) => resolver,
+            None => resolver.insert(resolver::Resolver::new(
+                interp,
+                &self.search_paths,
+                self.target,
+                &self.root,
+                backend,
+            )?),
+        };
+        let (path, rule) = resolver.lookup(name)?;
+        cache.insert_lookup(key, path.clone(), rule, &self.root.real_host_path(&path));
+        Ok((path, rule))
+    }
+
+    /// The baseline library of `path` found in a hwcaps subdirectory, which ld.so falls back to on CPUs
+    /// without the level.
+    fn baseline_of(&self, path: &Path, backend: &ResolverBackend) -> Option<PathBuf> {
+        let baseline = backend.hwcaps().baseline_of(path)?;
+        if self.root.real_host_path(&baseline).exists() {
+            Some(baseline)
+        } else {
+            tracing::warn!(
+                path = %path.display(),
+                "exe: no baseline library is found for the hwcaps variant, the bundle only runs on CPUs of its level",
+            );
+            None
+        }
+    }
+
+    /// Resolves the shared objects required by this object loaded by `interp`, recursively.
+    /// With `all_edges`, names required again by other objects are reported each time as well.
+    pub(super) fn dependencies(
+        &self,
+        interp: Option<&Path>,
+        backend: &ResolverBackend,
+        all_edges: bool,
+    ) -> Result<Vec<Dependency>> {
+        if self.libraries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let interp = if let Some(interp) = interp {
+            interp
+        } else {
+            tracing::warn!(
+                "exe: requesting dynamic libraries of the object without the interpreter"
+            );
+            return Ok(Vec::new());
+        };
+
+        let mut resolving_libraries = HashSet::new();
+        self.dependencies_impl(interp, &mut resolving_libraries, backend, all_edges)
+    }
+
+    fn dependencies_impl(
+        &self,
+        interp: &Path,
+        resolving_libraries: &mut HashSet<String>,
+        backend: &ResolverBackend,
+        all_edges: bool,
+    ) -> Result<Vec<Dependency>> {
+        let mut resolver = None;
+        let mut deps = Vec::new();
+        for lib in &self.libraries {
+            let (path, rule) = self.lookup_cached(lib, interp, backend, &mut resolver)?;
+            tracing::debug!(
+                name = %lib,
+                path = %path.display(),
+                %rule,
+                "exe: found shared object",
+            );
+
+            let baseline = self.baseline_of(&path, backend);
+            let dep = Dependency {
+                name: lib.to_owned(),
+                path,
+                required_by: self.path().to_owned(),
+                rule,
+                baseline,
+            };
+            if !resolving_libraries.contains(lib) {
+                resolving_libraries.insert(lib.to_owned());
+                let mut children =
+                    SharedObject::load_needed(&dep.path, self, interp, backend.cache())?
+                        .object()
+                        .dependencies_impl(interp, resolving_libraries, backend, all_edges)?;
+
+                deps.push(dep);
+                deps.append(&mut children);
+            } else if all_edges {
+                deps.push(dep);
+            }
+        }
+
+        Ok(deps)
+    }
+}
+
+/// An ELF file loaded as either an executable or a shared object, depending on whether it has `PT_INTERP`.
+#[derive(Debug)]
+pub enum ElfFile {
+    Executable(Executable),
+    SharedObject(SharedObject),
+}
+
+impl ElfFile {
+    pub fn load_in<P>(path: P, root: &Root, backend: &ResolverBackend) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let cache = backend.cache();
+        // shared objects with PT_INTERP, such as libc.so.6, can be run as executables as well
+        if cache
+            .object(&root.real_host_path(path))?
+            .interpreter
+            .is_some()
+        {
+            Executable::load_with_cache(path, root, cache).map(ElfFile::Executable)
+        } else {
+            SharedObject::load_with_cache(path, root, cache).map(ElfFile::SharedObject)
+        }
+    }
+
+    pub fn object(&self) -> &ElfObject {
+        match self {
+            ElfFile::Executable(exe) => exe.object(),
+            ElfFile::SharedObject(so) => so.object(),
+        }
+    }
+
+    /// The interpreter of the executable, or the dynamic linker that loads the shared object.
+    pub fn interpreter(&self) -> Option<&PathBuf> {
+        match self {
+            ElfFile::Executable(exe) => exe.interpreter(),
+            ElfFile::SharedObject(so) => so.loader(),
+        }
+    }
+
+    pub fn dependencies(&self, backend: &ResolverBackend) -> Result<Vec<Dependency>> {
+        match self {
+            ElfFile::Executable(exe) => exe.dependencies(backend),
+            ElfFile::SharedObject(so) => so.dependencies(backend),
+        }
+    }
+}
@@ -1,14 +1,22 @@
+use std::fmt;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{env, str};
+use std::{env, fs, str};
 
 use crate::base::log::CommandLogExt;
 use crate::base::{Error, Result};
-use crate::domain::executable::SearchPaths;
+use crate::domain::executable::{
+    musl, DependencyCache, ElfTarget, Hwcaps, LdConfig, MuslConfig, SearchPaths,
+};
+use crate::domain::Root;
 
+use sha2::{Digest, Sha256};
 use tempfile::NamedTempFile;
 
+// musl does not probe hwcap subdirectories
+static MUSL_HWCAPS: Hwcaps = Hwcaps::baseline();
+
 static GENERIC_RESOLVER_SOURCE_CODE: &str = r"
 #define _GNU_SOURCE
 #include <dlfcn.h>
@@ -100,106 +108,525 @@ impl ResolverProgram {
     }
 }
 
+/// The search rule that found a shared object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LookupRule {
+    /// The name contains a slash and is used as a path.
+    Path,
+    Rpath,
+    LdLibraryPath,
+    Runpath,
+    LdSoCache,
+    /// Directories in ld.so.conf, searched when ld.so.cache is not available.
+    LdSoConf,
+    DefaultDirs,
+    /// Provided by the musl dynamic linker itself.
+    MuslReserved,
+    MuslSysPaths,
+    /// Reported by ld.so through the dlopen(3) resolver program.
+    Dlopen,
+}
+
+impl LookupRule {
+    const ALL: &'static [LookupRule] = &[
+        LookupRule::Path,
+        LookupRule::Rpath,
+        LookupRule::LdLibraryPath,
+        LookupRule::Runpath,
+        LookupRule::LdSoCache,
+        LookupRule::LdSoConf,
+        LookupRule::DefaultDirs,
+        LookupRule::MuslReserved,
+        LookupRule::MuslSysPaths,
+        LookupRule::Dlopen,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LookupRule::Path => "path",
+            LookupRule::Rpath => "rpath",
+            LookupRule::LdLibraryPath => "ld_library_path",
+            LookupRule::Runpath => "runpath",
+            LookupRule::LdSoCache => "ld_so_cache",
+            LookupRule::LdSoConf => "ld_so_conf",
+            LookupRule::DefaultDirs => "default_dirs",
+            LookupRule::MuslReserved => "musl_reserved",
+            LookupRule::MuslSysPaths => "musl_sys_paths",
+            LookupRule::Dlopen => "dlopen",
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        LookupRule::ALL
+            .iter()
+            .copied()
+            .find(|rule| rule.as_str() == s)
+    }
+}
+
+impl fmt::Display for LookupRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug)]
-pub struct Resolver<'a> {
-    search_paths: &'a SearchPaths,
-    program: ResolverProgram,
+enum BackendKind {
+    Native {
+        config: LdConfig,
+        hwcaps: Hwcaps,
+    },
+    Dlopen {
+        cc_path: PathBuf,
+        noload: bool,
+        hwcaps: Hwcaps,
+    },
 }
 
-impl<'a> Resolver<'a> {
-    pub fn new<P, Q>(interp: P, search_paths: &'a SearchPaths, cc_path: Q) -> Result<Self>
+/// How shared objects that are not found in RPATH, LD_LIBRARY_PATH, and RUNPATH are looked up.
+/// The backend also holds the cache of parsed objects and lookups, which is shared by all executables
+/// resolved with it.
+#[derive(Debug)]
+pub struct ResolverBackend {
+    kind: BackendKind,
+    /// Identifies everything the backend looks up with, so that persisted lookups are not reused
+    /// with another configuration.
+    context: String,
+    cache: DependencyCache,
+}
+
+impl ResolverBackend {
+    /// Searches `ld.so.conf` and `ld.so.cache` in `root` in the same way as ld.so.
+    pub fn native(root: &Root, hwcaps: Hwcaps) -> Result<Self> {
+        let config = LdConfig::load(root)?;
+        // the configuration includes the directories in ld.so.conf and the entries of ld.so.cache
+        let context = sha256_hex(format!("native\0{:?}\0{:?}", config, hwcaps));
+        Ok(ResolverBackend {
+            kind: BackendKind::Native { config, hwcaps },
+            context,
+            cache: DependencyCache::new(),
+        })
+    }
+
+    /// Compiles and runs a program that loads shared objects with dlopen(3).
+    pub fn dlopen(cc: &str, noload: bool) -> Result<Self> {
+        let cc_path =
+            which::which(cc).map_err(|e| Error::ExecutableLocateFailed(cc.to_owned(), e))?;
+        // ld.so of the host probes the subdirectories supported by the host
+        let hwcaps = Hwcaps::host();
+        // ld.so of the host looks up ld.so.cache of the host, which reflects ld.so.conf
+        let ld_so_cache = match fs::read("/etc/ld.so.cache") {
+            Ok(content) => format!("{:x}", Sha256::digest(content)),
+            Err(_) => String::new(),
+        };
+        let context = sha256_hex(format!(
+            "dlopen\0{}\0{}\0{:?}\0{}",
+            cc_path.display(),
+            noload,
+            hwcaps,
+            ld_so_cache
+        ));
+        Ok(ResolverBackend {
+            kind: BackendKind::Dlopen {
+                cc_path,
+                noload,
+                hwcaps,
+            },
+            context,
+            cache: DependencyCache::new(),
+        })
+    }
+
+    /// Persists the cache in the file at `path`, reusing the results saved there by previous runs.
+    pub fn with_cache_file<P>(mut self, path: P) -> Result<Self>
     where
         P: AsRef<Path>,
-        Q: AsRef<Path>,
     {
-        let resolver = Resolver {
+        self.cache = DependencyCache::load(path)?;
+        Ok(self)
+    }
+
+    pub fn hwcaps(&self) -> &Hwcaps {
+        match &self.kind {
+            BackendKind::Native { hwcaps, .. } => hwcaps,
+            BackendKind::Dlopen { hwcaps, .. } => hwcaps,
+        }
+    }
+
+    pub fn cache(&self) -> &DependencyCache {
+        &self.cache
+    }
+
+    /// Writes the cache to the file given in `with_cache_file`, if any.
+    pub fn save_cache(&self) -> Result<()> {
+        self.cache.save()
+    }
+
+    /// A key that identifies the lookup of `name` with the backend, which is the same for lookups that
+    /// always find the same shared object unless files are added to the searched directories.
+    pub fn lookup_key(
+        &self,
+        name: &str,
+        interp: &Path,
+        search_paths: &SearchPaths,
+        target: ElfTarget,
+        root: &Root,
+    ) -> String {
+        // the path file of musl replaces the configuration of the backend
+        let musl_config = MuslConfig::load(root, interp).ok().flatten();
+        sha256_hex(format!(
+            "{}\0{}\0{}\0{:?}\0{:?}\0{:?}\0{:?}",
+            self.context,
+            name,
+            interp.display(),
             search_paths,
-            program: ResolverProgram::new(interp, cc_path)?,
-        };
+            target,
+            root,
+            musl_config
+        ))
+    }
 
-        tracing::debug!(?resolver, "resolver: created resolver");
-        Ok(resolver)
+    /// Looks up `name` only in RPATH, LD_LIBRARY_PATH, and RUNPATH, which the loader `interp` searches
+    /// before the other rules. A cached lookup is outdated if this finds another shared object, which
+    /// has been added to the search paths since.
+    pub fn lookup_search_paths(
+        &self,
+        name: &str,
+        interp: &Path,
+        search_paths: &SearchPaths,
+        target: ElfTarget,
+        root: &Root,
+    ) -> Option<PathBuf> {
+        let musl = musl::musl_arch(interp).is_some();
+        if name.contains('/') || (musl && is_musl_reserved(name)) {
+            return None;
+        }
+        let hwcaps = if musl { &MUSL_HWCAPS } else { self.hwcaps() };
+        lookup_search_paths(search_paths, hwcaps, target, root, musl, name).map(|(path, _)| path)
     }
+}
+
+fn sha256_hex(s: String) -> String {
+    format!("{:x}", Sha256::digest(s.as_bytes()))
+}
 
-    pub fn new_noload<P, Q>(interp: P, search_paths: &'a SearchPaths, cc_path: Q) -> Result<Self>
+#[derive(Debug)]
+enum RestLookup<'a> {
+    Native {
+        config: &'a LdConfig,
+        target: ElfTarget,
+    },
+    Musl {
+        config: MuslConfig,
+        interp: PathBuf,
+    },
+    Program(ResolverProgram),
+}
+
+#[derive(Debug)]
+pub struct Resolver<'a> {
+    search_paths: &'a SearchPaths,
+    hwcaps: &'a Hwcaps,
+    target: ElfTarget,
+    root: &'a Root,
+    rest: RestLookup<'a>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new<P>(
+        interp: P,
+        search_paths: &'a SearchPaths,
+        target: ElfTarget,
+        root: &'a Root,
+        backend: &'a ResolverBackend,
+    ) -> Result<Self>
     where
         P: AsRef<Path>,
-        Q: AsRef<Path>,
     {
+        if let Some(config) = MuslConfig::load(root, interp.as_ref())? {
+            if matches!(backend.kind, BackendKind::Dlopen { .. }) {
+                tracing::info!("resolver: searching shared objects by itself for musl executables");
+            }
+            let resolver = Resolver {
+                search_paths,
+                hwcaps: &MUSL_HWCAPS,
+                target,
+                root,
+                rest: RestLookup::Musl {
+                    config,
+                    interp: interp.as_ref().to_owned(),
+                },
+            };
+            tracing::debug!(?resolver, "resolver: created resolver for musl");
+            return Ok(resolver);
+        }
+
+        let rest = match &backend.kind {
+            BackendKind::Native { config, .. } => RestLookup::Native { config, target },
+            BackendKind::Dlopen {
+                cc_path,
+                noload: false,
+                ..
+            } => RestLookup::Program(ResolverProgram::new(interp, cc_path)?),
+            BackendKind::Dlopen {
+                cc_path,
+                noload: true,
+                ..
+            } => RestLookup::Program(ResolverProgram::new_noload(interp, cc_path)),
+        };
         let resolver = Resolver {
             search_paths,
-            program: ResolverProgram::new_noload(interp, cc_path),
+            hwcaps: backend.hwcaps(),
+            target,
+            root,
+            rest,
         };
 
         tracing::debug!(?resolver, "resolver: created resolver");
         Ok(resolver)
     }
 
-    // lookup_rpath --> lookup_env --> lookup_runpath --> lookup_rest
-    // TODO: take secure-execution mode into consideration
-    pub fn lookup(&self, name: &str) -> Result<PathBuf> {
-        if let Some(path) = self.lookup_rpath(name) {
-            tracing::debug!(%name, path = %path.display(), "resolver: found by Rpath");
-            return Ok(path);
+    // lookup_search_paths --> lookup_rest
+    // secure-execution mode is already applied to `search_paths`, and lookup_rest is not affected by it
+    pub fn lookup(&self, name: &str) -> Result<(PathBuf, LookupRule)> {
+        // names containing slashes are interpreted as paths by both glibc and musl
+        if name.contains('/') {
+            return lookup_path(name);
+        }
+
+        if let RestLookup::Musl { interp, .. } = &self.rest {
+            return self.lookup_musl(interp, name);
+        }
+
+        if let Some((path, rule)) = lookup_search_paths(
+            self.search_paths,
+            self.hwcaps,
+            self.target,
+            self.root,
+            false,
+            name,
+        ) {
+            tracing::debug!(%name, path = %path.display(), %rule, "resolver: found in search paths");
+            return Ok((path, rule));
+        }
+
+        self.lookup_rest(name)
+    }
+
+    // musl does not distinguish RPATH from RUNPATH, and both are inherited from the objects that need it
+    fn lookup_musl(&self, interp: &Path, name: &str) -> Result<(PathBuf, LookupRule)> {
+        if is_musl_reserved(name) {
+            tracing::debug!(%name, interp = %interp.display(), "resolver: provided by musl");
+            return Ok((interp.to_owned(), LookupRule::MuslReserved));
         }
 
-        if let Some(path) = self.lookup_env(name) {
+        if let Some((path, rule)) = lookup_search_paths(
+            self.search_paths,
+            self.hwcaps,
+            self.target,
+            self.root,
+            true,
+            name,
+        ) {
+            tracing::debug!(%name, path = %path.display(), %rule, "resolver: found in search paths");
+            return Ok((path, rule));
+        }
+
+        self.lookup_rest(name)
+    }
+
+    fn lookup_rest(&self, name: &str) -> Result<(PathBuf, LookupRule)> {
+        match &self.rest {
+            RestLookup::Native { config, target } => {
+                lookup_native(config, self.hwcaps, self.root, *target, name)
+            }
+            RestLookup::Musl { config, .. } => lookup_musl_sys_paths(config, self.root, name),
+            RestLookup::Program(program) => lookup_program(program, name),
+        }
+    }
+}
+
+// ld.so.cache --> default directories
+fn lookup_native(
+    config: &LdConfig,
+    hwcaps: &Hwcaps,
+    root: &Root,
+    target: ElfTarget,
+    name: &str,
+) -> Result<(PathBuf, LookupRule)> {
+    if let Some(cache) = config.cache() {
+        if let Some(entry) = hwcaps
+            .select(cache.lookup_entries(name))
+            .into_iter()
+            .find(|e| is_compatible(&e.value, root, target))
+        {
             tracing::debug!(
                 %name,
-                path = %path.display(),
-                "resolver: found by LD_LIBRARY_PATH",
+                path = %entry.value.display(),
+                hwcap = ?entry.hwcap,
+                "resolver: found in ld.so.cache",
             );
-            return Ok(path);
+            return Ok((entry.value.clone(), LookupRule::LdSoCache));
         }
+    } else if let Some(path) = config
+        .conf_dirs()
+        .iter()
+        .find_map(|dir| try_compatible(dir, name, hwcaps, root, target))
+    {
+        // ldconfig would have put the libraries in these directories into the cache
+        tracing::debug!(%name, path = %path.display(), "resolver: found by ld.so.conf");
+        return Ok((path, LookupRule::LdSoConf));
+    }
 
-        if let Some(path) = self.lookup_runpath(name) {
-            tracing::debug!(%name, path = %path.display(), "resolver: found by RunPath");
-            return Ok(path);
-        }
+    if let Some(path) = default_dirs(target)
+        .iter()
+        .find_map(|dir| try_compatible(dir, name, hwcaps, root, target))
+    {
+        tracing::debug!(%name, path = %path.display(), "resolver: found in default directories");
+        return Ok((path, LookupRule::DefaultDirs));
+    }
 
-        let path = self.lookup_rest(name)?;
-        tracing::debug!(%name, path = %path.display(), "resolver: found by ld.so");
+    Err(Error::SharedLibraryLookup(format!(
+        "{}: cannot find the shared object in ld.so.cache and default directories",
+        name
+    )))
+}
 
-        Ok(path)
+/// Relative paths are opened relative to the working directory of the process at runtime, which is
+/// unknown when bundling.
+fn lookup_path(name: &str) -> Result<(PathBuf, LookupRule)> {
+    if Path::new(name).is_absolute() {
+        Ok((name.into(), LookupRule::Path))
+    } else {
+        Err(Error::SharedLibraryLookup(format!(
+            "{}: relative paths depend on the working directory at runtime",
+            name
+        )))
     }
+}
 
-    fn lookup_rpath(&self, name: &str) -> Option<PathBuf> {
-        if self.search_paths.runpath().is_some() {
-            return None;
+/// Searches RPATH, LD_LIBRARY_PATH, and RUNPATH, which precede the other rules, in the order of the loader.
+/// glibc searches RPATH --> LD_LIBRARY_PATH --> RUNPATH, ignoring RPATH if RUNPATH is present, and musl
+/// searches LD_LIBRARY_PATH --> RUNPATH --> RPATH. glibc skips objects incompatible with `target`, while
+/// musl takes the first file found.
+fn lookup_search_paths(
+    search_paths: &SearchPaths,
+    hwcaps: &Hwcaps,
+    target: ElfTarget,
+    root: &Root,
+    musl: bool,
+    name: &str,
+) -> Option<(PathBuf, LookupRule)> {
+    let joined = |dir: &Path| {
+        if musl {
+            try_joined(dir, name, hwcaps, root)
+        } else {
+            try_compatible(dir, name, hwcaps, root, target)
         }
+    };
+    let rpath = || {
+        let path = search_paths.iter_rpaths().find_map(joined)?;
+        Some((path, LookupRule::Rpath))
+    };
+    let env = || {
+        let path = search_paths.iter_ld_library_paths().find_map(joined)?;
+        Some((path, LookupRule::LdLibraryPath))
+    };
+    let runpath = || {
+        let path = search_paths.iter_runpaths().find_map(joined)?;
+        Some((path, LookupRule::Runpath))
+    };
+
+    if musl {
+        env().or_else(runpath).or_else(rpath)
+    } else if search_paths.runpath().is_some() {
+        env().or_else(runpath)
+    } else {
+        rpath().or_else(env)
+    }
+}
+
+// musl implements these libraries in the dynamic linker itself, which is also libc.so
+fn is_musl_reserved(name: &str) -> bool {
+    const RESERVED: &[&str] = &["c", "pthread", "rt", "m", "dl", "util", "xnet"];
+    let lib = name
+        .strip_prefix("lib")
+        .and_then(|rest| rest.split_once('.'));
+    matches!(lib, Some((lib, _)) if RESERVED.contains(&lib))
+}
 
-        self.search_paths
-            .iter_rpaths()
-            .find_map(|x| try_joined(x, name))
+// unlike glibc, musl takes the first file found without checking if it is compatible
+fn lookup_musl_sys_paths(
+    config: &MuslConfig,
+    root: &Root,
+    name: &str,
+) -> Result<(PathBuf, LookupRule)> {
+    if let Some(path) = config
+        .sys_paths()
+        .iter()
+        .find_map(|dir| try_joined(dir, name, &MUSL_HWCAPS, root))
+    {
+        tracing::debug!(%name, path = %path.display(), "resolver: found in musl system paths");
+        return Ok((path, LookupRule::MuslSysPaths));
     }
 
-    fn lookup_runpath(&self, name: &str) -> Option<PathBuf> {
-        self.search_paths
-            .iter_runpaths()
-            .find_map(|x| try_joined(x, name))
+    Err(Error::SharedLibraryLookup(format!(
+        "{}: cannot find the shared object in musl system paths",
+        name
+    )))
+}
+
+fn lookup_program(program: &ResolverProgram, name: &str) -> Result<(PathBuf, LookupRule)> {
+    let program_path = program.setup_for(name)?;
+    let output = Command::new(&program_path)
+        .arg(name)
+        .env_clear()
+        .output_with_log()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        return Err(Error::SharedLibraryLookup(stderr));
     }
 
-    fn lookup_env(&self, name: &str) -> Option<PathBuf> {
-        self.search_paths
-            .iter_ld_library_paths()
-            .find_map(|x| try_joined(x, name))
+    let path: PathBuf = str::from_utf8(&output.stdout)?.trim().into();
+    tracing::debug!(%name, path = %path.display(), "resolver: found by ld.so");
+    Ok((path, LookupRule::Dlopen))
+}
+
+fn default_dirs(target: ElfTarget) -> &'static [&'static str] {
+    match target.lib_dir() {
+        "lib64" => &["/lib64", "/usr/lib64", "/lib", "/usr/lib"],
+        "libx32" => &["/libx32", "/usr/libx32", "/lib", "/usr/lib"],
+        _ => &["/lib", "/usr/lib"],
     }
+}
 
-    fn lookup_rest(&self, name: &str) -> Result<PathBuf> {
-        let program_path = self.program.setup_for(name)?;
-        let output = Command::new(&program_path)
-            .arg(name)
-            .env_clear()
-            .output_with_log()?;
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-            return Err(Error::SharedLibraryLookup(stderr));
+fn is_compatible(path: &Path, root: &Root, target: ElfTarget) -> bool {
+    match ElfTarget::read(root.real_host_path(path)) {
+        Ok(Some(found)) if found == target => true,
+        Ok(Some(found)) => {
+            tracing::debug!(path = %path.display(), ?found, ?target, "resolver: skipping incompatible object");
+            false
         }
-
-        Ok(str::from_utf8(&output.stdout)?.trim().to_string().into())
+        _ => false,
     }
 }
 
+fn try_compatible<P>(
+    dir: P,
+    name: &str,
+    hwcaps: &Hwcaps,
+    root: &Root,
+    target: ElfTarget,
+) -> Option<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    hwcaps
+        .candidates(dir.as_ref(), name)
+        .into_iter()
+        .find(|path| is_compatible(path, root, target))
+}
+
 fn calc_resolver_program_path<P, Q>(interp: P, cc_path: Q, name: &str) -> PathBuf
 where
     P: AsRef<Path>,
@@ -287,15 +714,320 @@ where
     Ok(())
 }
 
-fn try_joined<P, Q>(path1: P, path2: Q) -> Option<PathBuf>
+fn try_joined<P>(dir: P, name: &str, hwcaps: &Hwcaps, root: &Root) -> Option<PathBuf>
 where
     P: AsRef<Path>,
-    Q: AsRef<Path>,
 {
-    let joined = path1.as_ref().join(path2);
-    if joined.exists() {
-        Some(joined)
-    } else {
-        None
+    hwcaps
+        .candidates(dir.as_ref(), name)
+        .into_iter()
+        .find(|path| root.real_host_path(path).exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+    use std::fs;
+
+    #[test]
+    fn test_lookup_native_conf_dirs() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let exe = env::current_exe()?;
+        let target = ElfTarget::read(&exe)?.unwrap();
+
+        // not an ELF file, which must be skipped
+        dir.child("a/libfoo.so.1").write_str("not an ELF file")?;
+        dir.child("b").create_dir_all()?;
+        fs::copy(&exe, dir.child("b/libfoo.so.1").path())?;
+        dir.child("ld.so.conf").write_str(&format!(
+            "{}\n{}\n",
+            dir.child("a").path().display(),
+            dir.child("b").path().display()
+        ))?;
+
+        let root = Root::host();
+        let config = LdConfig::load_from(
+            &root,
+            dir.child("ld.so.conf").path(),
+            dir.child("ld.so.cache").path(),
+        )?;
+        let hwcaps = Hwcaps::baseline();
+        assert_eq!(
+            lookup_native(&config, &hwcaps, &root, target, "libfoo.so.1")?,
+            (
+                dir.child("b/libfoo.so.1").path().to_owned(),
+                LookupRule::LdSoConf
+            )
+        );
+        assert!(lookup_native(&config, &hwcaps, &root, target, "libnonexistent.so.1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_lookup_native_glibc_hwcaps() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let exe = env::current_exe()?;
+        let target = ElfTarget::read(&exe)?.unwrap();
+
+        dir.child("glibc-hwcaps/x86-64-v3").create_dir_all()?;
+        for path in ["libfoo.so.1", "glibc-hwcaps/x86-64-v3/libfoo.so.1"] {
+            fs::copy(&exe, dir.child(path).path())?;
+        }
+        dir.child("ld.so.conf")
+            .write_str(&format!("{}\n", dir.path().display()))?;
+
+        let root = Root::host();
+        let config = LdConfig::load_from(
+            &root,
+            dir.child("ld.so.conf").path(),
+            dir.child("ld.so.cache").path(),
+        )?;
+        assert_eq!(
+            lookup_native(
+                &config,
+                &Hwcaps::level("x86-64-v4", target)?,
+                &root,
+                target,
+                "libfoo.so.1"
+            )?
+            .0,
+            dir.child("glibc-hwcaps/x86-64-v3/libfoo.so.1").path()
+        );
+        assert_eq!(
+            lookup_native(
+                &config,
+                &Hwcaps::level("x86-64-v2", target)?,
+                &root,
+                target,
+                "libfoo.so.1"
+            )?
+            .0,
+            dir.child("libfoo.so.1").path()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_rpath_incompatible() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::domain::executable::Tokens;
+
+        let dir = assert_fs::TempDir::new()?;
+        let exe = env::current_exe()?;
+        let target = ElfTarget::read(&exe)?.unwrap();
+        // not an ELF file of the target, which glibc skips
+        dir.child("a/libfoo.so.1").write_str("not an ELF file")?;
+        dir.child("b").create_dir_all()?;
+        fs::copy(&exe, dir.child("b/libfoo.so.1").path())?;
+
+        let root = Root::new(dir.path())?;
+        let backend = ResolverBackend::native(&root, Hwcaps::baseline())?;
+        let mut search_paths =
+            SearchPaths::new(Tokens::new("/usr/bin".into(), target), false, true);
+        search_paths.append_rpath(["/a", "/b"]);
+        let interp = target.default_interpreters()[0];
+        let resolver = Resolver::new(interp, &search_paths, target, &root, &backend)?;
+        assert_eq!(
+            resolver.lookup("libfoo.so.1")?,
+            ("/b/libfoo.so.1".into(), LookupRule::Rpath)
+        );
+        assert_eq!(
+            backend.lookup_search_paths(
+                "libfoo.so.1",
+                Path::new(interp),
+                &search_paths,
+                target,
+                &root
+            ),
+            Some("/b/libfoo.so.1".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_path() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::domain::executable::Tokens;
+
+        let dir = assert_fs::TempDir::new()?;
+        let target = ElfTarget::read(env::current_exe()?)?.unwrap();
+        dir.child("rpath/plugins/libfoo.so").touch()?;
+
+        let root = Root::new(dir.path())?;
+        let backend = ResolverBackend::native(&root, Hwcaps::baseline())?;
+        let mut search_paths =
+            SearchPaths::new(Tokens::new("/usr/bin".into(), target), false, true);
+        search_paths.append_rpath(["/rpath"]);
+        let glibc_interp = target.default_interpreters()[0].to_owned();
+        let musl_interp = format!("/lib/ld-musl-{}.so.1", target.musl_arch().unwrap());
+        for interp in [glibc_interp, musl_interp] {
+            let resolver = Resolver::new(&interp, &search_paths, target, &root, &backend)?;
+            assert_eq!(
+                resolver.lookup("/opt/libfoo.so")?,
+                ("/opt/libfoo.so".into(), LookupRule::Path)
+            );
+            // relative paths are not searched in RPATH, but opened from the working directory
+            assert!(matches!(
+                resolver.lookup("plugins/libfoo.so"),
+                Err(Error::SharedLibraryLookup(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_lookup_musl() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::domain::executable::Tokens;
+
+        let dir = assert_fs::TempDir::new()?;
+        let target = ElfTarget::read(env::current_exe()?)?.unwrap();
+        dir.child("lib/ld-musl-x86_64.so.1").touch()?;
+        dir.child("etc/ld-musl-x86_64.path")
+            .write_str("/opt/lib\n")?;
+        // musl does not check whether the files are ELF objects of the target
+        for path in [
+            "opt/lib/libfoo.so.1",
+            "lib/libbar.so.1",
+            "rpath/libbar.so.1",
+        ] {
+            dir.child(path).touch()?;
+        }
+
+        let root = Root::new(dir.path())?;
+        let backend = ResolverBackend::native(&root, Hwcaps::level("x86-64-v3", target)?)?;
+        let mut search_paths =
+            SearchPaths::new(Tokens::new("/usr/bin".into(), target), false, true);
+        search_paths.append_rpath(["/rpath"]);
+        let resolver = Resolver::new(
+            "/lib/ld-musl-x86_64.so.1",
+            &search_paths,
+            target,
+            &root,
+            &backend,
+        )?;
+        assert_eq!(
+            resolver.lookup("libfoo.so.1")?,
+            ("/opt/lib/libfoo.so.1".into(), LookupRule::MuslSysPaths)
+        );
+        assert_eq!(
+            resolver.lookup("libbar.so.1")?,
+            ("/rpath/libbar.so.1".into(), LookupRule::Rpath)
+        );
+        // the path file replaces the default paths such as /lib
+        search_paths = SearchPaths::new(Tokens::new("/usr/bin".into(), target), false, true);
+        let resolver = Resolver::new(
+            "/lib/ld-musl-x86_64.so.1",
+            &search_paths,
+            target,
+            &root,
+            &backend,
+        )?;
+        assert!(resolver.lookup("libbar.so.1").is_err());
+        assert_eq!(
+            resolver.lookup("libpthread.so.0")?,
+            ("/lib/ld-musl-x86_64.so.1".into(), LookupRule::MuslReserved)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cached_lookup_shadowed() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::domain::Executable;
+
+        let cc = which::which("cc")?;
+
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("foo.c")
+            .write_str("int foo(void) { return 0; }\n")?;
+        dir.child("main.c")
+            .write_str("int foo(void);\nint main(void) { return foo(); }\n")?;
+        dir.child("a").create_dir_all()?;
+        dir.child("b").create_dir_all()?;
+        let status = Command::new(&cc)
+            .current_dir(dir.path())
+            .args([
+                "-shared",
+                "-fPIC",
+                "-Wl,-soname,libfoo.so.1",
+                "-o",
+                "b/libfoo.so.1",
+                "foo.c",
+            ])
+            .status()?;
+        assert!(status.success());
+        let status = Command::new(&cc)
+            .current_dir(dir.path())
+            .args([
+                "-o",
+                "main",
+                "main.c",
+                "b/libfoo.so.1",
+                "-Wl,-rpath,$ORIGIN/a:$ORIGIN/b",
+            ])
+            .status()?;
+        assert!(status.success());
+
+        let exe = Executable::load(dir.child("main").path())?;
+        let cache_path = dir.child("cache.json");
+        let resolve = || -> Result<Vec<PathBuf>> {
+            let backend = ResolverBackend::native(&Root::host(), Hwcaps::baseline())?
+                .with_cache_file(cache_path.path())?;
+            let libraries = exe.dynamic_libraries(&backend)?;
+            backend.save_cache()?;
+            Ok(libraries)
+        };
+        assert!(resolve()?.contains(&dir.child("b/libfoo.so.1").path().to_owned()));
+
+        // the library added to the directory searched first is found instead of the cached one
+        fs::copy(
+            dir.child("b/libfoo.so.1").path(),
+            dir.child("a/libfoo.so.1").path(),
+        )?;
+        let libraries = resolve()?;
+        assert!(libraries.contains(&dir.child("a/libfoo.so.1").path().to_owned()));
+        assert!(!libraries.contains(&dir.child("b/libfoo.so.1").path().to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "requires musl-gcc"]
+    fn test_musl_toolchain() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::domain::Executable;
+
+        let musl_gcc = which::which("musl-gcc")?;
+
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("foo.c")
+            .write_str("int foo(void) { return 0; }\n")?;
+        dir.child("main.c")
+            .write_str("int foo(void);\nint main(void) { return foo(); }\n")?;
+        dir.child("lib").create_dir_all()?;
+        let status = Command::new(&musl_gcc)
+            .current_dir(dir.path())
+            .args(["-shared", "-fPIC", "-o", "lib/libfoo.so", "foo.c"])
+            .status()?;
+        assert!(status.success());
+        let status = Command::new(&musl_gcc)
+            .current_dir(dir.path())
+            .args([
+                "-o",
+                "main",
+                "main.c",
+                "-Llib",
+                "-lfoo",
+                "-Wl,-rpath,$ORIGIN/lib",
+            ])
+            .status()?;
+        assert!(status.success());
+
+        let exe = Executable::load(dir.child("main").path())?;
+        assert!(exe.is_musl());
+        let backend = ResolverBackend::native(&Root::host(), Hwcaps::host())?;
+        let libraries = exe.dynamic_libraries(&backend)?;
+        assert!(libraries.contains(&dir.child("lib/libfoo.so").path().to_owned()));
+        // libc.so is the dynamic linker itself
+        assert!(libraries.contains(exe.interpreter().unwrap()));
+        Ok(())
     }
 }
@@ -1,28 +1,57 @@
 use std::ffi::{OsStr, OsString};
-use std::io;
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
-use crate::base::{Error, Result};
+use crate::domain::executable::ElfTarget;
+
+/// Values of dynamic string tokens, which depend on the executable and the target it runs on.
+#[derive(Hash, Default, Debug, Clone)]
+pub struct Tokens {
+    origin: OsString,
+    platform: Option<OsString>,
+    lib: OsString,
+    multiarch: Option<OsString>,
+}
+
+impl Tokens {
+    pub fn new(origin: OsString, target: ElfTarget) -> Self {
+        Tokens {
+            origin,
+            platform: target.platform(),
+            lib: target.lib_dir().into(),
+            multiarch: target.multiarch().map(Into::into),
+        }
+    }
+}
 
 #[derive(Hash, Default, Debug)]
 pub struct SearchPaths {
     rpath: Option<Vec<PathBuf>>,
     runpath: Option<Vec<PathBuf>>,
     ld_library_path: Option<Vec<PathBuf>>,
-    platform: OsString,
-    origin: OsString,
+    tokens: Tokens,
+    secure: bool,
+    main: bool,
 }
 
 impl SearchPaths {
-    pub fn new(origin: OsString) -> Result<Self> {
-        Ok(SearchPaths {
+    /// Creates empty search paths. `secure` enables the rules of secure-execution mode (see ld.so(8)),
+    /// which applies to set-user-ID, set-group-ID, and capability-enabled executables.
+    /// `main` tells whether the paths belong to the executable itself, whose `$ORIGIN` must expand into a
+    /// trusted directory in that mode.
+    pub fn new(tokens: Tokens, secure: bool, main: bool) -> Self {
+        SearchPaths {
             rpath: None,
             runpath: None,
             ld_library_path: None,
-            platform: auxv_platform()?,
-            origin,
-        })
+            tokens,
+            secure,
+            main,
+        }
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.secure
     }
 
     pub fn rpath(&self) -> Option<&Vec<PathBuf>> {
@@ -49,17 +78,24 @@ impl SearchPaths {
             .flat_map(|v| v.iter().map(AsRef::as_ref))
     }
 
-    fn append<I, S>(paths: &mut Option<Vec<PathBuf>>, other: I, origin: &OsStr, platform: &OsStr)
-    where
+    fn append<I, S>(
+        paths: &mut Option<Vec<PathBuf>>,
+        other: I,
+        tokens: &Tokens,
+        secure: bool,
+        main: bool,
+    ) where
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
         let inner = paths.get_or_insert(Vec::new());
-        inner.extend(
-            other
-                .into_iter()
-                .map(|x| expand_tokens(x, origin, platform)),
-        )
+        inner.extend(other.into_iter().filter_map(|x| {
+            if secure {
+                expand_tokens_secure(x, tokens, main)
+            } else {
+                Some(expand_tokens(x, tokens))
+            }
+        }))
     }
 
     pub fn append_rpath<I, S>(&mut self, rpath: I)
@@ -67,11 +103,7 @@ impl SearchPaths {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        let SearchPaths {
-            origin, platform, ..
-        } = self;
-
-        Self::append(&mut self.rpath, rpath, origin, platform)
+        Self::append(&mut self.rpath, rpath, &self.tokens, self.secure, self.main)
     }
 
     pub fn append_runpath<I, S>(&mut self, runpath: I)
@@ -79,11 +111,13 @@ impl SearchPaths {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        let SearchPaths {
-            origin, platform, ..
-        } = self;
-
-        Self::append(&mut self.runpath, runpath, origin, platform)
+        Self::append(
+            &mut self.runpath,
+            runpath,
+            &self.tokens,
+            self.secure,
+            self.main,
+        )
     }
 
     pub fn append_ld_library_path<I, S>(&mut self, ld_library_path: I)
@@ -91,27 +125,31 @@ impl SearchPaths {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        let SearchPaths {
-            origin, platform, ..
-        } = self;
+        if self.secure {
+            tracing::info!("search_paths: ignoring LD_LIBRARY_PATH in secure-execution mode");
+            return;
+        }
 
-        Self::append(&mut self.ld_library_path, ld_library_path, origin, platform)
+        Self::append(
+            &mut self.ld_library_path,
+            ld_library_path,
+            &self.tokens,
+            false,
+            self.main,
+        )
     }
 }
 
-fn expand_tokens<S, T, U>(input: S, origin: T, platform: U) -> PathBuf
+fn expand_tokens<S>(input: S, tokens: &Tokens) -> PathBuf
 where
     S: AsRef<OsStr>,
-    T: AsRef<OsStr>,
-    U: AsRef<OsStr>,
 {
     let input = input.as_ref();
 
     let mut result = OsString::new();
     result.reserve(input.len());
 
-    let mut buffer = Vec::new();
-    buffer.reserve(16);
+    let mut buffer = Vec::with_capacity(16);
 
     enum ParseState {
         Standby,
@@ -130,20 +168,20 @@ where
             }
             (ParseState::Scanning, b'{') if buffer.is_empty() => ParseState::ScanningBraced,
             (ParseState::Scanning, b'$') => {
-                result.push(substitute(&buffer, &origin, &platform));
+                result.push(substitute(&buffer, tokens));
                 buffer.clear();
 
                 ParseState::Scanning
             }
             (ParseState::Scanning, b'/') => {
-                result.push(substitute(&buffer, &origin, &platform));
+                result.push(substitute(&buffer, tokens));
                 buffer.clear();
 
                 buffer.push(*b);
                 ParseState::Standby
             }
             (ParseState::ScanningBraced, b'}') => {
-                result.push(substitute(&buffer, &origin, &platform));
+                result.push(substitute(&buffer, tokens));
                 buffer.clear();
                 ParseState::Standby
             }
@@ -162,7 +200,7 @@ where
             result.push(OsStr::from_bytes(&buffer));
         }
         ParseState::Scanning => {
-            result.push(substitute(&buffer, &origin, &platform));
+            result.push(substitute(&buffer, tokens));
         }
         ParseState::Standby => {
             result.push(OsStr::from_bytes(&buffer));
@@ -180,106 +218,225 @@ where
     result.into()
 }
 
-fn substitute<S, T>(s: &[u8], origin: S, platform: T) -> OsString
+/// Expands tokens in the same way as ld.so in secure-execution mode.
+/// `$ORIGIN` is only allowed as the first component, and must expand into a trusted directory if
+/// `check_trusted` is set for the main executable, otherwise the whole path is ignored.
+fn expand_tokens_secure<S>(input: S, tokens: &Tokens, check_trusted: bool) -> Option<PathBuf>
 where
     S: AsRef<OsStr>,
-    T: AsRef<OsStr>,
 {
-    match s {
-        b"ORIGIN" => origin.as_ref().to_owned(),
-        b"LIB" => match is_64bit(&platform) {
-            Some(true) => OsStr::new("lib64").to_owned(),
-            Some(false) => OsStr::new("lib").to_owned(),
-            None => {
-                tracing::warn!(
-                    platform = %platform.as_ref().to_string_lossy(),
-                    "search_paths: assuming 32-bit platform",
-                );
-                OsStr::new("lib").to_owned()
+    let input = input.as_ref();
+    let bytes = input.as_bytes();
+    let origin_count =
+        count_occurrences(bytes, b"$ORIGIN") + count_occurrences(bytes, b"${ORIGIN}");
+    let leading_origin = [&b"$ORIGIN"[..], b"${ORIGIN}"].iter().any(|token| {
+        bytes.starts_with(token) && matches!(bytes.get(token.len()), None | Some(b'/'))
+    });
+
+    if origin_count > 1 || (origin_count == 1 && !leading_origin) {
+        tracing::info!(
+            path = %input.to_string_lossy(),
+            "search_paths: ignoring $ORIGIN not at the beginning in secure-execution mode",
+        );
+        return None;
+    }
+
+    let expanded = expand_tokens(input, tokens);
+    if check_trusted && origin_count == 1 && !is_trusted(&expanded, tokens) {
+        tracing::info!(
+            path = %input.to_string_lossy(),
+            expanded = %expanded.display(),
+            "search_paths: ignoring $ORIGIN expanded into untrusted directory in secure-execution mode",
+        );
+        return None;
+    }
+    Some(expanded)
+}
+
+fn count_occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack
+        .windows(needle.len())
+        .filter(|window| *window == needle)
+        .count()
+}
+
+// Directories that $ORIGIN may expand into in secure-execution mode. ld.so only trusts the system
+// directories it is configured with, which are the multiarch directories on Debian and its derivatives
+// and the $LIB directories elsewhere.
+fn trusted_dirs(tokens: &Tokens) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(multiarch) = &tokens.multiarch {
+        dirs.push(Path::new("/lib").join(multiarch));
+        dirs.push(Path::new("/usr/lib").join(multiarch));
+    }
+    dirs.push(Path::new("/").join(&tokens.lib));
+    dirs.push(Path::new("/usr").join(&tokens.lib));
+    dirs
+}
+
+fn is_trusted(path: &Path, tokens: &Tokens) -> bool {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
             }
-        },
-        b"PLATFORM" => platform.as_ref().to_owned(),
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    trusted_dirs(tokens)
+        .iter()
+        .any(|dir| normalized.starts_with(dir))
+}
+
+fn substitute(s: &[u8], tokens: &Tokens) -> OsString {
+    match (s, &tokens.platform) {
+        (b"ORIGIN", _) => tokens.origin.clone(),
+        (b"LIB", _) => tokens.lib.clone(),
+        (b"PLATFORM", Some(platform)) => platform.clone(),
+        (b"PLATFORM", None) => {
+            tracing::warn!("search_paths: $PLATFORM is unknown for the target, leaving as it is");
+            OsStr::new("$PLATFORM").to_owned()
+        }
         _ => {
             tracing::warn!(
                 token = %format!("${}", String::from_utf8_lossy(s)),
                 "search_paths: unknown dynamic string token",
             );
-            OsString::from_vec([&[b'$'], s].concat().to_vec())
+            OsString::from_vec([b"$", s].concat().to_vec())
         }
     }
 }
 
-fn is_64bit<S>(platform: S) -> Option<bool>
-where
-    S: AsRef<OsStr>,
-{
-    match platform.as_ref().to_string_lossy().as_ref() {
-        "x86_64" | "amd64" | "aarch64" => Some(true),
-        "i386" | "i686" | "x86" | "arm" => Some(false),
-        _ => None,
-    }
-}
-
-fn auxv_platform() -> Result<OsString> {
-    let mut reader = crt0stack::Reader::from_environ().done();
-    let platform = reader
-        .find_map(|entry| {
-            if let crt0stack::Entry::Platform(platform) = entry {
-                Some(platform)
-            } else {
-                None
-            }
-        })
-        .ok_or_else(|| {
-            Error::IO(io::Error::new(
-                io::ErrorKind::NotFound,
-                "could not find AT_PLATFORM auxval",
-            ))
-        })?;
-    tracing::debug!(%platform, "search_paths: read platform from auxv");
-    Ok(platform.into())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn tokens(origin: &str, platform: Option<&str>, lib: &str) -> Tokens {
+        Tokens {
+            origin: origin.into(),
+            platform: platform.map(Into::into),
+            lib: lib.into(),
+            multiarch: None,
+        }
+    }
+
     #[test]
     fn test_substitute() {
         let origin = "/home/user/";
         let platform = "x86_64";
-        assert_eq!(substitute(b"ORIGIN", origin, platform), origin);
-        assert_eq!(substitute(b"PLATFORM", origin, platform), platform);
-
-        assert_eq!(substitute(b"LIB", origin, "x86_64"), "lib64");
-        assert_eq!(substitute(b"LIB", origin, "x86"), "lib");
-
-        assert_eq!(substitute(b"WTF", origin, platform), "$WTF");
+        let t = tokens(origin, Some(platform), "lib64");
+        assert_eq!(substitute(b"ORIGIN", &t), origin);
+        assert_eq!(substitute(b"PLATFORM", &t), platform);
+        assert_eq!(substitute(b"LIB", &t), "lib64");
+        assert_eq!(substitute(b"WTF", &t), "$WTF");
+
+        let t = tokens(origin, None, "lib");
+        assert_eq!(substitute(b"LIB", &t), "lib");
+        assert_eq!(substitute(b"PLATFORM", &t), "$PLATFORM");
     }
 
     #[test]
     fn test_expand_tokens() {
         let origin = "/home/user/";
         let platform = "x86_64";
+        let t = tokens(origin, Some(platform), "lib64");
         assert_eq!(
-            expand_tokens("$ORIGIN/$LIB", origin, platform),
+            expand_tokens("$ORIGIN/$LIB", &t),
             PathBuf::from(format!("{}/lib64", origin))
         );
         assert_eq!(
-            expand_tokens("${ORIGIN}/${LIB}", origin, platform),
+            expand_tokens("${ORIGIN}/${LIB}", &t),
             PathBuf::from(format!("{}/lib64", origin))
         );
         assert_eq!(
-            expand_tokens("/$ORIGIN$LIB", origin, platform),
+            expand_tokens("/$ORIGIN$LIB", &t),
             PathBuf::from(format!("/{}lib64", origin))
         );
         assert_eq!(
-            expand_tokens("/lib/$PLATFORM", origin, platform),
+            expand_tokens("/lib/$PLATFORM", &t),
             PathBuf::from(format!("/lib/{}", platform))
         );
         assert_eq!(
-            expand_tokens("${PLATFORM}${ORIGIN}", origin, platform),
+            expand_tokens("${PLATFORM}${ORIGIN}", &t),
             PathBuf::from(format!("{}{}", platform, origin))
         );
     }
+
+    #[test]
+    fn test_expand_tokens_secure() {
+        let t = |origin| Tokens {
+            multiarch: Some("x86_64-linux-gnu".into()),
+            ..tokens(origin, Some("x86_64"), "lib64")
+        };
+        assert_eq!(
+            expand_tokens_secure("$ORIGIN/../lib64", &t("/usr/bin"), true),
+            Some(PathBuf::from("/usr/bin/../lib64"))
+        );
+        assert_eq!(
+            expand_tokens_secure("${ORIGIN}", &t("/usr/lib/x86_64-linux-gnu/foo"), true),
+            Some(PathBuf::from("/usr/lib/x86_64-linux-gnu/foo"))
+        );
+        // subdirectories of /usr/lib other than the system directories are not trusted
+        assert_eq!(
+            expand_tokens_secure("${ORIGIN}", &t("/usr/lib/foo"), true),
+            None
+        );
+        assert_eq!(
+            expand_tokens_secure("$ORIGIN/../lib", &t("/usr/bin"), true),
+            None
+        );
+        assert_eq!(
+            expand_tokens_secure("/opt/$LIB", &t("/home/user"), true),
+            Some(PathBuf::from("/opt/lib64"))
+        );
+        assert_eq!(
+            expand_tokens_secure("$ORIGIN/lib", &t("/home/user"), true),
+            None
+        );
+        assert_eq!(
+            expand_tokens_secure("$ORIGIN/../../home", &t("/usr/bin"), true),
+            None
+        );
+        assert_eq!(
+            expand_tokens_secure("/usr/lib/$ORIGIN", &t("/usr/lib"), true),
+            None
+        );
+        // only the position of $ORIGIN is checked without `check_trusted`
+        assert_eq!(
+            expand_tokens_secure("$ORIGIN/lib", &t("/home/user"), false),
+            Some(PathBuf::from("/home/user/lib"))
+        );
+        assert_eq!(
+            expand_tokens_secure("/opt/$ORIGIN", &t("/home/user"), false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_secure_ignores_ld_library_path() {
+        let mut paths = SearchPaths::new(tokens("/usr/bin", Some("x86_64"), "lib64"), true, true);
+        paths.append_ld_library_path(["/home/user/lib"]);
+        paths.append_runpath(["$ORIGIN/../lib64", "$ORIGIN/../../home/user/lib"]);
+        assert_eq!(paths.iter_ld_library_paths().count(), 0);
+        assert_eq!(
+            paths.iter_runpaths().collect::<Vec<_>>(),
+            [Path::new("/usr/bin/../lib64")]
+        );
+    }
+
+    #[test]
+    fn test_secure_shared_object_origin() {
+        // $ORIGIN of shared objects may expand into untrusted directories
+        let mut paths =
+            SearchPaths::new(tokens("/opt/app/lib", Some("x86_64"), "lib64"), true, false);
+        paths.append_ld_library_path(["/home/user/lib"]);
+        paths.append_runpath(["$ORIGIN/plugins", "/opt/$ORIGIN"]);
+        assert_eq!(paths.iter_ld_library_paths().count(), 0);
+        assert_eq!(
+            paths.iter_runpaths().collect::<Vec<_>>(),
+            [Path::new("/opt/app/lib/plugins")]
+        );
+    }
 }
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::executable::{
+    Dependency, DependencyCache, ElfObject, Executable, ExecutableLocation, ResolverBackend,
+};
+use crate::domain::Root;
+
+/// A shared object, either required by others in `DT_NEEDED`, loaded with dlopen(3), or loaded on its own.
+#[derive(Debug)]
+pub struct SharedObject {
+    object: ElfObject,
+    /// The dynamic linker that loads the shared object, which is the interpreter of the executable.
+    loader: Option<PathBuf>,
+}
+
+impl SharedObject {
+    /// Loads the shared object at `path` in `root` on its own, such as a plugin. It is assumed to be
+    /// loaded by the dynamic linker in its `PT_INTERP` or the default one for its target.
+    pub fn load_in<P>(path: P, root: &Root, backend: &ResolverBackend) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        SharedObject::load_with_cache(path.as_ref(), root, backend.cache())
+    }
+
+    pub(super) fn load_with_cache(
+        path: &Path,
+        root: &Root,
+        cache: &DependencyCache,
+    ) -> Result<Self> {
+        let location = ExecutableLocation::Fixed(path.to_owned());
+        let (object, interp) = ElfObject::load(location, None, false, false, root.clone(), cache)?;
+        let loader = interp.or_else(|| object.default_interpreter());
+        Ok(SharedObject { object, loader })
+    }
+
+    /// Loads the shared object at `path` loaded into `exe`, such as the one opened with dlopen(3) from it.
+    pub fn load_for<P>(path: P, exe: &Executable, backend: &ResolverBackend) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let location = ExecutableLocation::Fixed(path.as_ref().to_owned());
+        let (object, _) = ElfObject::load(
+            location,
+            None,
+            exe.is_secure(),
+            false,
+            exe.root().clone(),
+            backend.cache(),
+        )?;
+        Ok(SharedObject {
+            object,
+            loader: exe.interpreter().cloned(),
+        })
+    }
+
+    /// Loads the shared object at `path` required by `dependent`, which inherits the search paths of it.
+    pub(super) fn load_needed(
+        path: &Path,
+        dependent: &ElfObject,
+        loader: &Path,
+        cache: &DependencyCache,
+    ) -> Result<Self> {
+        let location = ExecutableLocation::Fixed(path.to_owned());
+        let (object, _) = ElfObject::load(
+            location,
+            dependent.propagated_rpaths(loader),
+            dependent.is_secure(),
+            false,
+            dependent.root().clone(),
+            cache,
+        )?;
+        Ok(SharedObject {
+            object,
+            loader: Some(loader.to_owned()),
+        })
+    }
+
+    pub fn object(&self) -> &ElfObject {
+        &self.object
+    }
+
+    pub fn path(&self) -> &Path {
+        self.object.path()
+    }
+
+    pub fn soname(&self) -> Option<&str> {
+        self.object.soname()
+    }
+
+    pub fn loader(&self) -> Option<&PathBuf> {
+        self.loader.as_ref()
+    }
+
+    /// Looks up the shared object `name` in the same way as dlopen(3) called from this shared object.
+    pub fn resolve(&self, name: &str, backend: &ResolverBackend) -> Result<PathBuf> {
+        let loader = self.loader.as_ref().ok_or(Error::InterpretorNotFound)?;
+        self.object.resolve(name, loader, backend)
+    }
+
+    pub fn dependencies(&self, backend: &ResolverBackend) -> Result<Vec<Dependency>> {
+        self.object
+            .dependencies(self.loader.as_deref(), backend, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ElfFile;
+    use std::env;
+
+    #[test]
+    fn test_load() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let exe = Executable::load(env::current_exe()?)?;
+        let backend = ResolverBackend::native(&Root::host(), crate::domain::Hwcaps::host())?;
+        let interp = exe.interpreter().unwrap();
+
+        let deps = exe.dependencies(&backend)?;
+        assert!(!deps.is_empty());
+        for dep in deps {
+            let lib = SharedObject::load_for(&dep.path, &exe, &backend)?;
+            assert_eq!(lib.soname(), Some(dep.name.as_str()));
+            assert_eq!(lib.loader(), Some(interp));
+        }
+
+        // the dynamic linker is a shared object without PT_INTERP nor dependencies
+        match ElfFile::load_in(interp, &Root::host(), &backend)? {
+            ElfFile::SharedObject(ld_so) => {
+                assert!(ld_so.object().libraries().is_empty());
+                assert_eq!(ld_so.loader(), None);
+            }
+            ElfFile::Executable(_) => panic!("{} is loaded as an executable", interp.display()),
+        }
+        assert!(matches!(
+            ElfFile::load_in(env::current_exe()?, &Root::host(), &backend)?,
+            ElfFile::Executable(_)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+
+use goblin::elf::section_header::SHN_UNDEF;
+use goblin::elf::sym::{STB_GLOBAL, STB_GNU_UNIQUE, STB_WEAK};
+use goblin::elf::symver::VER_FLG_WEAK;
+use goblin::elf::Elf;
+
+/// A symbol referenced by an object, optionally with the version required.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SymbolRef {
+    pub name: String,
+    pub version: Option<SymbolVersion>,
+    /// Weak references are allowed to be left undefined.
+    pub weak: bool,
+}
+
+/// A version of symbols required from the object named `file`, such as `GLIBC_2.34` from `libc.so.6`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SymbolVersion {
+    pub file: String,
+    pub name: String,
+}
+
+/// Dynamic symbols and symbol versions defined and required by an ELF object.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    soname: Option<String>,
+    /// Defined symbols and the versions they are defined with, `None` for unversioned ones.
+    defined: HashMap<String, Vec<Option<String>>>,
+    undefined: Vec<SymbolRef>,
+    version_defs: HashSet<String>,
+    /// Required versions that are not marked as weak in `DT_VERNEED`.
+    version_needs: Vec<SymbolVersion>,
+}
+
+impl Symbols {
+    pub fn parse(elf: &Elf<'_>) -> Self {
+        let strtab = &elf.dynstrtab;
+        let string = |offset: usize| strtab.get_at(offset).map(ToOwned::to_owned);
+
+        // version index to the name, as referred from `.gnu.version`
+        let mut def_names = HashMap::new();
+        let mut version_defs = HashSet::new();
+        if let Some(verdef) = &elf.verdef {
+            for def in verdef.iter() {
+                // the first auxiliary entry is the name of the version itself, and the rest are its parents
+                if let Some(name) = def.iter().next().and_then(|aux| string(aux.vda_name)) {
+                    def_names.insert(def.vd_ndx, name.clone());
+                    version_defs.insert(name);
+                }
+            }
+        }
+
+        let mut need_versions = HashMap::new();
+        let mut version_needs = Vec::new();
+        if let Some(verneed) = &elf.verneed {
+            for need in verneed.iter() {
+                let file = match string(need.vn_file) {
+                    Some(file) => file,
+                    None => continue,
+                };
+                for aux in need.iter() {
+                    let name = match string(aux.vna_name) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let version = SymbolVersion {
+                        file: file.clone(),
+                        name,
+                    };
+                    if aux.vna_flags & VER_FLG_WEAK == 0 {
+                        version_needs.push(version.clone());
+                    }
+                    need_versions.insert(aux.vna_other, version);
+                }
+            }
+        }
+
+        let mut defined: HashMap<String, Vec<Option<String>>> = HashMap::new();
+        let mut undefined = Vec::new();
+        for (idx, sym) in elf.dynsyms.iter().enumerate() {
+            let bind = sym.st_bind();
+            if !matches!(bind, STB_GLOBAL | STB_WEAK | STB_GNU_UNIQUE) {
+                continue;
+            }
+            let name = match strtab.get_at(sym.st_name) {
+                Some(name) if !name.is_empty() => name.to_owned(),
+                _ => continue,
+            };
+            let version_idx = elf
+                .versym
+                .as_ref()
+                .and_then(|versym| versym.get_at(idx))
+                .map(|versym| versym.version());
+
+            if sym.st_shndx == SHN_UNDEF as usize {
+                let version = version_idx.and_then(|idx| need_versions.get(&idx).cloned());
+                undefined.push(SymbolRef {
+                    name,
+                    version,
+                    weak: bind == STB_WEAK,
+                });
+            } else {
+                let version = version_idx.and_then(|idx| def_names.get(&idx).cloned());
+                defined.entry(name).or_default().push(version);
+            }
+        }
+
+        Symbols {
+            soname: elf.soname.map(ToOwned::to_owned),
+            defined,
+            undefined,
+            version_defs,
+            version_needs,
+        }
+    }
+
+    pub fn soname(&self) -> Option<&str> {
+        self.soname.as_deref()
+    }
+
+    pub fn undefined(&self) -> &[SymbolRef] {
+        &self.undefined
+    }
+
+    pub fn version_needs(&self) -> &[SymbolVersion] {
+        &self.version_needs
+    }
+
+    pub fn defines_version(&self, version: &str) -> bool {
+        self.version_defs.contains(version)
+    }
+
+    pub fn defines_name(&self, name: &str) -> bool {
+        self.defined.contains_key(name)
+    }
+
+    /// Tells whether the symbol `name` is defined with `version`. Unversioned references can be
+    /// satisfied by any definition of the name.
+    pub fn defines(&self, name: &str, version: Option<&str>) -> bool {
+        match (self.defined.get(name), version) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(versions), Some(version)) => versions
+                .iter()
+                .any(|v| v.is_none() || v.as_deref() == Some(version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn test_parse() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let buffer = fs::read(env::current_exe()?)?;
+        let elf = Elf::parse(&buffer)?;
+        let symbols = Symbols::parse(&elf);
+
+        // the test binary calls into libc with versioned references
+        let malloc = symbols
+            .undefined()
+            .iter()
+            .find(|sym| sym.name == "malloc")
+            .unwrap();
+        let version = malloc.version.as_ref().unwrap();
+        assert_eq!(version.file, "libc.so.6");
+        assert!(version.name.starts_with("GLIBC_"));
+        assert!(symbols.version_needs().contains(version));
+        assert!(!symbols.defines_name("malloc"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_defines() {
+        let mut symbols = Symbols::default();
+        symbols
+            .defined
+            .insert("foo".to_owned(), vec![Some("V1".to_owned())]);
+        symbols.defined.insert("bar".to_owned(), vec![None]);
+
+        assert!(symbols.defines("foo", None));
+        assert!(symbols.defines("foo", Some("V1")));
+        assert!(!symbols.defines("foo", Some("V2")));
+        // unversioned definitions satisfy any version
+        assert!(symbols.defines("bar", Some("V2")));
+        assert!(!symbols.defines("baz", None));
+    }
+}
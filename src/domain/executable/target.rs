@@ -0,0 +1,354 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::base::Result;
+
+use goblin::elf::header::{
+    EM_386, EM_AARCH64, EM_ARM, EM_MIPS, EM_PPC, EM_PPC64, EM_RISCV, EM_S390, EM_X86_64,
+};
+use goblin::elf::Elf;
+use once_cell::sync::OnceCell;
+
+// not defined in goblin yet
+const EM_LOONGARCH: u16 = 258;
+
+const EF_ARM_ABI_FLOAT_HARD: u32 = 0x0400;
+const EF_RISCV_FLOAT_ABI: u32 = 0x0006;
+const EF_RISCV_FLOAT_ABI_SINGLE: u32 = 0x0002;
+const EF_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x0004;
+const EF_RISCV_RVE: u32 = 0x0008;
+const EF_MIPS_ABI2: u32 = 0x0020;
+const EF_MIPS_NAN2008: u32 = 0x0400;
+
+/// The machine an ELF object is built for. Shared objects are only loaded into a process of the same target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ElfTarget {
+    machine: u16,
+    is_64: bool,
+    is_little_endian: bool,
+    /// Bits of `e_flags` that ld.so requires to match, such as the floating-point ABI.
+    abi: u32,
+}
+
+impl ElfTarget {
+    pub(crate) fn new(machine: u16, is_64: bool, is_little_endian: bool, flags: u32) -> Self {
+        let abi_mask = match machine {
+            EM_ARM => EF_ARM_ABI_FLOAT_HARD,
+            EM_RISCV => EF_RISCV_FLOAT_ABI | EF_RISCV_RVE,
+            EM_MIPS => EF_MIPS_ABI2 | EF_MIPS_NAN2008,
+            _ => 0,
+        };
+        ElfTarget {
+            machine,
+            is_64,
+            is_little_endian,
+            abi: flags & abi_mask,
+        }
+    }
+
+    pub fn from_elf(elf: &Elf<'_>) -> Self {
+        ElfTarget::new(
+            elf.header.e_machine,
+            elf.is_64,
+            elf.little_endian,
+            elf.header.e_flags,
+        )
+    }
+
+    /// Reads the target from the ELF header of `path`. Returns `None` if `path` is not an ELF file.
+    pub fn read<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        // e_flags is at the end of the header of 32-bit objects, and before e_ehsize in 64-bit ones
+        let mut header = [0; 52];
+        match fs::File::open(path)?.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        if &header[..4] != b"\x7fELF" {
+            return Ok(None);
+        }
+
+        let is_64 = match header[4] {
+            1 => false,
+            2 => true,
+            _ => return Ok(None),
+        };
+        let is_little_endian = match header[5] {
+            1 => true,
+            2 => false,
+            _ => return Ok(None),
+        };
+        let u16_at = |offset: usize| {
+            let bytes = [header[offset], header[offset + 1]];
+            if is_little_endian {
+                u16::from_le_bytes(bytes)
+            } else {
+                u16::from_be_bytes(bytes)
+            }
+        };
+        let u32_at = |offset: usize| {
+            // unwrap is ok because the slice has the exact length
+            let bytes = header[offset..offset + 4].try_into().unwrap();
+            if is_little_endian {
+                u32::from_le_bytes(bytes)
+            } else {
+                u32::from_be_bytes(bytes)
+            }
+        };
+        let flags = u32_at(if is_64 { 48 } else { 36 });
+        Ok(Some(ElfTarget::new(
+            u16_at(18),
+            is_64,
+            is_little_endian,
+            flags,
+        )))
+    }
+
+    /// The target of the running magicpak process, which is read from our own executable only once.
+    pub fn host() -> Option<Self> {
+        static HOST: OnceCell<Option<ElfTarget>> = OnceCell::new();
+        *HOST.get_or_init(|| {
+            let exe = env::current_exe().ok()?;
+            ElfTarget::read(exe).ok().flatten()
+        })
+    }
+
+    /// Whether objects of this target can run on the host.
+    pub fn is_host(&self) -> bool {
+        ElfTarget::host().as_ref() == Some(self)
+    }
+
+    pub fn machine(&self) -> u16 {
+        self.machine
+    }
+
+    pub fn is_64(&self) -> bool {
+        self.is_64
+    }
+
+    pub fn is_little_endian(&self) -> bool {
+        self.is_little_endian
+    }
+
+    /// The ABI-related bits of `e_flags`.
+    pub fn abi(&self) -> u32 {
+        self.abi
+    }
+
+    /// Paths of the glibc dynamic linker for this target, used for objects without `PT_INTERP`.
+    pub fn default_interpreters(&self) -> &'static [&'static str] {
+        let float_abi = self.abi & EF_RISCV_FLOAT_ABI;
+        match (self.machine, self.is_64, self.is_little_endian) {
+            (EM_X86_64, true, _) => &["/lib64/ld-linux-x86-64.so.2"],
+            (EM_X86_64, false, _) => &["/libx32/ld-linux-x32.so.2"],
+            (EM_386, _, _) => &["/lib/ld-linux.so.2"],
+            (EM_AARCH64, _, true) => &["/lib/ld-linux-aarch64.so.1"],
+            (EM_AARCH64, _, false) => &["/lib/ld-linux-aarch64_be.so.1"],
+            (EM_ARM, _, _) if self.abi & EF_ARM_ABI_FLOAT_HARD != 0 => {
+                &["/lib/ld-linux-armhf.so.3"]
+            }
+            (EM_ARM, _, _) => &["/lib/ld-linux.so.3"],
+            (EM_RISCV, true, _) if float_abi == EF_RISCV_FLOAT_ABI_DOUBLE => {
+                &["/lib/ld-linux-riscv64-lp64d.so.1"]
+            }
+            (EM_RISCV, true, _) if float_abi == EF_RISCV_FLOAT_ABI_SINGLE => {
+                &["/lib/ld-linux-riscv64-lp64f.so.1"]
+            }
+            (EM_RISCV, true, _) => &["/lib/ld-linux-riscv64-lp64.so.1"],
+            (EM_RISCV, false, _) if float_abi == EF_RISCV_FLOAT_ABI_DOUBLE => {
+                &["/lib/ld-linux-riscv32-ilp32d.so.1"]
+            }
+            (EM_RISCV, false, _) if float_abi == EF_RISCV_FLOAT_ABI_SINGLE => {
+                &["/lib/ld-linux-riscv32-ilp32f.so.1"]
+            }
+            (EM_RISCV, false, _) => &["/lib/ld-linux-riscv32-ilp32.so.1"],
+            (EM_PPC64, _, true) => &["/lib64/ld64.so.2"],
+            (EM_PPC64, _, false) => &["/lib64/ld64.so.1"],
+            (EM_PPC, _, _) => &["/lib/ld.so.1"],
+            (EM_S390, true, _) => &["/lib/ld64.so.1"],
+            (EM_S390, false, _) => &["/lib/ld.so.1"],
+            (EM_MIPS, true, _) => &["/lib64/ld.so.1", "/lib64/ld-linux-mipsn8.so.1"],
+            (EM_MIPS, false, _) if self.abi & EF_MIPS_ABI2 != 0 => {
+                &["/lib32/ld.so.1", "/lib32/ld-linux-mipsn8.so.1"]
+            }
+            (EM_MIPS, false, _) => &["/lib/ld.so.1", "/lib/ld-linux-mipsn8.so.1"],
+            (EM_LOONGARCH, true, _) => &["/lib64/ld-linux-loongarch-lp64d.so.1"],
+            _ => &[],
+        }
+    }
+
+    /// The architecture name in the path of the musl dynamic linker, `/lib/ld-musl-$ARCH.so.1`.
+    pub fn musl_arch(&self) -> Option<&'static str> {
+        let hard_float = self.abi & EF_ARM_ABI_FLOAT_HARD != 0;
+        let arch = match (self.machine, self.is_64, self.is_little_endian) {
+            (EM_X86_64, true, _) => "x86_64",
+            (EM_X86_64, false, _) => "x32",
+            (EM_386, _, _) => "i386",
+            (EM_AARCH64, _, true) => "aarch64",
+            (EM_AARCH64, _, false) => "aarch64_be",
+            (EM_ARM, _, true) if hard_float => "armhf",
+            (EM_ARM, _, true) => "arm",
+            (EM_ARM, _, false) if hard_float => "armebhf",
+            (EM_ARM, _, false) => "armeb",
+            (EM_RISCV, true, _) => "riscv64",
+            (EM_RISCV, false, _) => "riscv32",
+            (EM_PPC64, _, true) => "powerpc64le",
+            (EM_PPC64, _, false) => "powerpc64",
+            (EM_PPC, _, _) => "powerpc",
+            (EM_S390, true, _) => "s390x",
+            (EM_MIPS, true, true) => "mips64el",
+            (EM_MIPS, true, false) => "mips64",
+            (EM_MIPS, false, true) => "mipsel",
+            (EM_MIPS, false, false) => "mips",
+            (EM_LOONGARCH, true, _) => "loongarch64",
+            _ => return None,
+        };
+        Some(arch)
+    }
+
+    /// The multiarch tuple of Debian and its derivatives, which names the system library directories
+    /// such as `/lib/x86_64-linux-gnu`.
+    pub fn multiarch(&self) -> Option<&'static str> {
+        let hard_float = self.abi & EF_ARM_ABI_FLOAT_HARD != 0;
+        let tuple = match (self.machine, self.is_64, self.is_little_endian) {
+            (EM_X86_64, true, _) => "x86_64-linux-gnu",
+            (EM_X86_64, false, _) => "x86_64-linux-gnux32",
+            (EM_386, _, _) => "i386-linux-gnu",
+            (EM_AARCH64, _, true) => "aarch64-linux-gnu",
+            (EM_AARCH64, _, false) => "aarch64_be-linux-gnu",
+            (EM_ARM, _, true) if hard_float => "arm-linux-gnueabihf",
+            (EM_ARM, _, true) => "arm-linux-gnueabi",
+            (EM_RISCV, true, _) => "riscv64-linux-gnu",
+            (EM_PPC64, _, true) => "powerpc64le-linux-gnu",
+            (EM_PPC64, _, false) => "powerpc64-linux-gnu",
+            (EM_PPC, _, _) => "powerpc-linux-gnu",
+            (EM_S390, true, _) => "s390x-linux-gnu",
+            (EM_MIPS, true, true) => "mips64el-linux-gnuabi64",
+            (EM_MIPS, true, false) => "mips64-linux-gnuabi64",
+            (EM_MIPS, false, true) => "mipsel-linux-gnu",
+            (EM_MIPS, false, false) => "mips-linux-gnu",
+            (EM_LOONGARCH, true, _) => "loongarch64-linux-gnu",
+            _ => return None,
+        };
+        Some(tuple)
+    }
+
+    /// The value of `$PLATFORM`, which is `AT_PLATFORM` given by the kernel running the object.
+    /// The value is read from the auxiliary vector if the target is the same as the host.
+    pub fn platform(&self) -> Option<OsString> {
+        if self.is_host() {
+            if let Some(platform) = auxv_platform() {
+                return Some(platform);
+            }
+        }
+
+        let platform = match (self.machine, self.is_64, self.is_little_endian) {
+            (EM_X86_64, true, _) => "x86_64",
+            (EM_386, _, _) => "i686",
+            (EM_AARCH64, _, true) => "aarch64",
+            (EM_AARCH64, _, false) => "aarch64_be",
+            (EM_ARM, _, true) => "v7l",
+            (EM_ARM, _, false) => "v7b",
+            _ => return None,
+        };
+        Some(platform.into())
+    }
+
+    /// The value of `$LIB`.
+    pub fn lib_dir(&self) -> &'static str {
+        match (self.machine, self.is_64) {
+            (EM_X86_64, false) => "libx32",
+            (_, true) => "lib64",
+            (_, false) => "lib",
+        }
+    }
+}
+
+fn auxv_platform() -> Option<OsString> {
+    let mut reader = crt0stack::Reader::from_environ().done();
+    let platform = reader.find_map(|entry| {
+        if let crt0stack::Entry::Platform(platform) = entry {
+            Some(platform)
+        } else {
+            None
+        }
+    })?;
+    tracing::debug!(%platform, "target: read platform from auxv");
+    Some(platform.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target() {
+        let aarch64 = ElfTarget::new(EM_AARCH64, true, true, 0);
+        assert_eq!(
+            aarch64.default_interpreters(),
+            ["/lib/ld-linux-aarch64.so.1"]
+        );
+        assert_eq!(aarch64.lib_dir(), "lib64");
+        assert_eq!(aarch64.multiarch(), Some("aarch64-linux-gnu"));
+
+        // RVC does not matter, but the floating-point ABI does
+        let riscv64 = ElfTarget::new(EM_RISCV, true, true, 0x5);
+        assert_eq!(riscv64, ElfTarget::new(EM_RISCV, true, true, 0x4));
+        assert_ne!(riscv64, ElfTarget::new(EM_RISCV, true, true, 0x1));
+        assert_eq!(
+            riscv64.default_interpreters(),
+            ["/lib/ld-linux-riscv64-lp64d.so.1"]
+        );
+        assert_eq!(riscv64.platform(), None);
+
+        let armhf = ElfTarget::new(EM_ARM, false, true, 0x0500_0400);
+        assert_eq!(armhf.default_interpreters(), ["/lib/ld-linux-armhf.so.3"]);
+        assert_eq!(armhf.lib_dir(), "lib");
+        assert_eq!(armhf.musl_arch(), Some("armhf"));
+        assert_eq!(armhf.multiarch(), Some("arm-linux-gnueabihf"));
+    }
+
+    #[test]
+    fn test_read() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use assert_fs::prelude::*;
+
+        let host = ElfTarget::host().unwrap();
+        assert!(host.is_host());
+        assert!(!host.default_interpreters().is_empty());
+        assert!(host.platform().is_some());
+
+        let dir = assert_fs::TempDir::new()?;
+        let mut header = vec![0; 64];
+        header[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        header[18..20].copy_from_slice(&EM_RISCV.to_le_bytes());
+        header[48..52].copy_from_slice(&0x5u32.to_le_bytes());
+        let file = dir.child("riscv64");
+        file.write_binary(&header)?;
+        assert_eq!(
+            ElfTarget::read(file.path())?,
+            Some(ElfTarget::new(EM_RISCV, true, true, 0x4))
+        );
+
+        // 32-bit big-endian object has e_flags at a different offset
+        let mut header = vec![0; 52];
+        header[..6].copy_from_slice(b"\x7fELF\x01\x02");
+        header[18..20].copy_from_slice(&EM_MIPS.to_be_bytes());
+        header[36..40].copy_from_slice(&EF_MIPS_ABI2.to_be_bytes());
+        let file = dir.child("mipsn32");
+        file.write_binary(&header)?;
+        let target = ElfTarget::read(file.path())?.unwrap();
+        assert_eq!(target.default_interpreters()[0], "/lib32/ld.so.1");
+        assert!(!target.is_host());
+
+        let file = dir.child("script");
+        file.write_str("#!/bin/sh\n")?;
+        assert_eq!(ElfTarget::read(file.path())?, None);
+        Ok(())
+    }
+}
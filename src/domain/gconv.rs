@@ -0,0 +1,238 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::base::Result;
+
+/// The configuration file of gconv modules in the gconv directory.
+pub const GCONV_MODULES: &str = "gconv-modules";
+
+/// Charsets converted by glibc itself, without loading modules.
+const BUILTIN_CHARSETS: &[&str] = &[
+    "INTERNAL",
+    "ISO-10646/UCS4/",
+    "ISO-10646/UTF8/",
+    "ISO-10646/UCS2/",
+    "ISO-10646/UTF-8/",
+    "ISO-10646",
+    "UCS4",
+    "UCS-4",
+    "UCS-4LE",
+    "UCS-4BE",
+    "UTF8",
+    "UTF-8",
+    "UCS2",
+    "UCS-2",
+    "UCS-2LE",
+    "UCS-2BE",
+    "UNICODELITTLE",
+    "UNICODEBIG",
+    "ANSI_X3.4-1968",
+    "ANSI_X3.4",
+    "ASCII",
+    "US-ASCII",
+    "UTF-16",
+    "UTF-16LE",
+    "UTF-16BE",
+    "UTF-32",
+    "UTF-32LE",
+    "UTF-32BE",
+    "UTF-7",
+    "UTF-7-IMAP",
+    "WCHAR_T",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Alias {
+    alias: String,
+    target: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GconvModule {
+    pub from: String,
+    pub to: String,
+    /// The file name of the module without `.so`, relative to the directory of the configuration.
+    pub file: String,
+    pub cost: String,
+}
+
+/// Configuration of iconv(3) modules in `gconv-modules` and `gconv-modules.d/*.conf`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GconvModules {
+    aliases: Vec<Alias>,
+    modules: Vec<GconvModule>,
+}
+
+impl GconvModules {
+    /// Loads the configuration in the gconv directory `dir` on the host.
+    pub fn load<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let mut content = fs::read_to_string(dir.join(GCONV_MODULES))?;
+        // glibc 2.34 and later read files in gconv-modules.d in the order of their names
+        let mut conf_paths = match fs::read_dir(dir.join("gconv-modules.d")) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<PathBuf>>>()?,
+            Err(_) => Vec::new(),
+        };
+        conf_paths.retain(|path| matches!(path.extension(), Some(ext) if ext == "conf"));
+        conf_paths.sort();
+        for path in conf_paths {
+            content.push('\n');
+            content.push_str(&fs::read_to_string(path)?);
+        }
+        Ok(GconvModules::parse(&content))
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut conf = GconvModules::default();
+        for line in content.lines() {
+            let line = match line.split_once('#') {
+                Some((line, _)) => line,
+                None => line,
+            };
+            let words: Vec<_> = line.split_whitespace().collect();
+            match words[..] {
+                [] => {}
+                ["alias", alias, target] => conf.aliases.push(Alias {
+                    alias: normalize_charset(alias),
+                    target: normalize_charset(target),
+                }),
+                ["module", from, to, file] | ["module", from, to, file, _] => {
+                    conf.modules.push(GconvModule {
+                        from: normalize_charset(from),
+                        to: normalize_charset(to),
+                        file: file.to_owned(),
+                        cost: words.get(4).unwrap_or(&"1").to_string(),
+                    })
+                }
+                _ => tracing::warn!(%line, "gconv: ignoring malformed line"),
+            }
+        }
+        conf
+    }
+
+    /// Resolves `charset` given by its name or alias into the canonical name used in modules.
+    pub fn canonical_name(&self, charset: &str) -> String {
+        let charset = normalize_charset(charset);
+        match self.aliases.iter().find(|a| a.alias == charset) {
+            Some(alias) => alias.target.clone(),
+            None => charset,
+        }
+    }
+
+    /// Whether `charset` is converted by glibc without modules.
+    pub fn is_builtin(&self, charset: &str) -> bool {
+        let charset = self.canonical_name(charset);
+        BUILTIN_CHARSETS.contains(&charset.as_str())
+    }
+
+    /// Modules converting from or to the canonical name of `charset`.
+    pub fn modules_for(&self, charset: &str) -> Vec<&GconvModule> {
+        let charset = self.canonical_name(charset);
+        self.modules
+            .iter()
+            .filter(|m| m.from == charset || m.to == charset)
+            .collect()
+    }
+
+    /// Keeps only `charsets`, their aliases and the modules converting them.
+    pub fn retain_charsets(&mut self, charsets: &[&str]) {
+        let canonical: Vec<_> = charsets.iter().map(|c| self.canonical_name(c)).collect();
+        self.aliases.retain(|a| canonical.contains(&a.target));
+        self.modules
+            .retain(|m| canonical.contains(&m.from) || canonical.contains(&m.to));
+    }
+
+    /// Writes the configuration in the format of `gconv-modules`.
+    pub fn render(&self) -> String {
+        let mut content = String::new();
+        // unwraps are ok because writing to String never fails
+        for alias in &self.aliases {
+            writeln!(content, "alias\t{}//\t{}//", alias.alias, alias.target).unwrap();
+        }
+        for module in &self.modules {
+            writeln!(
+                content,
+                "module\t{}\t{}\t{}\t{}",
+                render_charset(&module.from),
+                render_charset(&module.to),
+                module.file,
+                module.cost
+            )
+            .unwrap();
+        }
+        content
+    }
+
+    /// The path of the shared object of `module` in the gconv directory `dir`.
+    pub fn module_path(dir: &Path, module: &GconvModule) -> PathBuf {
+        dir.join(format!("{}.so", module.file))
+    }
+}
+
+/// Uppercases the charset name and removes the trailing `//` as glibc does, while keeping the
+/// `/` in names like `ISO-10646/UTF8/`.
+fn normalize_charset(name: &str) -> String {
+    name.strip_suffix("//").unwrap_or(name).to_ascii_uppercase()
+}
+
+fn render_charset(name: &str) -> String {
+    if name == "INTERNAL" || name.ends_with('/') {
+        name.to_owned()
+    } else {
+        format!("{}//", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "\
+# comment
+alias\tLATIN1//\t\tISO-8859-1//
+alias\tISO8859-1//\t\tISO-8859-1//
+module\tISO-8859-1//\t\tINTERNAL\t\tISO8859-1\t1
+module\tINTERNAL\t\tISO-8859-1//\t\tISO8859-1\t1
+
+alias\tSJIS//\t\t\tSHIFT_JIS//
+module\tSHIFT_JIS//\t\tINTERNAL\t\tSJIS\t\t1
+module\tINTERNAL\t\tSHIFT_JIS//\t\tSJIS\t\t1
+";
+
+    #[test]
+    fn test_parse() {
+        let conf = GconvModules::parse(CONTENT);
+        assert_eq!(conf.canonical_name("latin1"), "ISO-8859-1");
+        assert_eq!(conf.canonical_name("SHIFT_JIS"), "SHIFT_JIS");
+        assert!(conf.is_builtin("utf-8"));
+        assert!(!conf.is_builtin("sjis"));
+
+        let modules = conf.modules_for("sjis");
+        assert_eq!(modules.len(), 2);
+        assert_eq!(
+            GconvModules::module_path(Path::new("/usr/lib/gconv"), modules[0]),
+            Path::new("/usr/lib/gconv/SJIS.so")
+        );
+        assert!(conf.modules_for("EUC-JP").is_empty());
+    }
+
+    #[test]
+    fn test_retain_and_render() {
+        let mut conf = GconvModules::parse(CONTENT);
+        conf.retain_charsets(&["latin1"]);
+        assert_eq!(
+            conf.render(),
+            "alias\tLATIN1//\tISO-8859-1//\n\
+             alias\tISO8859-1//\tISO-8859-1//\n\
+             module\tISO-8859-1//\tINTERNAL\tISO8859-1\t1\n\
+             module\tINTERNAL\tISO-8859-1//\tISO8859-1\t1\n"
+        );
+        assert_eq!(GconvModules::parse(&conf.render()), conf);
+    }
+}
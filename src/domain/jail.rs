@@ -100,14 +100,8 @@ mod tests {
         let jail = Jail::new()?;
         jail.install_busybox(locate_busybox()?)?;
 
-        assert_eq!(
-            true,
-            predicate::path::is_file().eval(&jail.path().join("bin/busybox"))
-        );
-        assert_eq!(
-            true,
-            predicate::path::is_file().eval(&jail.path().join("bin/sh"))
-        );
+        assert!(predicate::path::is_file().eval(&jail.path().join("bin/busybox")));
+        assert!(predicate::path::is_file().eval(&jail.path().join("bin/sh")));
         Ok(())
     }
 
@@ -0,0 +1,298 @@
+use std::fs;
+use std::path::Path;
+
+use crate::base::{Error, Result};
+
+/// The path glibc reads compiled locales from, unless `LOCPATH` is set.
+pub const LOCALE_DIR: &str = "/usr/lib/locale";
+
+const ARCHIVE_MAGIC: u32 = 0xde02_0109;
+const HEADER_SIZE: usize = 56;
+const NAMEHASH_ENTRY_SIZE: usize = 12;
+
+/// Names of the categories in the order of their indices in glibc, where `LC_ALL` is not a file.
+const CATEGORIES: &[Option<&str>] = &[
+    Some("LC_CTYPE"),
+    Some("LC_NUMERIC"),
+    Some("LC_TIME"),
+    Some("LC_COLLATE"),
+    Some("LC_MONETARY"),
+    Some("LC_MESSAGES"),
+    None,
+    Some("LC_PAPER"),
+    Some("LC_NAME"),
+    Some("LC_ADDRESS"),
+    Some("LC_TELEPHONE"),
+    Some("LC_MEASUREMENT"),
+    Some("LC_IDENTIFICATION"),
+];
+
+/// A locale extracted from the archive.
+#[derive(Debug)]
+pub struct Locale {
+    pub name: String,
+    /// Paths of category files relative to the locale directory, and their contents.
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+/// `locale-archive` generated by localedef(1), which stores compiled locales in a single file.
+#[derive(Debug)]
+pub struct LocaleArchive {
+    data: Vec<u8>,
+    little_endian: bool,
+}
+
+impl LocaleArchive {
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let data = fs::read(path.as_ref())?;
+        let archive = LocaleArchive::parse(data)?;
+        tracing::debug!(path = %path.as_ref().display(), "locale_archive: loaded");
+        Ok(archive)
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        let magic = data
+            .get(..4)
+            .ok_or_else(|| malformed("file is too short".to_owned()))?;
+        // unwrap is ok because `magic` has the exact length
+        let little_endian = if u32::from_le_bytes(magic.try_into().unwrap()) == ARCHIVE_MAGIC {
+            true
+        } else if u32::from_be_bytes(magic.try_into().unwrap()) == ARCHIVE_MAGIC {
+            false
+        } else {
+            return Err(malformed("invalid magic".to_owned()));
+        };
+        if data.len() < HEADER_SIZE {
+            return Err(malformed("header is truncated".to_owned()));
+        }
+        Ok(LocaleArchive {
+            data,
+            little_endian,
+        })
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| malformed(format!("unexpected end of file at {}", offset)))
+    }
+
+    fn u32(&self, offset: usize) -> Result<usize> {
+        // unwrap is ok because `bytes` returns a slice with the exact length
+        let bytes = self.bytes(offset, 4)?.try_into().unwrap();
+        let value = if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        };
+        Ok(value as usize)
+    }
+
+    fn str(&self, offset: usize) -> Result<&str> {
+        let rest = self
+            .data
+            .get(offset..)
+            .ok_or_else(|| malformed(format!("string offset {} is out of range", offset)))?;
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| malformed(format!("unterminated string at {}", offset)))?;
+        std::str::from_utf8(&rest[..len]).map_err(Error::Encoding)
+    }
+
+    /// Entries of the name hash table in use, as pairs of the name and the offset of the locale record.
+    fn entries(&self) -> Result<Vec<(&str, usize)>> {
+        let namehash_offset = self.u32(8)?;
+        let namehash_size = self.u32(16)?;
+        let mut entries = Vec::new();
+        for i in 0..namehash_size {
+            let entry = namehash_offset + i * NAMEHASH_ENTRY_SIZE;
+            let name_offset = self.u32(entry + 4)?;
+            let locrec_offset = self.u32(entry + 8)?;
+            // empty slots have no locale record
+            if locrec_offset != 0 {
+                entries.push((self.str(name_offset)?, locrec_offset));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Names of all locales in the archive.
+    pub fn names(&self) -> Result<Vec<String>> {
+        let mut names: Vec<_> = self
+            .entries()?
+            .into_iter()
+            .map(|(name, _)| name.to_owned())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Extracts the locale `name`, which is also looked up with the codeset normalized as glibc does.
+    pub fn extract(&self, name: &str) -> Result<Option<Locale>> {
+        let normalized = normalize_locale_name(name);
+        let entries = self.entries()?;
+        let found = entries
+            .iter()
+            .find(|(entry, _)| *entry == name)
+            .or_else(|| entries.iter().find(|(entry, _)| *entry == normalized));
+        let (name, locrec_offset) = match found {
+            Some(found) => *found,
+            None => return Ok(None),
+        };
+
+        let mut files = Vec::new();
+        for (i, category) in CATEGORIES.iter().enumerate() {
+            let category = match category {
+                Some(category) => category,
+                None => continue,
+            };
+            // each record is preceded by the reference count
+            let record = locrec_offset + 4 + i * 8;
+            let offset = self.u32(record)?;
+            let len = self.u32(record + 4)?;
+            let content = self.bytes(offset, len)?.to_vec();
+            // LC_MESSAGES is a directory in the locale directory
+            let path = if *category == "LC_MESSAGES" {
+                "LC_MESSAGES/SYS_LC_MESSAGES".to_owned()
+            } else {
+                (*category).to_owned()
+            };
+            files.push((path, content));
+        }
+        Ok(Some(Locale {
+            name: name.to_owned(),
+            files,
+        }))
+    }
+}
+
+/// Normalizes the codeset in the locale name as `_nl_normalize_codeset` in glibc, like `en_US.UTF-8` into
+/// `en_US.utf8`.
+pub fn normalize_locale_name(name: &str) -> String {
+    let (name, modifier) = match name.split_once('@') {
+        Some((name, modifier)) => (name, Some(modifier)),
+        None => (name, None),
+    };
+    let mut normalized = match name.split_once('.') {
+        Some((language, codeset)) => {
+            let codeset: String = codeset
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .map(|c| c.to_ascii_lowercase())
+                .collect();
+            if codeset.chars().all(|c| c.is_ascii_digit()) {
+                format!("{}.iso{}", language, codeset)
+            } else {
+                format!("{}.{}", language, codeset)
+            }
+        }
+        None => name.to_owned(),
+    };
+    if let Some(modifier) = modifier {
+        normalized.push('@');
+        normalized.push_str(modifier);
+    }
+    normalized
+}
+
+fn malformed(message: String) -> Error {
+    Error::MalformedLocaleArchive(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_locale_name() {
+        assert_eq!(normalize_locale_name("en_US.UTF-8"), "en_US.utf8");
+        assert_eq!(
+            normalize_locale_name("de_DE.ISO-8859-15@euro"),
+            "de_DE.iso885915@euro"
+        );
+        assert_eq!(normalize_locale_name("ja_JP.eucJP"), "ja_JP.eucjp");
+        assert_eq!(normalize_locale_name("C"), "C");
+    }
+
+    /// Builds an archive with one locale, whose category files contain their own names.
+    fn build_archive() -> Vec<u8> {
+        let namehash_offset = HEADER_SIZE;
+        let namehash_size = 2;
+        let string_offset = namehash_offset + namehash_size * NAMEHASH_ENTRY_SIZE;
+        let name = b"en_US.utf8\0";
+        let locrec_offset = string_offset + name.len();
+        let locrec_size = 4 + CATEGORIES.len() * 8;
+        let mut data_offset = locrec_offset + locrec_size;
+
+        let mut header = Vec::new();
+        for value in [
+            ARCHIVE_MAGIC as usize,
+            0,
+            namehash_offset,
+            1,
+            namehash_size,
+            string_offset,
+            name.len(),
+            name.len(),
+            locrec_offset,
+            1,
+            1,
+            0,
+            0,
+            0,
+        ] {
+            header.extend((value as u32).to_le_bytes());
+        }
+        // the second slot of the name hash table is empty
+        for value in [0, string_offset, locrec_offset, 0, 0, 0] {
+            header.extend((value as u32).to_le_bytes());
+        }
+        header.extend(name);
+
+        let mut record = 1u32.to_le_bytes().to_vec();
+        let mut contents: Vec<u8> = Vec::new();
+        for category in CATEGORIES {
+            let content = category.unwrap_or("LC_ALL").as_bytes();
+            record.extend((data_offset as u32).to_le_bytes());
+            record.extend((content.len() as u32).to_le_bytes());
+            contents.extend(content);
+            data_offset += content.len();
+        }
+        header.extend(record);
+        header.extend(contents);
+        header
+    }
+
+    #[test]
+    fn test_extract() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let archive = LocaleArchive::parse(build_archive())?;
+        assert_eq!(archive.names()?, ["en_US.utf8"]);
+        assert!(archive.extract("de_DE")?.is_none());
+
+        let locale = archive.extract("en_US.UTF-8")?.unwrap();
+        assert_eq!(locale.name, "en_US.utf8");
+        assert_eq!(locale.files.len(), 12);
+        assert_eq!(
+            locale.files[0],
+            ("LC_CTYPE".to_owned(), b"LC_CTYPE".to_vec())
+        );
+        assert_eq!(
+            locale.files[5],
+            (
+                "LC_MESSAGES/SYS_LC_MESSAGES".to_owned(),
+                b"LC_MESSAGES".to_vec()
+            )
+        );
+
+        assert!(matches!(
+            LocaleArchive::parse(b"not an archive".to_vec()),
+            Err(Error::MalformedLocaleArchive(_))
+        ));
+        Ok(())
+    }
+}
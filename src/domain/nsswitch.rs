@@ -0,0 +1,219 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::base::Result;
+
+/// The path glibc reads the NSS configuration from.
+pub const NSSWITCH_CONF_PATH: &str = "/etc/nsswitch.conf";
+
+/// The configuration glibc uses for each database when nsswitch.conf does not exist.
+const GLIBC_DEFAULT_CONF: &str = "\
+passwd: files
+group: files
+shadow: files
+gshadow: files
+hosts: dns [!UNAVAIL=return] files
+networks: dns [!UNAVAIL=return] files
+protocols: files
+services: files
+ethers: files
+rpc: files
+netgroup: files
+aliases: files
+publickey: files
+";
+
+/// A service that a database is looked up with, such as `files` or `dns`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NssSource {
+    pub service: String,
+    /// Action items following the service like `[NOTFOUND=return]`.
+    pub actions: Vec<String>,
+}
+
+/// A line of nsswitch.conf, which maps a database like `hosts` to its services.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NssDatabase {
+    pub name: String,
+    pub sources: Vec<NssSource>,
+}
+
+/// The Name Service Switch configuration in nsswitch.conf(5).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NsswitchConf {
+    databases: Vec<NssDatabase>,
+}
+
+impl NsswitchConf {
+    pub fn load<P>(host_path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let content = fs::read_to_string(host_path)?;
+        Ok(NsswitchConf::parse(&content))
+    }
+
+    /// The configuration glibc uses when nsswitch.conf does not exist.
+    pub fn glibc_default() -> Self {
+        NsswitchConf::parse(GLIBC_DEFAULT_CONF)
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut databases = Vec::new();
+        for line in content.lines() {
+            let line = match line.split_once('#') {
+                Some((line, _)) => line,
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, spec) = match line.split_once(':') {
+                Some((name, spec)) => (name.trim(), spec),
+                None => {
+                    tracing::warn!(%line, "nsswitch: ignoring malformed line");
+                    continue;
+                }
+            };
+            databases.push(NssDatabase {
+                name: name.to_owned(),
+                sources: parse_sources(spec),
+            });
+        }
+        NsswitchConf { databases }
+    }
+
+    pub fn databases(&self) -> &[NssDatabase] {
+        &self.databases
+    }
+
+    /// Names of all services used in the configuration, without duplicates.
+    pub fn services(&self) -> Vec<&str> {
+        let mut services = Vec::new();
+        for source in self.databases.iter().flat_map(|db| &db.sources) {
+            if !services.contains(&source.service.as_str()) {
+                services.push(source.service.as_str());
+            }
+        }
+        services
+    }
+
+    /// Removes services for which `predicate` returns `false`, together with their action items.
+    pub fn retain_services<F>(&mut self, mut predicate: F)
+    where
+        F: FnMut(&str) -> bool,
+    {
+        for db in &mut self.databases {
+            db.sources.retain(|source| predicate(&source.service));
+        }
+        self.databases.retain(|db| !db.sources.is_empty());
+    }
+
+    /// Writes the configuration back in the format of nsswitch.conf, without comments.
+    pub fn render(&self) -> String {
+        let mut content = String::new();
+        for db in &self.databases {
+            // unwraps are ok because writing to String never fails
+            write!(content, "{}:", db.name).unwrap();
+            for source in &db.sources {
+                write!(content, " {}", source.service).unwrap();
+                for action in &source.actions {
+                    write!(content, " {}", action).unwrap();
+                }
+            }
+            content.push('\n');
+        }
+        content
+    }
+}
+
+/// Parses `service [STATUS=action]...` following the database name. Whitespace in action items is removed.
+fn parse_sources(spec: &str) -> Vec<NssSource> {
+    let mut sources: Vec<NssSource> = Vec::new();
+    let mut rest = spec.trim_start();
+    while !rest.is_empty() {
+        if let Some(action) = rest.strip_prefix('[') {
+            let (action, next) = action.split_once(']').unwrap_or((action, ""));
+            let action: String = action.split_whitespace().collect();
+            match sources.last_mut() {
+                Some(source) => source.actions.push(format!("[{}]", action)),
+                None => tracing::warn!(%action, "nsswitch: ignoring action item without service"),
+            }
+            rest = next.trim_start();
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '[')
+                .unwrap_or(rest.len());
+            sources.push(NssSource {
+                service: rest[..end].to_owned(),
+                actions: Vec::new(),
+            });
+            rest = rest[end..].trim_start();
+        }
+    }
+    sources
+}
+
+/// The name of the shared object that glibc loads for `service`.
+pub fn module_name(service: &str) -> String {
+    format!("libnss_{}.so.2", service)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let conf = NsswitchConf::parse(
+            "# comment\n\
+             passwd:  files systemd\n\
+             hosts:   files mdns4_minimal [ NOTFOUND = return ] dns # trailing\n\
+             \n\
+             malformed line\n",
+        );
+        assert_eq!(
+            conf.databases()[1],
+            NssDatabase {
+                name: "hosts".to_owned(),
+                sources: vec![
+                    NssSource {
+                        service: "files".to_owned(),
+                        actions: vec![],
+                    },
+                    NssSource {
+                        service: "mdns4_minimal".to_owned(),
+                        actions: vec!["[NOTFOUND=return]".to_owned()],
+                    },
+                    NssSource {
+                        service: "dns".to_owned(),
+                        actions: vec![],
+                    },
+                ],
+            }
+        );
+        assert_eq!(conf.databases().len(), 2);
+        assert_eq!(
+            conf.services(),
+            ["files", "systemd", "mdns4_minimal", "dns"]
+        );
+    }
+
+    #[test]
+    fn test_retain_and_render() {
+        let mut conf = NsswitchConf::parse(
+            "passwd: files systemd\nhosts: mdns4_minimal [NOTFOUND=return] dns\nnetgroup: nis\n",
+        );
+        conf.retain_services(|service| service != "mdns4_minimal" && service != "nis");
+        assert_eq!(conf.render(), "passwd: files systemd\nhosts: dns\n");
+        assert_eq!(NsswitchConf::parse(&conf.render()), conf);
+        assert!(NsswitchConf::glibc_default()
+            .services()
+            .iter()
+            .all(|service| ["files", "dns"].contains(service)));
+        assert_eq!(module_name("dns"), "libnss_dns.so.2");
+    }
+}
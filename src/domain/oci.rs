@@ -0,0 +1,341 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::executable::ElfTarget;
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+const MEDIA_TYPE_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+const MEDIA_TYPE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const MEDIA_TYPE_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+const MEDIA_TYPE_LAYER: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// The platform an image runs on, in the GOARCH values used in OCI image configs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub architecture: &'static str,
+    pub variant: Option<&'static str>,
+}
+
+impl Platform {
+    /// The platform of executables of `target`, or `None` if OCI defines no architecture for it.
+    pub fn from_target(target: ElfTarget) -> Option<Self> {
+        use goblin::elf::header::{
+            EM_386, EM_AARCH64, EM_ARM, EM_MIPS, EM_PPC64, EM_RISCV, EM_S390, EM_X86_64,
+        };
+        // not defined in goblin yet
+        const EM_LOONGARCH: u16 = 258;
+        const EF_ARM_ABI_FLOAT_HARD: u32 = 0x0400;
+
+        let (architecture, variant) =
+            match (target.machine(), target.is_64(), target.is_little_endian()) {
+                (EM_X86_64, true, _) => ("amd64", None),
+                (EM_386, false, _) => ("386", None),
+                (EM_AARCH64, true, true) => ("arm64", Some("v8")),
+                // armhf in Debian requires ARMv7, and armel runs on ARMv5
+                (EM_ARM, false, true) if target.abi() & EF_ARM_ABI_FLOAT_HARD != 0 => {
+                    ("arm", Some("v7"))
+                }
+                (EM_ARM, false, true) => ("arm", Some("v5")),
+                (EM_RISCV, true, _) => ("riscv64", None),
+                (EM_PPC64, true, true) => ("ppc64le", None),
+                (EM_PPC64, true, false) => ("ppc64", None),
+                (EM_S390, true, _) => ("s390x", None),
+                (EM_MIPS, true, true) => ("mips64le", None),
+                (EM_MIPS, true, false) => ("mips64", None),
+                (EM_MIPS, false, true) => ("mipsle", None),
+                (EM_MIPS, false, false) => ("mips", None),
+                (EM_LOONGARCH, true, _) => ("loong64", None),
+                _ => return None,
+            };
+        Some(Platform {
+            architecture,
+            variant,
+        })
+    }
+
+    /// The platform of the executables of `targets`, which must be the same.
+    pub fn from_targets<I>(targets: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = ElfTarget>,
+    {
+        let mut targets = targets.into_iter();
+        let target = targets.next().ok_or(Error::ImageTargetUnknown)?;
+        if let Some(other) = targets.find(|other| *other != target) {
+            return Err(Error::MixedImageTargets(
+                format!("{:?}", target),
+                format!("{:?}", other),
+            ));
+        }
+        Platform::from_target(target)
+            .ok_or_else(|| Error::UnsupportedImageTarget(format!("{:?}", target)))
+    }
+
+    /// The platform of the running magicpak process.
+    fn host() -> Self {
+        let architecture = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "x86" => "386",
+            "aarch64" => "arm64",
+            arch => arch,
+        };
+        Platform {
+            architecture,
+            variant: None,
+        }
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        let mut platform = json!({
+            "architecture": self.architecture,
+            "os": "linux",
+        });
+        if let Some(variant) = self.variant {
+            platform["variant"] = json!(variant);
+        }
+        platform
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ImageConfig {
+    /// The platform of the bundled executables, or the host if not specified.
+    pub platform: Option<Platform>,
+    pub entrypoint: Option<Vec<String>>,
+    pub cmd: Option<Vec<String>>,
+    pub working_dir: Option<String>,
+    pub env: Vec<String>,
+}
+
+impl ImageConfig {
+    fn to_json(&self) -> serde_json::Value {
+        let mut config = serde_json::Map::new();
+        if let Some(entrypoint) = &self.entrypoint {
+            config.insert("Entrypoint".to_owned(), json!(entrypoint));
+        }
+        if let Some(cmd) = &self.cmd {
+            config.insert("Cmd".to_owned(), json!(cmd));
+        }
+        if let Some(working_dir) = &self.working_dir {
+            config.insert("WorkingDir".to_owned(), json!(working_dir));
+        }
+        if !self.env.is_empty() {
+            config.insert("Env".to_owned(), json!(self.env));
+        }
+        serde_json::Value::Object(config)
+    }
+}
+
+#[derive(Debug)]
+pub struct Descriptor {
+    media_type: &'static str,
+    digest: String,
+    size: u64,
+}
+
+impl Descriptor {
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "mediaType": self.media_type,
+            "digest": self.digest,
+            "size": self.size,
+        })
+    }
+}
+
+pub struct BlobWriter {
+    file: NamedTempFile,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+pub struct OciLayout {
+    path: PathBuf,
+}
+
+impl OciLayout {
+    pub fn create<P>(dest: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dest.as_ref().to_owned();
+        tracing::debug!(path = %path.display(), "oci: creating image layout");
+
+        fs::create_dir_all(path.join("blobs/sha256"))?;
+        fs::write(
+            path.join("oci-layout"),
+            json!({ "imageLayoutVersion": "1.0.0" }).to_string(),
+        )?;
+        Ok(OciLayout { path })
+    }
+
+    fn write_blob_with<F>(&self, media_type: &'static str, write: F) -> Result<Descriptor>
+    where
+        F: FnOnce(&mut BlobWriter) -> Result<()>,
+    {
+        let mut writer = BlobWriter {
+            file: NamedTempFile::new_in(self.path.join("blobs"))?,
+            hasher: Sha256::new(),
+            size: 0,
+        };
+        write(&mut writer)?;
+        writer.flush()?;
+
+        let BlobWriter { file, hasher, size } = writer;
+        let hex = format!("{:x}", hasher.finalize());
+        let blob_path = self.path.join("blobs/sha256").join(&hex);
+        file.persist(&blob_path).map_err(|e| e.error)?;
+
+        let descriptor = Descriptor {
+            media_type,
+            digest: format!("sha256:{}", hex),
+            size,
+        };
+        tracing::debug!(
+            path = %blob_path.display(),
+            ?descriptor,
+            "oci: wrote blob",
+        );
+        Ok(descriptor)
+    }
+
+    fn write_blob(&self, media_type: &'static str, content: &[u8]) -> Result<Descriptor> {
+        self.write_blob_with(media_type, |w| w.write_all(content).map_err(Into::into))
+    }
+
+    pub fn write_layer<F>(&self, write: F) -> Result<Descriptor>
+    where
+        F: FnOnce(&mut BlobWriter) -> Result<()>,
+    {
+        self.write_blob_with(MEDIA_TYPE_LAYER, write)
+    }
+
+    pub fn write_image(&self, layer: &Descriptor, config: &ImageConfig) -> Result<()> {
+        let platform = config.platform.unwrap_or_else(Platform::host).to_json();
+        let mut config_json = platform.clone();
+        config_json["config"] = config.to_json();
+        config_json["rootfs"] = json!({
+            "type": "layers",
+            // the layer is not compressed, so DiffID is equal to its digest
+            "diff_ids": [layer.digest()],
+        });
+        let config_descriptor =
+            self.write_blob(MEDIA_TYPE_CONFIG, config_json.to_string().as_bytes())?;
+
+        let manifest_json = json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE_MANIFEST,
+            "config": config_descriptor.to_json(),
+            "layers": [layer.to_json()],
+        });
+        let manifest_descriptor =
+            self.write_blob(MEDIA_TYPE_MANIFEST, manifest_json.to_string().as_bytes())?;
+
+        let mut manifest_entry = manifest_descriptor.to_json();
+        manifest_entry["platform"] = platform;
+        let index_json = json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE_INDEX,
+            "manifests": [manifest_entry],
+        });
+        fs::write(self.path.join("index.json"), index_json.to_string())?;
+
+        tracing::info!(
+            path = %self.path.display(),
+            manifest = %manifest_descriptor.digest(),
+            "oci: wrote image",
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goblin::elf::header::{EM_AARCH64, EM_ARM, EM_X86_64};
+
+    #[test]
+    fn test_platform() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let aarch64 = ElfTarget::new(EM_AARCH64, true, true, 0);
+        assert_eq!(
+            Platform::from_target(aarch64),
+            Some(Platform {
+                architecture: "arm64",
+                variant: Some("v8")
+            })
+        );
+        let armhf = ElfTarget::new(EM_ARM, false, true, 0x0500_0400);
+        assert_eq!(
+            Platform::from_target(armhf).and_then(|p| p.variant),
+            Some("v7")
+        );
+
+        let x86_64 = ElfTarget::new(EM_X86_64, true, true, 0);
+        assert_eq!(
+            Platform::from_targets([aarch64, aarch64])?.architecture,
+            "arm64"
+        );
+        assert!(matches!(
+            Platform::from_targets([x86_64, aarch64]),
+            Err(Error::MixedImageTargets(..))
+        ));
+        assert!(matches!(
+            Platform::from_targets([ElfTarget::new(EM_AARCH64, true, false, 0)]),
+            Err(Error::UnsupportedImageTarget(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_image() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dest = assert_fs::TempDir::new()?;
+        let layout = OciLayout::create(dest.path())?;
+        let layer = layout.write_layer(|w| w.write_all(b"layer").map_err(Into::into))?;
+        // the platform of an aarch64 root is used, whatever the host is
+        let config = ImageConfig {
+            platform: Platform::from_target(ElfTarget::new(EM_AARCH64, true, true, 0)),
+            ..Default::default()
+        };
+        layout.write_image(&layer, &config)?;
+
+        let index: serde_json::Value =
+            serde_json::from_slice(&fs::read(dest.path().join("index.json"))?)?;
+        let manifest_platform = &index["manifests"][0]["platform"];
+        assert_eq!(manifest_platform["architecture"], "arm64");
+        assert_eq!(manifest_platform["variant"], "v8");
+
+        let blob_path = |digest: &serde_json::Value| {
+            let digest = digest.as_str().unwrap().replacen(':', "/", 1);
+            dest.path().join("blobs").join(digest)
+        };
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&fs::read(blob_path(&index["manifests"][0]["digest"]))?)?;
+        let image_config: serde_json::Value =
+            serde_json::from_slice(&fs::read(blob_path(&manifest["config"]["digest"]))?)?;
+        assert_eq!(image_config["architecture"], "arm64");
+        assert_eq!(image_config["variant"], "v8");
+        assert_eq!(image_config["os"], "linux");
+        Ok(())
+    }
+}
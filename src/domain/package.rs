@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::base::log::CommandLogExt;
+use crate::base::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Dpkg,
+    Rpm,
+    Apk,
+}
+
+impl PackageManager {
+    fn purl_type(self) -> &'static str {
+        match self {
+            PackageManager::Dpkg => "deb",
+            PackageManager::Rpm => "rpm",
+            PackageManager::Apk => "apk",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub manager: PackageManager,
+    pub name: String,
+    pub version: String,
+    pub architecture: Option<String>,
+    pub license: Option<String>,
+}
+
+impl Package {
+    /// Returns the package URL (https://github.com/package-url/purl-spec) of the package.
+    pub fn purl(&self, distro: &str) -> String {
+        let mut purl = format!(
+            "pkg:{}/{}/{}@{}",
+            self.manager.purl_type(),
+            distro,
+            purl_encode(&self.name),
+            purl_encode(&self.version)
+        );
+        if let Some(arch) = &self.architecture {
+            purl.push_str("?arch=");
+            purl.push_str(arch);
+        }
+        purl
+    }
+}
+
+fn purl_encode(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' | '~' => c.to_string(),
+            c => c
+                .to_string()
+                .bytes()
+                .map(|b| format!("%{:02X}", b))
+                .collect(),
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+enum Database {
+    Indexed {
+        packages: Vec<Package>,
+        owners: HashMap<PathBuf, usize>,
+    },
+}
+
+/// Maps files to the distribution packages that own them, reading the local package database.
+#[derive(Debug)]
+pub struct PackageDatabase {
+    root: PathBuf,
+    distro: String,
+    database: Database,
+}
+
+impl PackageDatabase {
+    /// Detects and loads the package database under `root`. Returns `None` if no known database is found.
+    pub fn load<P>(root: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref();
+        let distro = read_distro_id(root);
+
+        let database = if root.join("var/lib/dpkg/status").exists() {
+            tracing::debug!(root = %root.display(), "package: found dpkg database");
+            load_dpkg(root)?
+        } else if root.join("lib/apk/db/installed").exists() {
+            tracing::debug!(root = %root.display(), "package: found apk database");
+            load_apk(root)?
+        } else if let Some(dbpath) = ["var/lib/rpm", "usr/lib/sysimage/rpm"]
+            .iter()
+            .map(|p| root.join(p))
+            .find(|p| p.is_dir())
+        {
+            tracing::debug!(dbpath = %dbpath.display(), "package: found rpm database");
+            match load_rpm(root, &dbpath)? {
+                Some(database) => database,
+                None => return Ok(None),
+            }
+        } else {
+            tracing::warn!(root = %root.display(), "package: no package database found");
+            return Ok(None);
+        };
+
+        Ok(Some(PackageDatabase {
+            root: root.to_owned(),
+            distro,
+            database,
+        }))
+    }
+
+    pub fn distro(&self) -> &str {
+        &self.distro
+    }
+
+    /// Finds the package that owns the file at `path` on the host.
+    pub fn owner<P>(&self, path: P) -> Result<Option<Package>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let candidates = owner_candidates(&self.root, path);
+
+        match &self.database {
+            Database::Indexed { packages, owners } => Ok(candidates
+                .iter()
+                .find_map(|p| owners.get(p))
+                .map(|idx| packages[*idx].clone())),
+        }
+    }
+}
+
+// Package databases record paths as installed, but the path we see may differ due to
+// symlinked directories such as merged /usr.
+fn owner_candidates(root: &Path, path: &Path) -> Vec<PathBuf> {
+    let rooted = |p: &Path| -> PathBuf {
+        match p.strip_prefix(root) {
+            Ok(stripped) => Path::new("/").join(stripped),
+            Err(_) => p.to_owned(),
+        }
+    };
+
+    let mut candidates = vec![rooted(path)];
+    if let Some(parent) = path.parent() {
+        if let (Ok(parent), Some(file_name)) = (parent.canonicalize(), path.file_name()) {
+            candidates.push(rooted(&parent.join(file_name)));
+        }
+    }
+    for candidate in candidates.clone() {
+        if let Ok(stripped) = candidate.strip_prefix("/usr") {
+            candidates.push(Path::new("/").join(stripped));
+        } else {
+            candidates
+                .push(Path::new("/usr").join(candidate.strip_prefix("/").unwrap_or(&candidate)));
+        }
+    }
+    candidates.dedup();
+    candidates
+}
+
+fn read_distro_id(root: &Path) -> String {
+    let content = fs::read_to_string(root.join("etc/os-release"))
+        .or_else(|_| fs::read_to_string(root.join("usr/lib/os-release")))
+        .unwrap_or_default();
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("ID="))
+        .map(|id| id.trim_matches('"').to_owned())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+fn paragraphs(content: &str) -> impl Iterator<Item = Vec<(&str, &str)>> {
+    content.split("\n\n").map(|paragraph| {
+        paragraph
+            .lines()
+            .filter(|line| !line.starts_with(' ') && !line.starts_with('\t'))
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k, v.trim()))
+            .collect()
+    })
+}
+
+fn load_dpkg(root: &Path) -> Result<Database> {
+    let status = fs::read_to_string(root.join("var/lib/dpkg/status"))?;
+    let info_dir = root.join("var/lib/dpkg/info");
+
+    let mut packages = Vec::new();
+    let mut owners = HashMap::new();
+    for fields in paragraphs(&status) {
+        let get = |key: &str| fields.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+        let (Some(name), Some(version)) = (get("Package"), get("Version")) else {
+            continue;
+        };
+        if !matches!(get("Status"), Some(status) if status.ends_with(" installed")) {
+            continue;
+        }
+        let architecture = get("Architecture");
+
+        let list_path = match architecture {
+            Some(arch) if info_dir.join(format!("{}:{}.list", name, arch)).exists() => {
+                info_dir.join(format!("{}:{}.list", name, arch))
+            }
+            _ => info_dir.join(format!("{}.list", name)),
+        };
+        let list = match fs::read_to_string(&list_path) {
+            Ok(list) => list,
+            Err(e) => {
+                tracing::debug!(path = %list_path.display(), error = %e, "package: no file list");
+                continue;
+            }
+        };
+
+        let idx = packages.len();
+        for line in list.lines() {
+            owners.insert(PathBuf::from(line), idx);
+        }
+        packages.push(Package {
+            manager: PackageManager::Dpkg,
+            name: name.to_owned(),
+            version: version.to_owned(),
+            architecture: architecture.map(ToOwned::to_owned),
+            license: read_dpkg_license(root, name),
+        });
+    }
+
+    tracing::debug!(packages = packages.len(), "package: loaded dpkg database");
+    Ok(Database::Indexed { packages, owners })
+}
+
+// Only machine-readable copyright files (DEP-5) are recognized.
+fn read_dpkg_license(root: &Path, name: &str) -> Option<String> {
+    let path = root.join("usr/share/doc").join(name).join("copyright");
+    let content = fs::read_to_string(path).ok()?;
+    if !content.starts_with("Format:") {
+        return None;
+    }
+
+    let mut licenses: Vec<&str> = Vec::new();
+    for fields in paragraphs(&content) {
+        if !fields.iter().any(|(k, _)| *k == "Files") {
+            continue;
+        }
+        if let Some((_, license)) = fields.iter().find(|(k, _)| *k == "License") {
+            if !license.is_empty() && !licenses.contains(license) {
+                licenses.push(license);
+            }
+        }
+    }
+    if licenses.is_empty() {
+        None
+    } else {
+        Some(licenses.join(" AND "))
+    }
+}
+
+fn load_apk(root: &Path) -> Result<Database> {
+    let installed = fs::read_to_string(root.join("lib/apk/db/installed"))?;
+
+    let mut packages = Vec::new();
+    let mut owners = HashMap::new();
+    for fields in paragraphs(&installed) {
+        let get = |key: &str| fields.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+        let (Some(name), Some(version)) = (get("P"), get("V")) else {
+            continue;
+        };
+
+        let idx = packages.len();
+        let mut folder = PathBuf::from("/");
+        for (key, value) in &fields {
+            match *key {
+                "F" => folder = Path::new("/").join(value),
+                "R" => {
+                    owners.insert(folder.join(value), idx);
+                }
+                _ => {}
+            }
+        }
+        packages.push(Package {
+            manager: PackageManager::Apk,
+            name: name.to_owned(),
+            version: version.to_owned(),
+            architecture: get("A").map(ToOwned::to_owned),
+            license: get("L").map(ToOwned::to_owned),
+        });
+    }
+
+    tracing::debug!(packages = packages.len(), "package: loaded apk database");
+    Ok(Database::Indexed { packages, owners })
+}
+
+// The rpm database is read with a single query of all files, since its format (BerkeleyDB, NDB, or
+// SQLite) depends on the version of rpm that wrote it.
+fn load_rpm(root: &Path, dbpath: &Path) -> Result<Option<Database>> {
+    let output = Command::new("rpm")
+        .arg("--root")
+        .arg(root)
+        .arg("--dbpath")
+        .arg(Path::new("/").join(dbpath.strip_prefix(root).unwrap_or(dbpath)))
+        .arg("--query")
+        .arg("--all")
+        .arg("--queryformat")
+        .arg("[%{FILENAMES}\\t%{NAME}\\t%{VERSION}-%{RELEASE}\\t%{ARCH}\\t%{LICENSE}\\n]")
+        .output_with_log();
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            tracing::warn!(
+                status = %output.status,
+                "package: failed to query the rpm database, files are not mapped to packages",
+            );
+            return Ok(None);
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            tracing::warn!("package: rpm is not found, files are not mapped to packages");
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let database = parse_rpm_files(&String::from_utf8_lossy(&output.stdout));
+    Ok(Some(database))
+}
+
+/// Parses lines of `path`, `name`, `version`, `arch`, and `license` separated by tabs.
+fn parse_rpm_files(content: &str) -> Database {
+    let mut packages = Vec::new();
+    let mut indices = HashMap::new();
+    let mut owners = HashMap::new();
+    for line in content.lines() {
+        let fields: Vec<_> = line.split('\t').collect();
+        let &[path, name, version, arch, license] = &fields[..] else {
+            continue;
+        };
+
+        let idx = *indices.entry((name, version, arch)).or_insert_with(|| {
+            packages.push(Package {
+                manager: PackageManager::Rpm,
+                name: name.to_owned(),
+                version: version.to_owned(),
+                architecture: Some(arch.to_owned()),
+                license: Some(license.to_owned()),
+            });
+            packages.len() - 1
+        });
+        owners.entry(PathBuf::from(path)).or_insert(idx);
+    }
+
+    tracing::debug!(packages = packages.len(), "package: loaded rpm database");
+    Database::Indexed { packages, owners }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn test_dpkg() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let root = assert_fs::TempDir::new()?;
+        root.child("etc/os-release").write_str("ID=debian\n")?;
+        root.child("var/lib/dpkg/status").write_str(
+            "Package: libfoo1\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1:1.2-3+b1\nDescription: foo\n multi-line\n\n\
+             Package: removed\nStatus: deinstall ok config-files\nVersion: 1.0\n",
+        )?;
+        root.child("var/lib/dpkg/info/libfoo1:amd64.list")
+            .write_str("/.\n/usr/lib/x86_64-linux-gnu/libfoo.so.1\n")?;
+        root.child("usr/share/doc/libfoo1/copyright").write_str(
+            "Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/\n\n\
+             Files: *\nCopyright: someone\nLicense: MIT\n\nFiles: debian/*\nLicense: GPL-2+\n\nLicense: MIT\n text\n",
+        )?;
+
+        let db = PackageDatabase::load(root.path())?.unwrap();
+        assert_eq!(db.distro(), "debian");
+        let package = db
+            .owner(root.path().join("lib/x86_64-linux-gnu/libfoo.so.1"))?
+            .unwrap();
+        assert_eq!(package.name, "libfoo1");
+        assert_eq!(package.license.as_deref(), Some("MIT AND GPL-2+"));
+        assert_eq!(
+            package.purl(db.distro()),
+            "pkg:deb/debian/libfoo1@1%3A1.2-3%2Bb1?arch=amd64"
+        );
+        assert_eq!(db.owner(root.path().join("usr/bin/none"))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apk() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let root = assert_fs::TempDir::new()?;
+        root.child("lib/apk/db/installed").write_str(
+            "C:Q1abc=\nP:musl\nV:1.2.4-r2\nA:x86_64\nL:MIT\nF:lib\nR:ld-musl-x86_64.so.1\nR:libc.musl-x86_64.so.1\n\n\
+             P:zlib\nV:1.3-r0\nA:x86_64\nL:Zlib\nF:lib\nR:libz.so.1\n",
+        )?;
+
+        let db = PackageDatabase::load(root.path())?.unwrap();
+        let package = db.owner(root.path().join("lib/libz.so.1"))?.unwrap();
+        assert_eq!(package.name, "zlib");
+        assert_eq!(package.version, "1.3-r0");
+        assert_eq!(package.license.as_deref(), Some("Zlib"));
+        assert_eq!(
+            db.owner(root.path().join("lib/ld-musl-x86_64.so.1"))?
+                .map(|p| p.name),
+            Some("musl".to_owned())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rpm() {
+        let database = parse_rpm_files(
+            "/usr/lib64/libz.so.1\tzlib\t1.2.13-4.fc39\tx86_64\tzlib and Boost\n\
+             /usr/lib64/libz.so.1.2.13\tzlib\t1.2.13-4.fc39\tx86_64\tzlib and Boost\n\
+             /usr/lib64/libc.so.6\tglibc\t2.38-7.fc39\tx86_64\tLGPLv2+\n\
+             (none)\n",
+        );
+        let db = PackageDatabase {
+            root: PathBuf::from("/"),
+            distro: "fedora".to_owned(),
+            database,
+        };
+
+        let Database::Indexed { packages, .. } = &db.database;
+        assert_eq!(packages.len(), 2);
+        let package = db.owner("/lib64/libz.so.1.2.13").unwrap().unwrap();
+        assert_eq!(package.name, "zlib");
+        assert_eq!(package.version, "1.2.13-4.fc39");
+        assert_eq!(package.architecture.as_deref(), Some("x86_64"));
+        assert_eq!(
+            db.owner("/usr/lib64/libc.so.6").unwrap().map(|p| p.name),
+            Some("glibc".to_owned())
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use std::path::{Path, PathBuf};
 
+use crate::domain::executable::Dependency;
 use crate::domain::{Bundle, BundlePath};
 
 pub trait Resource {
@@ -24,6 +25,15 @@ impl Resource for &PathBuf {
     }
 }
 
+impl Resource for Dependency {
+    fn bundle_to(self, bundle: &mut Bundle) {
+        if let Some(baseline) = self.baseline {
+            bundle.add_required_by(baseline, &self.required_by);
+        }
+        bundle.add_required_by(self.path, self.required_by);
+    }
+}
+
 impl<R> Resource for Option<R>
 where
     R: Resource,
@@ -0,0 +1,231 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::base::Result;
+
+use nix::errno::Errno;
+
+/// Same as `SYMLOOP_MAX` in glibc.
+pub const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+/// The root filesystem that absolute paths of analyzed files are interpreted in.
+///
+/// Paths given to and returned from the analysis are paths in the root, as they would appear after chroot(2).
+/// Symbolic links are also resolved inside the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Root {
+    dir: Option<PathBuf>,
+}
+
+impl Root {
+    /// The root filesystem of the host.
+    pub fn host() -> Self {
+        Root { dir: None }
+    }
+
+    pub fn new<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = fs::canonicalize(dir)?;
+        if dir == Path::new("/") {
+            return Ok(Root::host());
+        }
+        Ok(Root { dir: Some(dir) })
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.dir.is_none()
+    }
+
+    /// The directory of the root on the host.
+    pub fn dir(&self) -> &Path {
+        self.dir.as_deref().unwrap_or_else(|| Path::new("/"))
+    }
+
+    /// Maps `path` in the root to the path on the host. Symbolic links in parent directories are resolved
+    /// inside the root, while the last component is left as it is.
+    pub fn host_path<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return path.to_owned(),
+        };
+        let resolved = self.resolve(path, false).unwrap_or_else(|e| {
+            tracing::debug!(path = %path.display(), error = %e, "root: unable to resolve path");
+            Path::new("/").join(path)
+        });
+        join_inside(dir, &resolved)
+    }
+
+    /// Same as `host_path`, but the last component is also resolved if it is a symbolic link.
+    pub fn real_host_path<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            // the host resolves symbolic links by itself
+            None => return path.to_owned(),
+        };
+        match self.resolve(path, true) {
+            Ok(resolved) => join_inside(dir, &resolved),
+            Err(_) => self.host_path(path),
+        }
+    }
+
+    /// Returns the canonical form of `path` in the root, with all symbolic links resolved inside the root.
+    pub fn canonicalize<P>(&self, path: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return fs::canonicalize(path).map_err(Into::into),
+        };
+        let resolved = self.resolve(path, true)?;
+        // fail in the same way as `fs::canonicalize` if the path does not exist
+        fs::symlink_metadata(join_inside(dir, &resolved))?;
+        Ok(resolved)
+    }
+
+    /// Matches `pattern` against paths in the root.
+    pub fn glob(&self, pattern: &str) -> Result<Vec<glob::GlobResult>> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(glob::glob(pattern)?.collect()),
+        };
+        let host_pattern = format!(
+            "{}/{}",
+            glob::Pattern::escape(&dir.to_string_lossy()),
+            pattern.trim_start_matches('/')
+        );
+        Ok(glob::glob(&host_pattern)?
+            .map(|entry| {
+                entry.map(|path| match path.strip_prefix(dir) {
+                    Ok(stripped) => Path::new("/").join(stripped),
+                    Err(_) => path,
+                })
+            })
+            .collect())
+    }
+
+    fn resolve(&self, path: &Path, follow_last: bool) -> io::Result<PathBuf> {
+        let dir = self.dir();
+        let mut resolved = PathBuf::from("/");
+        let mut pending: Vec<OsString> = Vec::new();
+        push_components(&mut pending, path);
+        let mut follows = 0;
+
+        while let Some(name) = pending.pop() {
+            if name == ".." {
+                resolved.pop();
+                continue;
+            }
+            let candidate = resolved.join(&name);
+            if pending.is_empty() && !follow_last {
+                return Ok(candidate);
+            }
+
+            let host_candidate = join_inside(dir, &candidate);
+            match fs::symlink_metadata(&host_candidate) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    follows += 1;
+                    if follows > MAX_SYMLINK_FOLLOWS {
+                        return Err(io::Error::from(Errno::ELOOP));
+                    }
+                    let link = fs::read_link(&host_candidate)?;
+                    if link.is_absolute() {
+                        resolved = PathBuf::from("/");
+                    }
+                    push_components(&mut pending, &link);
+                }
+                Ok(_) => resolved = candidate,
+                // the rest does not exist, so there is nothing to resolve
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !follow_last => {
+                    resolved = candidate;
+                    while let Some(name) = pending.pop() {
+                        if name == ".." {
+                            resolved.pop();
+                        } else {
+                            resolved.push(name);
+                        }
+                    }
+                    return Ok(resolved);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// Pushes the components of `path` in reverse order so that they can be popped from the first one.
+fn push_components(pending: &mut Vec<OsString>, path: &Path) {
+    for component in path.components().rev() {
+        match component {
+            Component::Normal(name) => pending.push(name.to_owned()),
+            Component::ParentDir => pending.push("..".into()),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+}
+
+fn join_inside(dir: &Path, path: &Path) -> PathBuf {
+    dir.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+    use std::os::unix;
+
+    #[test]
+    fn test_resolve_in_root() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        dir.child("usr/lib/libfoo.so.1").touch()?;
+        unix::fs::symlink("/usr/lib", dir.child("lib").path())?;
+        unix::fs::symlink("/lib/libfoo.so.1", dir.child("usr/lib/libfoo.so").path())?;
+        unix::fs::symlink("../../..", dir.child("usr/lib/up").path())?;
+
+        let root = Root::new(dir.path())?;
+        let dir = fs::canonicalize(dir.path())?;
+        assert_eq!(
+            root.host_path("/lib/libfoo.so"),
+            dir.join("usr/lib/libfoo.so")
+        );
+        assert_eq!(
+            root.real_host_path("/lib/libfoo.so"),
+            dir.join("usr/lib/libfoo.so.1")
+        );
+        assert_eq!(
+            root.canonicalize("/lib/libfoo.so")?,
+            Path::new("/usr/lib/libfoo.so.1")
+        );
+        // `..` never escapes the root
+        assert_eq!(root.canonicalize("/usr/lib/up/usr")?, Path::new("/usr"));
+        assert_eq!(
+            root.host_path("/lib/nonexistent/a"),
+            dir.join("usr/lib/nonexistent/a")
+        );
+        assert!(root.canonicalize("/lib/nonexistent").is_err());
+        assert_eq!(
+            root.glob("/usr/lib/*.so*")?
+                .into_iter()
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            [
+                PathBuf::from("/usr/lib/libfoo.so"),
+                PathBuf::from("/usr/lib/libfoo.so.1")
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use crate::base::Result;
+use crate::domain::bundle::{EntryKind, ManifestEmitter};
+use crate::domain::package::{Package, PackageDatabase};
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    Spdx,
+    CycloneDx,
+}
+
+struct SbomFile {
+    path: String,
+    sha256: String,
+    package: Option<usize>,
+}
+
+/// Software bill of materials of the files in a bundle.
+pub struct Sbom {
+    distro: String,
+    packages: Vec<Package>,
+    files: Vec<SbomFile>,
+    created: u64,
+}
+
+impl Sbom {
+    /// Builds the SBOM from bundled files, looking up their owners in `database`.
+    /// `created` is the creation time in seconds since the Unix epoch.
+    pub fn new(
+        manifest: &ManifestEmitter,
+        database: Option<&PackageDatabase>,
+        created: u64,
+    ) -> Result<Self> {
+        let mut packages = Vec::new();
+        let mut files = Vec::new();
+        for (path, entry) in manifest.entries() {
+            if entry.kind != EntryKind::File {
+                continue;
+            }
+
+            let package = match (database, &entry.host_path) {
+                (Some(database), Some(host_path)) => database.owner(host_path)?,
+                _ => None,
+            };
+            let package = match package {
+                Some(package) => Some(intern_package(&mut packages, package)),
+                None => {
+                    tracing::info!(path = %path.display(), "sbom: no package owns the file");
+                    None
+                }
+            };
+
+            files.push(SbomFile {
+                path: format!("/{}", path.to_str_lossy()),
+                // unwrap is ok because manifest entries of files always have digests
+                sha256: entry.sha256.clone().unwrap(),
+                package,
+            });
+        }
+
+        Ok(Sbom {
+            distro: database.map_or_else(|| "unknown".to_owned(), |db| db.distro().to_owned()),
+            packages,
+            files,
+            created,
+        })
+    }
+
+    pub fn to_json(&self, format: SbomFormat) -> serde_json::Value {
+        match format {
+            SbomFormat::Spdx => self.to_spdx(),
+            SbomFormat::CycloneDx => self.to_cyclonedx(),
+        }
+    }
+
+    fn content_digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        for file in &self.files {
+            hasher.update(file.path.as_bytes());
+            hasher.update(file.sha256.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn to_spdx(&self) -> serde_json::Value {
+        let package_id = |idx: usize| format!("SPDXRef-Package-{}", idx);
+        let file_id = |idx: usize| format!("SPDXRef-File-{}", idx);
+
+        let packages: Vec<_> = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(idx, package)| {
+                let mut value = json!({
+                    "SPDXID": package_id(idx),
+                    "name": package.name,
+                    "versionInfo": package.version,
+                    "downloadLocation": "NOASSERTION",
+                    "filesAnalyzed": false,
+                    "licenseConcluded": "NOASSERTION",
+                    "licenseDeclared": "NOASSERTION",
+                    "externalRefs": [{
+                        "referenceCategory": "PACKAGE-MANAGER",
+                        "referenceType": "purl",
+                        "referenceLocator": package.purl(&self.distro),
+                    }],
+                });
+                // license names in package databases are not necessarily valid SPDX expressions
+                if let Some(license) = &package.license {
+                    value["licenseComments"] = json!(format!("Declared license: {}", license));
+                }
+                value
+            })
+            .collect();
+
+        let files: Vec<_> = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(idx, file)| {
+                json!({
+                    "SPDXID": file_id(idx),
+                    "fileName": format!(".{}", file.path),
+                    "checksums": [{ "algorithm": "SHA256", "checksumValue": file.sha256 }],
+                    "licenseConcluded": "NOASSERTION",
+                    "copyrightText": "NOASSERTION",
+                })
+            })
+            .collect();
+
+        let mut relationships = Vec::new();
+        for idx in 0..self.packages.len() {
+            relationships.push(json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": package_id(idx),
+            }));
+        }
+        for (idx, file) in self.files.iter().enumerate() {
+            let (element, relationship) = match file.package {
+                Some(package) => (package_id(package), "CONTAINS"),
+                None => ("SPDXRef-DOCUMENT".to_owned(), "DESCRIBES"),
+            };
+            relationships.push(json!({
+                "spdxElementId": element,
+                "relationshipType": relationship,
+                "relatedSpdxElement": file_id(idx),
+            }));
+        }
+
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": "magicpak-bundle",
+            "documentNamespace": format!(
+                "https://github.com/coord-e/magicpak/spdx/{}",
+                self.content_digest()
+            ),
+            "creationInfo": {
+                "created": format_rfc3339(self.created),
+                "creators": [format!("Tool: magicpak-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "files": files,
+            "relationships": relationships,
+        })
+    }
+
+    fn to_cyclonedx(&self) -> serde_json::Value {
+        let mut contained: BTreeMap<usize, Vec<_>> = BTreeMap::new();
+        let mut unowned = Vec::new();
+        for file in &self.files {
+            let component = json!({
+                "type": "file",
+                "name": file.path,
+                "hashes": [{ "alg": "SHA-256", "content": file.sha256 }],
+            });
+            match file.package {
+                Some(idx) => contained.entry(idx).or_default().push(component),
+                None => unowned.push(component),
+            }
+        }
+
+        let mut components: Vec<_> = self
+            .packages
+            .iter()
+            .enumerate()
+            .map(|(idx, package)| {
+                let purl = package.purl(&self.distro);
+                let mut value = json!({
+                    "type": "library",
+                    "bom-ref": purl,
+                    "name": package.name,
+                    "version": package.version,
+                    "purl": purl,
+                    "components": contained.remove(&idx).unwrap_or_default(),
+                });
+                if let Some(license) = &package.license {
+                    value["licenses"] = json!([{ "license": { "name": license } }]);
+                }
+                value
+            })
+            .collect();
+        components.append(&mut unowned);
+
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "serialNumber": format!("urn:uuid:{}", uuid_from_digest(&self.content_digest())),
+            "version": 1,
+            "metadata": {
+                "timestamp": format_rfc3339(self.created),
+                "tools": [{ "vendor": "coord-e", "name": "magicpak", "version": env!("CARGO_PKG_VERSION") }],
+            },
+            "components": components,
+        })
+    }
+}
+
+fn intern_package(packages: &mut Vec<Package>, package: Package) -> usize {
+    if let Some(idx) = packages.iter().position(|p| p == &package) {
+        return idx;
+    }
+    tracing::debug!(?package, "sbom: found package");
+    packages.push(package);
+    packages.len() - 1
+}
+
+fn uuid_from_digest(hex: &str) -> String {
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn format_rfc3339(timestamp: u64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_rfc3339(1700000000), "2023-11-14T22:13:20Z");
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::base::{Error, Result};
+use crate::domain::{Executable, ResolverBackend, Root};
+
+/// Same as `BINPRM_BUF_SIZE` in Linux, which only reads this many bytes for the shebang line.
+const SHEBANG_MAX_LEN: usize = 256;
+
+/// Linux runs at most this many interpreters for a script, which is `BINPRM_MAX_RECURSION` plus one.
+const MAX_INTERPRETERS: usize = 5;
+
+/// Directories searched for the command given to `env`, the default `PATH` in docker images.
+const DEFAULT_PATH: &[&str] = &[
+    "/usr/local/sbin",
+    "/usr/local/bin",
+    "/usr/sbin",
+    "/usr/bin",
+    "/sbin",
+    "/bin",
+];
+
+/// The `#!` line of a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shebang {
+    pub interpreter: String,
+    /// Linux passes everything after the interpreter to it as a single argument.
+    pub arg: Option<String>,
+}
+
+impl Shebang {
+    /// Reads the shebang of the file at `host_path`, or `None` if it is not a script.
+    pub fn read<P>(host_path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut buffer = Vec::with_capacity(SHEBANG_MAX_LEN);
+        fs::File::open(host_path)?
+            .take(SHEBANG_MAX_LEN as u64)
+            .read_to_end(&mut buffer)?;
+        Ok(Shebang::parse(&buffer))
+    }
+
+    fn parse(buffer: &[u8]) -> Option<Self> {
+        let line = buffer.strip_prefix(b"#!")?;
+        let line = match line.iter().position(|b| *b == b'\n') {
+            Some(end) => &line[..end],
+            None => line,
+        };
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_matches(|c| c == ' ' || c == '\t' || c == '\r');
+        let (interpreter, arg) = match line.split_once([' ', '\t']) {
+            Some((interpreter, arg)) => (interpreter, Some(arg.trim_matches([' ', '\t']))),
+            None => (line, None),
+        };
+        if interpreter.is_empty() {
+            return None;
+        }
+        Some(Shebang {
+            interpreter: interpreter.to_owned(),
+            arg: arg.filter(|arg| !arg.is_empty()).map(ToOwned::to_owned),
+        })
+    }
+
+    /// The command `env` runs with the argument of the shebang, skipping options and variable assignments
+    /// as in `#!/usr/bin/env -S FOO=1 python3 -u`.
+    fn env_command(&self) -> Option<&str> {
+        self.arg
+            .as_deref()?
+            .split_whitespace()
+            .find(|word| !word.starts_with('-') && !word.contains('='))
+    }
+}
+
+/// A script, and the interpreters that run it through its shebang.
+#[derive(Debug)]
+pub struct Script {
+    path: PathBuf,
+    name: String,
+    /// Scripts used as interpreters in the chain of shebangs.
+    scripts: Vec<PathBuf>,
+    /// Executables in the chain of shebangs, such as `/usr/bin/env` and the command it runs.
+    executables: Vec<Executable>,
+}
+
+impl Script {
+    /// Loads the script at `path` in `root`, or returns `None` if it does not start with `#!`.
+    /// The interpreters are parsed through the cache of `backend`.
+    pub fn load_in<P>(path: P, root: &Root, backend: &ResolverBackend) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let shebang = match Shebang::read(root.real_host_path(path))? {
+            Some(shebang) => shebang,
+            None => return Ok(None),
+        };
+        tracing::debug!(path = %path.display(), ?shebang, "script: loading");
+
+        let name = path
+            .file_name()
+            .ok_or_else(|| Error::InvalidObjectPath(path.to_owned()))?;
+        let name = name
+            .to_str()
+            .ok_or_else(|| Error::PathEncoding(name.to_os_string()))?
+            .to_owned();
+        let mut script = Script {
+            path: path.to_owned(),
+            name,
+            scripts: Vec::new(),
+            executables: Vec::new(),
+        };
+        script.follow(path, &shebang, root, backend)?;
+        Ok(Some(script))
+    }
+
+    /// Follows the interpreter in `shebang` of the script at `path`, until an executable is reached.
+    fn follow(
+        &mut self,
+        path: &Path,
+        shebang: &Shebang,
+        root: &Root,
+        backend: &ResolverBackend,
+    ) -> Result<()> {
+        if self.scripts.len() + self.executables.len() >= MAX_INTERPRETERS {
+            return Err(Error::ScriptNestingTooDeep(self.path.clone()));
+        }
+
+        let interpreter = Path::new(&shebang.interpreter);
+        if !interpreter.is_absolute() || !root.real_host_path(interpreter).is_file() {
+            return Err(Error::ScriptInterpreterNotFound {
+                script: path.to_owned(),
+                interpreter: shebang.interpreter.clone(),
+            });
+        }
+
+        if let Some(next) = Shebang::read(root.real_host_path(interpreter))? {
+            tracing::debug!(interpreter = %interpreter.display(), "script: interpreter is a script");
+            self.scripts.push(interpreter.to_owned());
+            return self.follow(interpreter, &next, root, backend);
+        }
+
+        let exe = Executable::load_in(interpreter, root, backend)?;
+        tracing::debug!(interpreter = %interpreter.display(), "script: found interpreter");
+        self.executables.push(exe);
+
+        if interpreter.file_name().and_then(|name| name.to_str()) != Some("env") {
+            return Ok(());
+        }
+        let command = match shebang.env_command() {
+            Some(command) => command,
+            None => {
+                tracing::warn!(script = %path.display(), "script: no command is given to env");
+                return Ok(());
+            }
+        };
+        let command_path =
+            find_command(command, root).ok_or_else(|| Error::ScriptInterpreterNotFound {
+                script: path.to_owned(),
+                interpreter: command.to_owned(),
+            })?;
+        tracing::debug!(%command, path = %command_path.display(), "script: found command run by env");
+
+        // the command can be a script as well, which is run with its own shebang
+        let env_shebang = Shebang {
+            interpreter: command_path.to_string_lossy().into_owned(),
+            arg: None,
+        };
+        self.follow(path, &env_shebang, root, backend)
+    }
+
+    /// The path of the script in the root.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// Scripts used as interpreters in the chain of shebangs, excluding this script.
+    pub fn scripts(&self) -> &[PathBuf] {
+        &self.scripts
+    }
+
+    /// Executables in the chain of shebangs, in the order they are run.
+    pub fn executables(&self) -> &[Executable] {
+        &self.executables
+    }
+
+    /// The executable that finally interprets the script.
+    pub fn executable(&self) -> &Executable {
+        // unwrap is ok because `follow` always ends with an executable
+        self.executables.last().unwrap()
+    }
+}
+
+/// Searches `DEFAULT_PATH` in `root` for the executable file named `command`, as `env` does with `PATH`.
+fn find_command(command: &str, root: &Root) -> Option<PathBuf> {
+    if command.contains('/') {
+        return Some(command.into());
+    }
+    DEFAULT_PATH
+        .iter()
+        .map(|dir| Path::new(dir).join(command))
+        .find(|path| {
+            matches!(fs::metadata(root.real_host_path(path)),
+                Ok(metadata) if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Hwcaps;
+    use assert_fs::prelude::*;
+    use std::env;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Shebang::parse(b"\x7fELF"), None);
+        assert_eq!(Shebang::parse(b"#!\n"), None);
+        assert_eq!(
+            Shebang::parse(b"#!/bin/sh\necho hello\n"),
+            Some(Shebang {
+                interpreter: "/bin/sh".to_owned(),
+                arg: None
+            })
+        );
+        assert_eq!(
+            Shebang::parse(b"#! /usr/bin/perl -w -T \r\n"),
+            Some(Shebang {
+                interpreter: "/usr/bin/perl".to_owned(),
+                arg: Some("-w -T".to_owned())
+            })
+        );
+    }
+
+    #[test]
+    fn test_env_command() {
+        let shebang = |arg: &str| Shebang {
+            interpreter: "/usr/bin/env".to_owned(),
+            arg: Some(arg.to_owned()),
+        };
+        assert_eq!(shebang("python3").env_command(), Some("python3"));
+        assert_eq!(
+            shebang("-S FOO=1 python3 -u").env_command(),
+            Some("python3")
+        );
+        assert_eq!(shebang("-i").env_command(), None);
+    }
+
+    #[test]
+    fn test_load_in() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let dir = assert_fs::TempDir::new()?;
+        let exe = env::current_exe()?;
+        for path in ["usr/bin/env", "bin/sh"] {
+            dir.child(path).touch()?;
+            fs::copy(&exe, dir.child(path).path())?;
+        }
+        // a wrapper script found in PATH, which is run with another shebang
+        dir.child("usr/local/bin/wrapper")
+            .write_str("#!/bin/sh\nexec \"$@\"\n")?;
+        fs::set_permissions(
+            dir.child("usr/local/bin/wrapper").path(),
+            fs::Permissions::from_mode(0o755),
+        )?;
+        dir.child("app/main")
+            .write_str("#!/usr/bin/env -S wrapper -x\n")?;
+        dir.child("app/broken")
+            .write_str("#!/usr/bin/env nonexistent\n")?;
+        dir.child("app/binary").touch()?;
+        fs::copy(&exe, dir.child("app/binary").path())?;
+
+        let root = Root::new(dir.path())?;
+        let backend = ResolverBackend::native(&root, Hwcaps::baseline())?;
+        let script = Script::load_in("/app/main", &root, &backend)?.unwrap();
+        assert_eq!(script.name(), "main");
+        assert_eq!(script.scripts(), [PathBuf::from("/usr/local/bin/wrapper")]);
+        let executables: Vec<_> = script.executables().iter().map(|e| e.path()).collect();
+        assert_eq!(
+            executables,
+            [Path::new("/usr/bin/env"), Path::new("/bin/sh")]
+        );
+        assert_eq!(script.executable().path(), Path::new("/bin/sh"));
+
+        assert!(matches!(
+            Script::load_in("/app/broken", &root, &backend),
+            Err(Error::ScriptInterpreterNotFound { .. })
+        ));
+        assert!(Script::load_in("/app/binary", &root, &backend)?.is_none());
+        Ok(())
+    }
+}
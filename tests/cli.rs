@@ -0,0 +1,55 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+#[test]
+fn test_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let exe = std::env::current_exe()?;
+
+    Command::cargo_bin("magicpak")?
+        .arg(&exe)
+        .arg("--dry-run")
+        .arg("--manifest")
+        .arg(dir.child("manifest.json").path())
+        .arg("--sbom")
+        .arg(dir.child("sbom.json").path())
+        .arg("--dependency-graph")
+        .arg(dir.child("graph.dot").path())
+        .arg("--cache")
+        .arg(dir.child("cache.json").path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            exe.file_name().unwrap().to_str().unwrap(),
+        ));
+
+    for path in ["manifest.json", "sbom.json", "graph.dot", "cache.json"] {
+        dir.child(path).assert(predicate::path::missing());
+    }
+
+    // every positional argument is an INPUT in the dry run
+    let bin = assert_cmd::cargo::cargo_bin("magicpak");
+    Command::cargo_bin("magicpak")?
+        .arg(&exe)
+        .arg(&bin)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            exe.file_name().unwrap().to_str().unwrap(),
+        ))
+        .stdout(predicate::str::contains(
+            bin.file_name().unwrap().to_str().unwrap(),
+        ));
+    Command::cargo_bin("magicpak")?.arg(&exe).assert().failure();
+
+    // --test would run the bundle, which is never written in the dry run
+    Command::cargo_bin("magicpak")?
+        .arg(&exe)
+        .arg("--dry-run")
+        .arg("--test")
+        .assert()
+        .failure();
+    Ok(())
+}